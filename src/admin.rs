@@ -0,0 +1,793 @@
+#[cfg(feature = "client")]
+use std::collections::VecDeque;
+#[cfg(feature = "server")]
+use std::path::PathBuf;
+
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::network::channels_config::ChannelConfig;
+use crate::network::events::client::ClientEventAppExt;
+use crate::network::events::server::ServerEventAppExt;
+use crate::network::helper::{ClientId, TeamId};
+#[cfg(feature = "client")]
+use crate::player::TextInputFocus;
+use crate::Cli;
+#[cfg(feature = "server")]
+use crate::{
+	chat::Mutes,
+	map::MapSeed,
+	network::{
+		connection::{ConnectionRejected, RejectReason, Sessions},
+		events::{
+			client::FromClient,
+			server::{SendMode, ToClients},
+		},
+		helper::{EventChannel, ServerSn, SERVER_ID},
+		reject_client,
+		violations::{ClientViolation, Violation, Violations},
+	},
+	player::{Player, PlayerNames},
+	stats::RestartMatch,
+	zone::{SafeZone, ZoneUpdate},
+};
+
+/// Operator commands, typed into a dedicated server's terminal or into the F2 console of a client
+/// that knows the server's `--admin-password`.
+pub struct AdminPlugin;
+
+impl Plugin for AdminPlugin {
+	fn build(&self, app: &mut App) {
+		app.add_client_event_with::<AdminRequest>(
+			ChannelConfig::for_event::<AdminRequest>().with_rate_limit(ADMIN_RATE_LIMIT),
+		)
+		.add_server_event::<AdminResponse>()
+		.add_server_event::<ServerAnnouncement>();
+
+		#[cfg(feature = "client")]
+		app.init_resource::<AdminConsole>()
+			.add_systems(Startup, spawn_admin_console)
+			.add_systems(
+				Update,
+				(toggle_admin_console, type_admin_command, show_admin_responses, update_admin_console).chain(),
+			);
+
+		#[cfg(feature = "server")]
+		app.init_resource::<AdminQueue>()
+			.init_resource::<AdminAuditLog>()
+			.add_systems(
+				Update,
+				(receive_admin_requests, run_admin_commands)
+					.chain()
+					.run_if(resource_exists::<ServerSn>()),
+			);
+
+		// Only a dedicated server owns the terminal it runs in
+		#[cfg(all(feature = "server", not(feature = "client")))]
+		app.add_systems(Startup, spawn_stdin_reader).add_systems(
+			Update,
+			read_stdin_commands
+				.before(run_admin_commands)
+				.run_if(resource_exists::<StdinCommands>()),
+		);
+	}
+}
+
+/// Admin commands per second the server accepts from one client.
+const ADMIN_RATE_LIMIT: f32 = 2.0;
+/// Highest phase `set_zone_phase` accepts, the zone is a speck long before that.
+#[cfg(feature = "server")]
+const MAX_ZONE_PHASE: u32 = 12;
+/// Lines of history the client console keeps.
+#[cfg(feature = "client")]
+const CONSOLE_LINES: usize = 12;
+#[cfg(feature = "client")]
+const CONSOLE_FONT_SIZE: f32 = 16.0;
+
+/// A console line typed by an admin on a client, checked against `--admin-password` on the server.
+#[derive(Event, TypePath, Serialize, Deserialize, Debug, Clone)]
+pub struct AdminRequest {
+	/// Echoed in the [`AdminResponse`] so the client can match them up.
+	pub request_id: u32,
+	pub password: String,
+	pub command: String,
+}
+
+/// Outcome of an [`AdminRequest`], sent back to the client that made it.
+#[derive(Event, TypePath, Serialize, Deserialize, Debug, Clone)]
+pub struct AdminResponse {
+	pub request_id: u32,
+	pub result: Result<AdminOutput, AdminError>,
+}
+
+/// Message from the server operator, shown to every player.
+#[derive(Event, TypePath, Serialize, Deserialize, Debug, Clone)]
+pub struct ServerAnnouncement(pub String);
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub enum AdminOutput {
+	Done(String),
+	Players(Vec<PlayerEntry>),
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct PlayerEntry {
+	pub client_id: ClientId,
+	pub name: String,
+	/// `false` while the server waits for the player to reconnect.
+	pub connected: bool,
+	pub alive: bool,
+	pub team: Option<TeamId>,
+}
+
+impl std::fmt::Display for AdminOutput {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		match self {
+			AdminOutput::Done(message) => write!(f, "{message}"),
+			AdminOutput::Players(players) if players.is_empty() => write!(f, "no players"),
+			AdminOutput::Players(players) => {
+				let lines: Vec<String> = players
+					.iter()
+					.map(|player| {
+						let mut line = format!(
+							"{:032x} {} ({}, {}",
+							player.client_id,
+							player.name,
+							if player.connected { "connected" } else { "disconnected" },
+							if player.alive { "alive" } else { "dead" },
+						);
+						if let Some(team) = player.team {
+							line += &format!(", team {team}");
+						}
+						line + ")"
+					})
+					.collect();
+
+				write!(f, "{}", lines.join("\n"))
+			}
+		}
+	}
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub enum AdminError {
+	/// The server has no `--admin-password`, only its own terminal takes commands.
+	RemoteDisabled,
+	Unauthorized,
+	UnknownCommand(String),
+	/// Known command with the wrong arguments, holds the expected usage.
+	Usage(String),
+	UnknownPlayer(String),
+	/// More than one player matches.
+	AmbiguousPlayer(String),
+	/// A well formed command the server can't carry out, e.g. kicking the host.
+	Refused(String),
+}
+
+impl std::fmt::Display for AdminError {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		match self {
+			AdminError::RemoteDisabled => write!(f, "remote admin is disabled on this server"),
+			AdminError::Unauthorized => write!(f, "wrong admin password"),
+			AdminError::UnknownCommand(command) => write!(
+				f,
+				"unknown command `{command}`, try kick, ban, mute, unmute, restart_match, set_seed, \
+				 set_zone_phase, list_players, spawn_powerup or say"
+			),
+			AdminError::Usage(usage) => write!(f, "usage: {usage}"),
+			AdminError::UnknownPlayer(target) => write!(f, "no player matches `{target}`"),
+			AdminError::AmbiguousPlayer(target) => write!(f, "more than one player matches `{target}`"),
+			AdminError::Refused(reason) => write!(f, "{reason}"),
+		}
+	}
+}
+
+#[cfg(feature = "server")]
+#[derive(Debug, Clone, PartialEq)]
+enum AdminCommand {
+	Kick(String),
+	Ban(String),
+	Mute(String),
+	Unmute(String),
+	RestartMatch,
+	SetSeed(u64),
+	SetZonePhase(u32),
+	ListPlayers,
+	SpawnPowerup(String),
+	Say(String),
+}
+
+#[cfg(feature = "server")]
+impl std::str::FromStr for AdminCommand {
+	type Err = AdminError;
+
+	fn from_str(line: &str) -> Result<Self, Self::Err> {
+		let line = line.trim();
+		let (name, args) = line.split_once(char::is_whitespace).unwrap_or((line, ""));
+		let args = args.trim();
+		let usage = |usage: &str| AdminError::Usage(String::from(usage));
+
+		match name {
+			"kick" if !args.is_empty() => Ok(Self::Kick(String::from(args))),
+			"kick" => Err(usage("kick <player name or id>")),
+			"ban" if !args.is_empty() => Ok(Self::Ban(String::from(args))),
+			"ban" => Err(usage("ban <player name or id>")),
+			"mute" if !args.is_empty() => Ok(Self::Mute(String::from(args))),
+			"mute" => Err(usage("mute <player name or id>")),
+			"unmute" if !args.is_empty() => Ok(Self::Unmute(String::from(args))),
+			"unmute" => Err(usage("unmute <player name or id>")),
+			"restart_match" => Ok(Self::RestartMatch),
+			"set_seed" => args.parse().map(Self::SetSeed).map_err(|_| usage("set_seed <number>")),
+			"set_zone_phase" => match args.parse() {
+				Ok(phase) if phase <= MAX_ZONE_PHASE => Ok(Self::SetZonePhase(phase)),
+				_ => Err(AdminError::Usage(format!("set_zone_phase <0-{MAX_ZONE_PHASE}>"))),
+			},
+			"list_players" => Ok(Self::ListPlayers),
+			"spawn_powerup" => Ok(Self::SpawnPowerup(String::from(args))),
+			"say" if !args.is_empty() => Ok(Self::Say(String::from(args))),
+			"say" => Err(usage("say <message>")),
+			_ => Err(AdminError::UnknownCommand(String::from(name))),
+		}
+	}
+}
+
+/// Where an admin command came from.
+#[cfg(feature = "server")]
+#[derive(Serialize, Debug, Clone, Copy)]
+#[serde(rename_all = "snake_case")]
+enum AdminSource {
+	/// The dedicated server's terminal.
+	#[cfg(not(feature = "client"))]
+	Console,
+	Client(ClientId),
+}
+
+#[cfg(feature = "server")]
+impl std::fmt::Display for AdminSource {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		match self {
+			#[cfg(not(feature = "client"))]
+			AdminSource::Console => write!(f, "console"),
+			AdminSource::Client(client_id) => write!(f, "client {client_id}"),
+		}
+	}
+}
+
+#[cfg(feature = "server")]
+struct PendingCommand {
+	source: AdminSource,
+	request_id: u32,
+	command: String,
+}
+
+/// Authorized commands waiting for [`run_admin_commands`].
+#[cfg(feature = "server")]
+#[derive(Resource, Default)]
+struct AdminQueue(Vec<PendingCommand>);
+
+/// JSON lines file every admin command ends up in, whether it worked or not.
+#[cfg(feature = "server")]
+#[derive(Resource)]
+struct AdminAuditLog(PathBuf);
+
+#[cfg(feature = "server")]
+impl FromWorld for AdminAuditLog {
+	fn from_world(world: &mut World) -> Self {
+		Self(world.resource::<Cli>().admin_audit_log.clone())
+	}
+}
+
+#[cfg(feature = "server")]
+#[derive(Serialize)]
+struct AuditEntry<'a> {
+	/// Seconds since the unix epoch.
+	at: u64,
+	source: AdminSource,
+	command: &'a str,
+	result: &'a Result<AdminOutput, AdminError>,
+}
+
+#[cfg(feature = "server")]
+impl AdminAuditLog {
+	fn record(&self, source: AdminSource, command: &str, result: &Result<AdminOutput, AdminError>) {
+		match result {
+			Ok(_) => info!("admin command from {source}: {command}"),
+			Err(error) => warn!("admin command from {source} failed: {command}: {error}"),
+		}
+
+		let entry = AuditEntry {
+			at: std::time::SystemTime::now()
+				.duration_since(std::time::UNIX_EPOCH)
+				.map(|duration| duration.as_secs())
+				.unwrap_or_default(),
+			source,
+			command,
+			result,
+		};
+
+		if let Err(error) = self.append(&entry) {
+			error!("failed to write admin audit log: {error:#}");
+		}
+	}
+
+	fn append(&self, entry: &AuditEntry) -> anyhow::Result<()> {
+		use anyhow::Context;
+		use std::io::Write;
+
+		let mut file = std::fs::OpenOptions::new()
+			.create(true)
+			.append(true)
+			.open(&self.0)
+			.with_context(|| format!("opening {}", self.0.display()))?;
+		serde_json::to_writer(&mut file, entry)?;
+		writeln!(file)?;
+
+		Ok(())
+	}
+}
+
+/// Compares every byte whatever the outcome, so the time taken only gives away the length.
+#[cfg(feature = "server")]
+fn passwords_match(expected: &str, given: &str) -> bool {
+	let (expected, given) = (expected.as_bytes(), given.as_bytes());
+
+	let mut difference = expected.len() ^ given.len();
+	for (index, byte) in expected.iter().enumerate() {
+		difference |= usize::from(byte ^ given.get(index).copied().unwrap_or_default());
+	}
+
+	std::hint::black_box(difference) == 0
+}
+
+/// Queues commands from clients that know the password. The listen server's own player needs none.
+#[cfg(feature = "server")]
+fn receive_admin_requests(
+	cli: Res<Cli>,
+	audit_log: Res<AdminAuditLog>,
+	mut queue: ResMut<AdminQueue>,
+	mut request_events: EventReader<FromClient<AdminRequest>>,
+	mut response_events: EventWriter<ToClients<AdminResponse>>,
+	mut violation_events: EventWriter<ClientViolation>,
+) {
+	for FromClient { client_id, event, local } in request_events.read() {
+		let source = AdminSource::Client(*client_id);
+
+		let authorized = match &cli.admin_password {
+			// Trusted for where it came from, any client can claim to be `SERVER_ID`
+			_ if *local => Ok(()),
+			None => Err(AdminError::RemoteDisabled),
+			Some(password) if passwords_match(password, &event.password) => Ok(()),
+			Some(_) => {
+				violation_events.send(ClientViolation {
+					client_id: *client_id,
+					violation: Violation::AdminAuthFailed,
+				});
+				Err(AdminError::Unauthorized)
+			}
+		};
+
+		match authorized {
+			Ok(()) => queue.0.push(PendingCommand {
+				source,
+				request_id: event.request_id,
+				command: event.command.clone(),
+			}),
+			Err(error) => {
+				let result = Err(error);
+				audit_log.record(source, &event.command, &result);
+				response_events.send(ToClients {
+					mode: SendMode::Direct(*client_id),
+					event: AdminResponse { request_id: event.request_id, result },
+				});
+			}
+		}
+	}
+}
+
+/// Finds a player by name, ignoring case, or by a unique prefix of its hex client id.
+#[cfg(feature = "server")]
+fn resolve_player(target: &str, player_names: &PlayerNames) -> Result<ClientId, AdminError> {
+	if let Some((client_id, _)) = player_names.0.iter().find(|(_, name)| name.eq_ignore_ascii_case(target)) {
+		return Ok(*client_id);
+	}
+
+	let prefix = target.to_ascii_lowercase();
+	let mut matches = player_names
+		.0
+		.keys()
+		.filter(|client_id| format!("{client_id:032x}").starts_with(&prefix));
+
+	match (matches.next(), matches.next()) {
+		(Some(client_id), None) => Ok(*client_id),
+		(Some(_), Some(_)) => Err(AdminError::AmbiguousPlayer(String::from(target))),
+		_ => Err(AdminError::UnknownPlayer(String::from(target))),
+	}
+}
+
+#[cfg(feature = "server")]
+#[allow(clippy::too_many_arguments)]
+fn run_admin_commands(
+	mut queue: ResMut<AdminQueue>,
+	audit_log: Res<AdminAuditLog>,
+	mut server: ResMut<ServerSn>,
+	mut violations: ResMut<Violations>,
+	mut sessions: ResMut<Sessions>,
+	mut mutes: ResMut<Mutes>,
+	rejected_channel: Res<EventChannel<ConnectionRejected>>,
+	player_names: Res<PlayerNames>,
+	player_query: Query<&Player>,
+	mut map_seed: ResMut<MapSeed>,
+	mut zone: ResMut<SafeZone>,
+	mut zone_updates: EventWriter<ToClients<ZoneUpdate>>,
+	mut restart_events: EventWriter<RestartMatch>,
+	mut announcements: EventWriter<ToClients<ServerAnnouncement>>,
+	mut response_events: EventWriter<ToClients<AdminResponse>>,
+) {
+	for PendingCommand { source, request_id, command } in std::mem::take(&mut queue.0) {
+		let result = match command.parse::<AdminCommand>() {
+			Err(error) => Err(error),
+			Ok(AdminCommand::Kick(target)) => resolve_player(&target, &player_names).and_then(|client_id| {
+				let name = player_names.display_name(client_id);
+				if client_id == SERVER_ID {
+					return Err(AdminError::Refused(String::from("the host can't be kicked")));
+				}
+				if !server.client_connections.contains(&client_id) {
+					return Err(AdminError::Refused(format!("{name} is not connected")));
+				}
+
+				sessions.revoke(client_id);
+				reject_client(&mut server, &rejected_channel, client_id, RejectReason::Kicked);
+				Ok(AdminOutput::Done(format!("kicked {name}")))
+			}),
+			Ok(AdminCommand::Ban(target)) => resolve_player(&target, &player_names).and_then(|client_id| {
+				let name = player_names.display_name(client_id);
+				if client_id == SERVER_ID {
+					return Err(AdminError::Refused(String::from("the host can't be banned")));
+				}
+
+				violations.ban(client_id);
+				sessions.revoke(client_id);
+				if server.client_connections.contains(&client_id) {
+					reject_client(&mut server, &rejected_channel, client_id, RejectReason::Banned);
+				}
+				Ok(AdminOutput::Done(format!(
+					"banned {name} by client id, restarting their client gets them a new one"
+				)))
+			}),
+			Ok(AdminCommand::Mute(target)) => resolve_player(&target, &player_names).map(|client_id| {
+				mutes.0.insert(client_id);
+				AdminOutput::Done(format!("muted {}", player_names.display_name(client_id)))
+			}),
+			Ok(AdminCommand::Unmute(target)) => resolve_player(&target, &player_names).and_then(|client_id| {
+				let name = player_names.display_name(client_id);
+				if !mutes.0.remove(&client_id) {
+					return Err(AdminError::Refused(format!("{name} is not muted")));
+				}
+				Ok(AdminOutput::Done(format!("unmuted {name}")))
+			}),
+			Ok(AdminCommand::RestartMatch) => {
+				restart_events.send(RestartMatch);
+				Ok(AdminOutput::Done(String::from("match restarted")))
+			}
+			Ok(AdminCommand::SetSeed(seed)) => {
+				map_seed.set_if_neq(MapSeed(seed));
+				Ok(AdminOutput::Done(format!(
+					"map seed set to {seed}, restart the match to get ships out of the new planets"
+				)))
+			}
+			Ok(AdminCommand::SetZonePhase(phase)) => {
+				*zone = SafeZone::at_phase(phase);
+				zone_updates.send(ToClients {
+					mode: SendMode::Broadcast,
+					event: ZoneUpdate(*zone),
+				});
+				Ok(AdminOutput::Done(format!("zone set to phase {phase}")))
+			}
+			Ok(AdminCommand::ListPlayers) => {
+				let mut players: Vec<PlayerEntry> = player_names
+					.0
+					.iter()
+					.map(|(client_id, name)| PlayerEntry {
+						client_id: *client_id,
+						name: name.clone(),
+						connected: *client_id == SERVER_ID || server.client_connections.contains(client_id),
+						alive: player_query.iter().any(|player| player.0 == *client_id),
+						team: server.teams.get(client_id).copied(),
+					})
+					.collect();
+				players.sort_by(|a, b| a.name.cmp(&b.name));
+
+				Ok(AdminOutput::Players(players))
+			}
+			Ok(AdminCommand::SpawnPowerup(_)) => {
+				Err(AdminError::Refused(String::from("there are no powerups in the game yet")))
+			}
+			Ok(AdminCommand::Say(message)) => {
+				announcements.send(ToClients {
+					mode: SendMode::Broadcast,
+					event: ServerAnnouncement(message),
+				});
+				Ok(AdminOutput::Done(String::from("announced")))
+			}
+		};
+
+		audit_log.record(source, &command, &result);
+
+		match source {
+			#[cfg(not(feature = "client"))]
+			AdminSource::Console => match &result {
+				Ok(output) => println!("{output}"),
+				Err(error) => println!("error: {error}"),
+			},
+			AdminSource::Client(client_id) => response_events.send(ToClients {
+				mode: SendMode::Direct(client_id),
+				event: AdminResponse { request_id, result },
+			}),
+		}
+	}
+}
+
+/// Lines typed into the dedicated server's terminal, read on their own thread.
+#[cfg(all(feature = "server", not(feature = "client")))]
+#[derive(Resource)]
+struct StdinCommands(std::sync::Mutex<std::sync::mpsc::Receiver<String>>);
+
+#[cfg(all(feature = "server", not(feature = "client")))]
+fn spawn_stdin_reader(mut commands: Commands) {
+	let (sender, receiver) = std::sync::mpsc::channel();
+
+	std::thread::spawn(move || {
+		for line in std::io::stdin().lines() {
+			let Ok(line) = line else {
+				break;
+			};
+			if sender.send(line).is_err() {
+				break;
+			}
+		}
+	});
+
+	commands.insert_resource(StdinCommands(std::sync::Mutex::new(receiver)));
+	info!("reading admin commands from stdin");
+}
+
+#[cfg(all(feature = "server", not(feature = "client")))]
+fn read_stdin_commands(stdin: Res<StdinCommands>, mut queue: ResMut<AdminQueue>) {
+	let receiver = stdin.0.lock().expect("stdin reader should not be poisoned");
+
+	for command in receiver.try_iter() {
+		if command.trim().is_empty() {
+			continue;
+		}
+
+		queue.0.push(PendingCommand {
+			source: AdminSource::Console,
+			request_id: 0,
+			command,
+		});
+	}
+}
+
+/// Client side console, toggled with F2.
+#[cfg(feature = "client")]
+#[derive(Resource, Default)]
+struct AdminConsole {
+	open: bool,
+	input: String,
+	next_request_id: u32,
+	/// Commands sent and what came back, oldest first.
+	lines: VecDeque<String>,
+}
+
+#[cfg(feature = "client")]
+impl AdminConsole {
+	fn push_line(&mut self, line: String) {
+		if self.lines.len() >= CONSOLE_LINES {
+			self.lines.pop_front();
+		}
+		self.lines.push_back(line);
+	}
+}
+
+#[cfg(feature = "client")]
+#[derive(Component)]
+struct AdminConsoleText;
+
+#[cfg(feature = "client")]
+fn spawn_admin_console(mut commands: Commands) {
+	let mut console = TextBundle::from_section(
+		"",
+		TextStyle {
+			font_size: CONSOLE_FONT_SIZE,
+			color: Color::WHITE,
+			..default()
+		},
+	)
+	.with_style(Style {
+		position_type: PositionType::Absolute,
+		left: Val::Percent(25.),
+		top: Val::Px(10.),
+		width: Val::Percent(50.),
+		padding: UiRect::all(Val::Px(6.)),
+		..default()
+	})
+	.with_background_color(Color::rgba(0., 0., 0., 0.7));
+	console.visibility = Visibility::Hidden;
+
+	commands.spawn((console, AdminConsoleText));
+}
+
+#[cfg(feature = "client")]
+fn toggle_admin_console(
+	keys: Res<Input<KeyCode>>,
+	mut console: ResMut<AdminConsole>,
+	mut focus: ResMut<TextInputFocus>,
+) {
+	let toggled = keys.just_pressed(KeyCode::F2) || (console.open && keys.just_pressed(KeyCode::Escape));
+
+	// Another text box has the keyboard
+	if !toggled || (!console.open && focus.0) {
+		return;
+	}
+
+	console.open = !console.open;
+	focus.0 = console.open;
+}
+
+#[cfg(feature = "client")]
+fn type_admin_command(
+	cli: Res<Cli>,
+	keys: Res<Input<KeyCode>>,
+	mut characters: EventReader<ReceivedCharacter>,
+	mut console: ResMut<AdminConsole>,
+	mut requests: EventWriter<AdminRequest>,
+) {
+	if !console.open {
+		characters.clear();
+		return;
+	}
+
+	for ReceivedCharacter { char, .. } in characters.read() {
+		if !char.is_control() {
+			console.input.push(*char);
+		}
+	}
+
+	if keys.just_pressed(KeyCode::Back) {
+		console.input.pop();
+	}
+
+	if keys.just_pressed(KeyCode::Return) && !console.input.trim().is_empty() {
+		let command = std::mem::take(&mut console.input);
+		console.next_request_id += 1;
+		console.push_line(format!("> {command}"));
+
+		requests.send(AdminRequest {
+			request_id: console.next_request_id,
+			password: cli.admin_password.clone().unwrap_or_default(),
+			command,
+		});
+	}
+}
+
+#[cfg(feature = "client")]
+fn show_admin_responses(mut response_events: EventReader<AdminResponse>, mut console: ResMut<AdminConsole>) {
+	for AdminResponse { result, .. } in response_events.read() {
+		let text = match result {
+			Ok(output) => output.to_string(),
+			Err(error) => format!("error: {error}"),
+		};
+
+		for line in text.lines() {
+			console.push_line(String::from(line));
+		}
+	}
+}
+
+#[cfg(feature = "client")]
+fn update_admin_console(
+	console: Res<AdminConsole>,
+	mut console_query: Query<(&mut Text, &mut Visibility), With<AdminConsoleText>>,
+) {
+	if !console.is_changed() {
+		return;
+	}
+
+	for (mut text, mut visibility) in &mut console_query {
+		*visibility = if console.open { Visibility::Inherited } else { Visibility::Hidden };
+
+		let mut lines: Vec<&str> = console.lines.iter().map(String::as_str).collect();
+		let prompt = format!("> {}_", console.input);
+		lines.push(&prompt);
+		text.sections[0].value = lines.join("\n");
+	}
+}
+
+#[cfg(all(test, feature = "server"))]
+mod tests {
+	use clap::Parser;
+
+	use super::*;
+
+	fn app_with_password(password: &str) -> App {
+		let audit_log = std::env::temp_dir().join(format!("space_royale_admin_audit_{}.log", std::process::id()));
+		let cli = Cli::parse_from([
+			"space_royale",
+			"--admin-password",
+			password,
+			"--admin-audit-log",
+			audit_log.to_str().expect("temp dir should be valid unicode"),
+		]);
+
+		let mut app = App::new();
+		app.insert_resource(cli)
+			.init_resource::<AdminAuditLog>()
+			.init_resource::<AdminQueue>()
+			.add_event::<FromClient<AdminRequest>>()
+			.add_event::<ToClients<AdminResponse>>()
+			.add_event::<ClientViolation>()
+			.add_systems(Update, receive_admin_requests);
+		app
+	}
+
+	fn request(client_id: ClientId, password: &str, local: bool) -> FromClient<AdminRequest> {
+		FromClient {
+			client_id,
+			event: AdminRequest {
+				request_id: 1,
+				password: String::from(password),
+				command: String::from("list_players"),
+			},
+			local,
+		}
+	}
+
+	fn violations(app: &App) -> Vec<(ClientId, Violation)> {
+		let events = app.world.resource::<Events<ClientViolation>>();
+		events
+			.get_reader()
+			.read(events)
+			.map(|violation| (violation.client_id, violation.violation))
+			.collect()
+	}
+
+	#[test]
+	fn remote_client_claiming_server_id_needs_the_password() {
+		let mut app = app_with_password("hunter2");
+		app.world.send_event(request(SERVER_ID, "guess", false));
+		app.update();
+
+		assert_eq!(violations(&app), [(SERVER_ID, Violation::AdminAuthFailed)]);
+		assert!(app.world.resource::<AdminQueue>().0.is_empty());
+	}
+
+	#[test]
+	fn host_console_needs_no_password() {
+		let mut app = app_with_password("hunter2");
+		app.world.send_event(request(SERVER_ID, "", true));
+		app.update();
+
+		assert!(violations(&app).is_empty());
+		assert_eq!(app.world.resource::<AdminQueue>().0.len(), 1);
+	}
+
+	#[test]
+	fn remote_client_with_the_password_is_queued() {
+		let mut app = app_with_password("hunter2");
+		app.world.send_event(request(42, "hunter2", false));
+		app.update();
+
+		assert!(violations(&app).is_empty());
+		assert_eq!(app.world.resource::<AdminQueue>().0.len(), 1);
+	}
+
+	#[test]
+	fn passwords_only_match_in_full() {
+		assert!(passwords_match("hunter2", "hunter2"));
+		assert!(passwords_match("", ""));
+		assert!(!passwords_match("hunter2", "hunter"));
+		assert!(!passwords_match("hunter", "hunter2"));
+		assert!(!passwords_match("hunter2", "Hunter2"));
+		assert!(!passwords_match("hunter2", ""));
+	}
+}
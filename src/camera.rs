@@ -0,0 +1,211 @@
+use bevy::{
+	input::mouse::{MouseScrollUnit, MouseWheel},
+	prelude::*,
+	transform::TransformSystem,
+};
+use bevy_xpbd_2d::prelude::*;
+
+use crate::network::helper::ClientId;
+use crate::player::{Player, PlayerKilled};
+use crate::ClientIdResource;
+
+pub struct CameraPlugin;
+
+impl Plugin for CameraPlugin {
+	fn build(&self, app: &mut App) {
+		app.init_resource::<CameraState>()
+			.add_event::<ScreenShake>()
+			.add_systems(Startup, setup)
+			.add_systems(
+				Update,
+				(zoom_camera, cycle_spectate_target, shake_on_kill, add_screen_shake),
+			)
+			.add_systems(
+				PostUpdate,
+				update_camera
+					.after(PhysicsSet::Sync)
+					.before(TransformSystem::TransformPropagate),
+			);
+	}
+}
+
+const DEFAULT_ZOOM: f32 = 6.5;
+const MIN_ZOOM: f32 = 2.0;
+const MAX_ZOOM: f32 = 14.0;
+/// How much the zoom changes per scrolled line.
+const ZOOM_STEP: f32 = 0.5;
+/// Speed above which the camera starts zooming out on its own.
+const AUTO_ZOOM_SPEED: f64 = 800.0;
+/// Extra zoom per unit of speed above [`AUTO_ZOOM_SPEED`].
+const AUTO_ZOOM_PER_SPEED: f32 = 0.002;
+const MAX_AUTO_ZOOM: f32 = 4.0;
+/// How many seconds of velocity the camera leads the ship by.
+const LOOK_AHEAD_SECONDS: f64 = 0.4;
+const MAX_LOOK_AHEAD: f64 = 1500.0;
+/// Shake offset in world units at full trauma, before scaling by zoom.
+const MAX_SHAKE_OFFSET: f32 = 25.0;
+const SHAKE_DECAY_PER_SECOND: f32 = 1.5;
+/// Kills further than this from the camera don't shake it.
+const SHAKE_RANGE: f32 = 5000.0;
+
+#[derive(Bundle)]
+struct PlayerCameraBundle {
+	player_camera: PlayerCamera,
+	camera: Camera2dBundle,
+}
+
+#[derive(Component)]
+pub struct PlayerCamera;
+
+/// Adds trauma to the camera, which decays over time. Trauma is clamped to `0..=1`.
+#[derive(Event, Clone, Copy, Debug)]
+pub struct ScreenShake(pub f32);
+
+#[derive(Resource, Debug)]
+pub struct CameraState {
+	/// Where the camera is looking, before screen shake is applied.
+	focus: Vec3,
+	/// Zoom chosen with the mouse wheel, auto zoom is applied on top.
+	zoom: f32,
+	trauma: f32,
+	/// Player we follow after our own ship is destroyed.
+	pub spectating: Option<ClientId>,
+}
+
+impl Default for CameraState {
+	fn default() -> Self {
+		Self {
+			focus: Vec3::new(0., 0., 1.),
+			zoom: DEFAULT_ZOOM,
+			trauma: 0.,
+			spectating: None,
+		}
+	}
+}
+
+fn setup(mut commands: Commands) {
+	commands.spawn(PlayerCameraBundle {
+		player_camera: PlayerCamera,
+		camera: Camera2dBundle {
+			transform: Transform::from_xyz(0., 0., 1.),
+			projection: OrthographicProjection {
+				scale: DEFAULT_ZOOM,
+				..default()
+			},
+			..default()
+		},
+	});
+}
+
+fn zoom_camera(mut scroll_events: EventReader<MouseWheel>, mut camera_state: ResMut<CameraState>) {
+	for scroll in scroll_events.read() {
+		let lines = match scroll.unit {
+			MouseScrollUnit::Line => scroll.y,
+			MouseScrollUnit::Pixel => scroll.y / 100.,
+		};
+
+		camera_state.zoom = (camera_state.zoom - lines * ZOOM_STEP).clamp(MIN_ZOOM, MAX_ZOOM);
+	}
+}
+
+/// While our ship is dead, follows another player. Left and right arrows cycle through them.
+fn cycle_spectate_target(
+	keys: Res<Input<KeyCode>>,
+	client_id: Option<Res<ClientIdResource>>,
+	player_query: Query<&Player>,
+	mut camera_state: ResMut<CameraState>,
+) {
+	let local_id = client_id.map(|client_id| client_id.0);
+	if player_query.iter().any(|player| Some(player.0) == local_id) {
+		camera_state.spectating = None;
+		return;
+	}
+
+	let mut candidates: Vec<ClientId> = player_query.iter().map(|player| player.0).collect();
+	if candidates.is_empty() {
+		camera_state.spectating = None;
+		return;
+	}
+	candidates.sort();
+
+	let current_index = camera_state
+		.spectating
+		.and_then(|target| candidates.iter().position(|candidate| *candidate == target));
+
+	let next_index = match current_index {
+		None => 0,
+		Some(index) if keys.just_pressed(KeyCode::Right) => (index + 1) % candidates.len(),
+		Some(index) if keys.just_pressed(KeyCode::Left) => {
+			(index + candidates.len() - 1) % candidates.len()
+		}
+		Some(index) => index,
+	};
+
+	camera_state.spectating = Some(candidates[next_index]);
+}
+
+fn shake_on_kill(
+	mut kill_events: EventReader<PlayerKilled>,
+	mut shake_events: EventWriter<ScreenShake>,
+	camera_state: Res<CameraState>,
+) {
+	for kill in kill_events.read() {
+		// Kills out of our scope are too far away to feel anyway
+		let Some(position) = kill.position else {
+			continue;
+		};
+		let distance = position.as_vec2().distance(camera_state.focus.truncate());
+		if distance < SHAKE_RANGE {
+			shake_events.send(ScreenShake(1. - distance / SHAKE_RANGE));
+		}
+	}
+}
+
+fn add_screen_shake(mut shake_events: EventReader<ScreenShake>, mut camera_state: ResMut<CameraState>) {
+	for ScreenShake(trauma) in shake_events.read() {
+		camera_state.trauma = (camera_state.trauma + trauma).clamp(0., 1.);
+	}
+}
+
+fn update_camera(
+	player_query: Query<(&Player, &Position, &LinearVelocity)>,
+	mut camera_query: Query<(&mut Transform, &mut OrthographicProjection), With<PlayerCamera>>,
+	mut camera_state: ResMut<CameraState>,
+	time: Res<Time>,
+	client_id: Option<Res<ClientIdResource>>,
+) {
+	let Ok((mut transform, mut projection)) = camera_query.get_single_mut() else {
+		return;
+	};
+
+	let followed_id = camera_state
+		.spectating
+		.or(client_id.map(|client_id| client_id.0));
+
+	let followed = player_query
+		.iter()
+		.find(|(player, ..)| Some(player.0) == followed_id);
+
+	let mut target_zoom = camera_state.zoom;
+
+	if let Some((_, position, linear_velocity)) = followed {
+		let look_ahead = (linear_velocity.0 * LOOK_AHEAD_SECONDS).clamp_length_max(MAX_LOOK_AHEAD);
+		let target = (position.0 + look_ahead).extend(1.).as_vec3();
+
+		camera_state.focus = camera_state
+			.focus
+			.lerp(target, (time.delta_seconds() * 10.).min(1.));
+
+		let excess_speed = (linear_velocity.length() - AUTO_ZOOM_SPEED).max(0.) as f32;
+		target_zoom += (excess_speed * AUTO_ZOOM_PER_SPEED).min(MAX_AUTO_ZOOM);
+	}
+
+	projection.scale += (target_zoom - projection.scale) * (time.delta_seconds() * 4.).min(1.);
+
+	// Trauma squared feels better than linear, small hits barely move the camera
+	let shake = camera_state.trauma.powi(2) * MAX_SHAKE_OFFSET * projection.scale;
+	let shake_offset = Vec2::new(rand::random::<f32>() - 0.5, rand::random::<f32>() - 0.5) * 2. * shake;
+
+	transform.translation = camera_state.focus + shake_offset.extend(0.);
+	camera_state.trauma = (camera_state.trauma - SHAKE_DECAY_PER_SECOND * time.delta_seconds()).max(0.);
+}
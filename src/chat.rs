@@ -0,0 +1,458 @@
+#[cfg(feature = "client")]
+use std::collections::VecDeque;
+
+#[cfg(feature = "server")]
+use bevy::utils::{HashMap, HashSet};
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::network::channels_config::ChannelConfig;
+use crate::network::events::client::ClientEventAppExt;
+use crate::network::events::server::ServerEventAppExt;
+use crate::network::helper::ClientId;
+use crate::player::PlayerNames;
+#[cfg(feature = "client")]
+use crate::{player::TextInputFocus, ClientIdResource};
+#[cfg(feature = "server")]
+use crate::network::{
+	events::{
+		client::FromClient,
+		server::{SendMode, ToClients},
+	},
+	helper::{ServerSn, SERVER_ID},
+	violations::RateLimiter,
+};
+
+pub struct ChatPlugin;
+
+impl Plugin for ChatPlugin {
+	fn build(&self, app: &mut App) {
+		app.add_client_event_with::<ChatRequest>(
+			ChannelConfig::for_event::<ChatRequest>().with_rate_limit(CHAT_CHANNEL_RATE_LIMIT),
+		)
+		.add_server_event::<ChatMessage>()
+		.add_server_event::<ChatRejected>();
+
+		#[cfg(feature = "client")]
+		app.init_resource::<ChatBox>()
+			.add_systems(Startup, spawn_chat_box)
+			.add_systems(
+				Update,
+				(type_chat_message, receive_chat_messages, update_chat_box)
+					.chain()
+					.run_if(resource_exists::<ClientIdResource>()),
+			);
+
+		#[cfg(feature = "server")]
+		app.init_resource::<Mutes>()
+			.init_resource::<ChatLimiters>()
+			.add_systems(Update, handle_chat_requests.run_if(resource_exists::<ServerSn>()));
+	}
+}
+
+/// Longest message the server passes on, in characters.
+pub const MAX_CHAT_LENGTH: usize = 200;
+/// Hard limit on the chat channel, anything faster is flooding and counts as a violation.
+const CHAT_CHANNEL_RATE_LIMIT: f32 = 5.0;
+/// Messages per second a player may actually get out, the rest are turned away politely.
+#[cfg(feature = "server")]
+const CHAT_MESSAGES_PER_SECOND: f32 = 1.0;
+/// Words masked with asterisks before a message goes out.
+#[cfg(feature = "server")]
+const BLOCKED_WORDS: &[&str] = &["fuck", "fucking", "shit", "bitch", "cunt", "asshole", "bastard"];
+
+/// Messages the client keeps around.
+#[cfg(feature = "client")]
+const CHAT_HISTORY: usize = 50;
+/// Lines shown at once.
+#[cfg(feature = "client")]
+const CHAT_VISIBLE_LINES: usize = 8;
+/// Seconds a line stays up while the chat box is closed.
+#[cfg(feature = "client")]
+const CHAT_FADE_SECONDS: f32 = 10.0;
+#[cfg(feature = "client")]
+const CHAT_FONT_SIZE: f32 = 16.0;
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ChatTarget {
+	All,
+	/// Everyone on the sender's team, see [`ServerSn::teams`](crate::network::helper::ServerSn::teams).
+	Team,
+	Whisper(ClientId),
+}
+
+/// Sent by a client that wants to say something.
+#[derive(Event, TypePath, Serialize, Deserialize, Debug, Clone)]
+pub struct ChatRequest {
+	pub target: ChatTarget,
+	pub text: String,
+}
+
+/// A message that passed the server's checks, sent to everyone [`ChatTarget`] covers. Whispers
+/// also go back to their sender.
+#[derive(Event, TypePath, Serialize, Deserialize, Debug, Clone)]
+pub struct ChatMessage {
+	pub sender: ClientId,
+	pub sender_name: String,
+	pub target: ChatTarget,
+	pub text: String,
+}
+
+/// Sent directly to a client whose [`ChatRequest`] was turned away.
+#[derive(Event, TypePath, Serialize, Deserialize, Debug, Clone, Copy)]
+pub struct ChatRejected(pub ChatRejection);
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ChatRejection {
+	Empty,
+	TooLong { max: usize },
+	TooFast,
+	Muted,
+	NoTeam,
+	UnknownRecipient,
+}
+
+impl std::fmt::Display for ChatRejection {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		match self {
+			ChatRejection::Empty => write!(f, "message is empty"),
+			ChatRejection::TooLong { max } => write!(f, "message is longer than {max} characters"),
+			ChatRejection::TooFast => write!(f, "you are sending messages too fast"),
+			ChatRejection::Muted => write!(f, "you are muted"),
+			ChatRejection::NoTeam => write!(f, "you are not on a team"),
+			ChatRejection::UnknownRecipient => write!(f, "that player is not here"),
+		}
+	}
+}
+
+/// Players whose messages the server drops, managed with the `mute` and `unmute` admin commands.
+#[cfg(feature = "server")]
+#[derive(Resource, Default, Debug)]
+pub struct Mutes(pub HashSet<ClientId>);
+
+#[cfg(feature = "server")]
+#[derive(Resource, Default, Debug)]
+struct ChatLimiters(HashMap<ClientId, RateLimiter>);
+
+/// Strips control characters and masks [`BLOCKED_WORDS`], or says why `text` can't go out.
+#[cfg(feature = "server")]
+pub fn sanitize_chat_text(text: &str) -> Result<String, ChatRejection> {
+	let text: String = text.chars().filter(|c| !c.is_control()).collect();
+	let text = text.trim();
+
+	if text.is_empty() {
+		return Err(ChatRejection::Empty);
+	}
+	if text.chars().count() > MAX_CHAT_LENGTH {
+		return Err(ChatRejection::TooLong { max: MAX_CHAT_LENGTH });
+	}
+
+	let mut filtered = String::with_capacity(text.len());
+	let mut rest = text;
+	while let Some(start) = rest.find(char::is_alphanumeric) {
+		filtered.push_str(&rest[..start]);
+		rest = &rest[start..];
+
+		let end = rest.find(|c: char| !c.is_alphanumeric()).unwrap_or(rest.len());
+		let word = &rest[..end];
+		if BLOCKED_WORDS.contains(&word.to_lowercase().as_str()) {
+			filtered.push_str(&"*".repeat(word.chars().count()));
+		} else {
+			filtered.push_str(word);
+		}
+
+		rest = &rest[end..];
+	}
+	filtered.push_str(rest);
+
+	Ok(filtered)
+}
+
+#[cfg(feature = "server")]
+#[allow(clippy::too_many_arguments)]
+fn handle_chat_requests(
+	time: Res<Time<Real>>,
+	server: Res<ServerSn>,
+	player_names: Res<PlayerNames>,
+	mutes: Res<Mutes>,
+	mut limiters: ResMut<ChatLimiters>,
+	mut requests: EventReader<FromClient<ChatRequest>>,
+	mut messages: EventWriter<ToClients<ChatMessage>>,
+	mut rejections: EventWriter<ToClients<ChatRejected>>,
+) {
+	limiters.0.retain(|client_id, _| player_names.0.contains_key(client_id));
+
+	for FromClient { client_id, event, .. } in requests.read() {
+		let is_present = |client_id: ClientId| {
+			player_names.0.contains_key(&client_id)
+				&& (client_id == SERVER_ID || server.client_connections.contains(&client_id))
+		};
+
+		let routed = if mutes.0.contains(client_id) {
+			Err(ChatRejection::Muted)
+		} else {
+			sanitize_chat_text(&event.text).and_then(|text| {
+				let mode = match event.target {
+					ChatTarget::All => SendMode::Broadcast,
+					ChatTarget::Team => match server.teams.get(client_id) {
+						Some(team) => SendMode::ToTeam(*team),
+						None => return Err(ChatRejection::NoTeam),
+					},
+					ChatTarget::Whisper(recipient) if recipient != *client_id && is_present(recipient) => {
+						SendMode::ToMany(vec![*client_id, recipient])
+					}
+					ChatTarget::Whisper(_) => return Err(ChatRejection::UnknownRecipient),
+				};
+
+				let limiter = limiters
+					.0
+					.entry(*client_id)
+					.or_insert_with(|| RateLimiter::new(time.elapsed(), CHAT_MESSAGES_PER_SECOND));
+				if !limiter.try_take(time.elapsed(), CHAT_MESSAGES_PER_SECOND) {
+					return Err(ChatRejection::TooFast);
+				}
+
+				Ok((mode, text))
+			})
+		};
+
+		match routed {
+			Ok((mode, text)) => messages.send(ToClients {
+				mode,
+				event: ChatMessage {
+					sender: *client_id,
+					sender_name: player_names.display_name(*client_id),
+					target: event.target,
+					text,
+				},
+			}),
+			Err(rejection) => rejections.send(ToClients {
+				mode: SendMode::Direct(*client_id),
+				event: ChatRejected(rejection),
+			}),
+		}
+	}
+}
+
+#[cfg(feature = "client")]
+struct ChatLine {
+	text: String,
+	color: Color,
+	/// [`Time::elapsed_seconds`] when the line came in.
+	received_at: f32,
+}
+
+/// Enter opens the chat box, `/t` talks to the team and `/w <name>` whispers.
+#[cfg(feature = "client")]
+#[derive(Resource, Default)]
+struct ChatBox {
+	open: bool,
+	input: String,
+	/// Oldest first.
+	history: VecDeque<ChatLine>,
+}
+
+#[cfg(feature = "client")]
+impl ChatBox {
+	fn push_line(&mut self, text: String, color: Color, now: f32) {
+		if self.history.len() >= CHAT_HISTORY {
+			self.history.pop_front();
+		}
+		self.history.push_back(ChatLine { text, color, received_at: now });
+	}
+}
+
+#[cfg(feature = "client")]
+#[derive(Component)]
+struct ChatBoxText;
+
+#[cfg(feature = "client")]
+fn spawn_chat_box(mut commands: Commands) {
+	commands.spawn((
+		TextBundle::from_section(
+			"",
+			TextStyle {
+				font_size: CHAT_FONT_SIZE,
+				color: Color::WHITE,
+				..default()
+			},
+		)
+		.with_style(Style {
+			position_type: PositionType::Absolute,
+			left: Val::Px(10.),
+			bottom: Val::Px(180.),
+			width: Val::Px(420.),
+			padding: UiRect::all(Val::Px(4.)),
+			..default()
+		}),
+		ChatBoxText,
+	));
+}
+
+/// Splits what was typed into a [`ChatRequest`], resolving whisper names against [`PlayerNames`].
+#[cfg(feature = "client")]
+fn parse_chat_input(input: &str, player_names: &PlayerNames) -> Result<ChatRequest, String> {
+	if let Some(text) = input.strip_prefix("/t ") {
+		return Ok(ChatRequest { target: ChatTarget::Team, text: String::from(text) });
+	}
+
+	let Some(rest) = input.strip_prefix("/w ") else {
+		return Ok(ChatRequest { target: ChatTarget::All, text: String::from(input) });
+	};
+
+	// Names can have spaces, the longest one the line starts with wins
+	player_names
+		.0
+		.iter()
+		.filter(|(_, name)| {
+			rest.get(..name.len()).is_some_and(|prefix| prefix.eq_ignore_ascii_case(name))
+				&& rest[name.len()..].starts_with(' ')
+		})
+		.max_by_key(|(_, name)| name.len())
+		.map(|(client_id, name)| ChatRequest {
+			target: ChatTarget::Whisper(*client_id),
+			text: String::from(&rest[name.len()..]),
+		})
+		.ok_or_else(|| String::from("usage: /w <player name> <message>"))
+}
+
+#[cfg(feature = "client")]
+fn type_chat_message(
+	time: Res<Time>,
+	keys: Res<Input<KeyCode>>,
+	player_names: Res<PlayerNames>,
+	mut characters: EventReader<ReceivedCharacter>,
+	mut chat_box: ResMut<ChatBox>,
+	mut focus: ResMut<TextInputFocus>,
+	mut requests: EventWriter<ChatRequest>,
+) {
+	if !chat_box.open {
+		// Another text box has the keyboard
+		if keys.just_pressed(KeyCode::Return) && !focus.0 {
+			chat_box.open = true;
+			focus.0 = true;
+		}
+		characters.clear();
+		return;
+	}
+
+	for ReceivedCharacter { char, .. } in characters.read() {
+		if !char.is_control() && chat_box.input.chars().count() < MAX_CHAT_LENGTH {
+			chat_box.input.push(*char);
+		}
+	}
+
+	if keys.just_pressed(KeyCode::Back) {
+		chat_box.input.pop();
+	}
+
+	let send = keys.just_pressed(KeyCode::Return);
+	if !send && !keys.just_pressed(KeyCode::Escape) {
+		return;
+	}
+
+	chat_box.open = false;
+	focus.0 = false;
+	let input = std::mem::take(&mut chat_box.input);
+	if !send || input.trim().is_empty() {
+		return;
+	}
+
+	match parse_chat_input(input.trim(), &player_names) {
+		Ok(request) => requests.send(request),
+		Err(usage) => chat_box.push_line(usage, Color::GRAY, time.elapsed_seconds()),
+	}
+}
+
+#[cfg(feature = "client")]
+fn receive_chat_messages(
+	time: Res<Time>,
+	client_id: Res<ClientIdResource>,
+	player_names: Res<PlayerNames>,
+	mut message_events: EventReader<ChatMessage>,
+	mut rejected_events: EventReader<ChatRejected>,
+	mut chat_box: ResMut<ChatBox>,
+) {
+	let now = time.elapsed_seconds();
+
+	for message in message_events.read() {
+		let ChatMessage { sender, sender_name, target, text } = message;
+		let (line, color) = match target {
+			ChatTarget::All => (format!("{sender_name}: {text}"), Color::WHITE),
+			ChatTarget::Team => (format!("[team] {sender_name}: {text}"), Color::LIME_GREEN),
+			ChatTarget::Whisper(recipient) if *sender == client_id.0 => (
+				format!("[to {}] {text}", player_names.display_name(*recipient)),
+				Color::PINK,
+			),
+			ChatTarget::Whisper(_) => (format!("[from {sender_name}] {text}"), Color::PINK),
+		};
+		chat_box.push_line(line, color, now);
+	}
+
+	for ChatRejected(rejection) in rejected_events.read() {
+		chat_box.push_line(rejection.to_string(), Color::GRAY, now);
+	}
+}
+
+#[cfg(feature = "client")]
+fn update_chat_box(
+	time: Res<Time>,
+	chat_box: Res<ChatBox>,
+	mut chat_query: Query<(&mut Text, &mut BackgroundColor), With<ChatBoxText>>,
+) {
+	let Ok((mut text, mut background)) = chat_query.get_single_mut() else {
+		return;
+	};
+
+	let now = time.elapsed_seconds();
+	let visible: Vec<&ChatLine> = chat_box
+		.history
+		.iter()
+		.rev()
+		.filter(|line| chat_box.open || now - line.received_at < CHAT_FADE_SECONDS)
+		.take(CHAT_VISIBLE_LINES)
+		.collect();
+
+	let style = |color| TextStyle {
+		font_size: CHAT_FONT_SIZE,
+		color,
+		..default()
+	};
+
+	let mut sections: Vec<TextSection> = visible
+		.into_iter()
+		.rev()
+		.map(|line| TextSection::new(format!("{}\n", line.text), style(line.color)))
+		.collect();
+	if chat_box.open {
+		sections.push(TextSection::new(format!("> {}_", chat_box.input), style(Color::WHITE)));
+	}
+
+	text.sections = sections;
+	*background = if chat_box.open { Color::rgba(0., 0., 0., 0.6) } else { Color::NONE }.into();
+}
+
+#[cfg(all(test, feature = "server"))]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn chat_text_is_trimmed_and_stripped_of_control_characters() {
+		assert_eq!(sanitize_chat_text("  gg\u{7}\n "), Ok(String::from("gg")));
+	}
+
+	#[test]
+	fn blocked_words_are_masked_whole() {
+		assert_eq!(sanitize_chat_text("oh SHIT, shitake"), Ok(String::from("oh ****, shitake")));
+	}
+
+	#[test]
+	fn empty_and_long_chat_text_is_rejected() {
+		assert_eq!(sanitize_chat_text(" \t "), Err(ChatRejection::Empty));
+		assert_eq!(
+			sanitize_chat_text(&"a".repeat(MAX_CHAT_LENGTH + 1)),
+			Err(ChatRejection::TooLong { max: MAX_CHAT_LENGTH })
+		);
+		assert!(sanitize_chat_text(&"a".repeat(MAX_CHAT_LENGTH)).is_ok());
+	}
+}
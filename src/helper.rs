@@ -16,7 +16,7 @@ pub fn count_objects<T: std::hash::Hash + Eq>(objects: Vec<T>) -> HashMap<T, usi
 }
 
 pub fn modified_box_muller_transform(u: f64) -> f64 {
-	assert!(u >= 0.0 && u <= 1.0);
+	assert!((0.0..=1.0).contains(&u));
 
 	(-2.0 * u.ln()).sqrt() * (u * 2.0 * std::f64::consts::PI).cos()
 }
@@ -0,0 +1,345 @@
+use bevy::prelude::*;
+use bevy_xpbd_2d::prelude::*;
+
+use crate::admin::ServerAnnouncement;
+use crate::map::{gravitational_pull, Planet};
+use crate::network::connection::{ClientConnectionState, DisconnectReason, ReconnectBackoff};
+use crate::network::error::NetworkErrorEvent;
+use crate::player::{Ammo, Health, Player, PlayerKilled, PlayerNames};
+use crate::snapshot::PlayersAlive;
+use crate::zone::SafeZone;
+use crate::ClientIdResource;
+
+pub struct HudPlugin;
+
+impl Plugin for HudPlugin {
+	fn build(&self, app: &mut App) {
+		app.add_systems(Startup, spawn_hud).add_systems(
+			Update,
+			(
+				update_ship_stats.run_if(resource_exists::<ClientIdResource>()),
+				update_match_stats,
+				update_kill_feed,
+				show_network_errors,
+				update_connection_overlay,
+			),
+		);
+	}
+}
+
+const HUD_FONT_SIZE: f32 = 20.0;
+const KILL_FEED_LENGTH: usize = 5;
+const KILL_FEED_ENTRY_SECONDS: f32 = 8.0;
+const NETWORK_ERROR_SECONDS: f32 = 5.0;
+
+#[derive(Component, Clone, Copy)]
+enum HudText {
+	Health,
+	Speed,
+	Gravity,
+	Ammo,
+	PlayersAlive,
+	Zone,
+}
+
+#[derive(Component)]
+struct KillFeed;
+
+#[derive(Component)]
+struct KillFeedEntry(Timer);
+
+#[derive(Component)]
+struct ConnectionOverlay;
+
+/// Shows the latest [`NetworkErrorEvent`] until its timer runs out.
+#[derive(Component)]
+struct NetworkErrorText(Timer);
+
+fn spawn_hud(mut commands: Commands) {
+	let text_style = TextStyle {
+		font_size: HUD_FONT_SIZE,
+		color: Color::WHITE,
+		..default()
+	};
+
+	commands
+		.spawn(NodeBundle {
+			style: Style {
+				position_type: PositionType::Absolute,
+				left: Val::Px(10.),
+				bottom: Val::Px(10.),
+				flex_direction: FlexDirection::Column,
+				..default()
+			},
+			..default()
+		})
+		.with_children(|parent| {
+			for hud_text in [
+				HudText::Health,
+				HudText::Speed,
+				HudText::Gravity,
+				HudText::Ammo,
+			] {
+				parent.spawn((TextBundle::from_section("", text_style.clone()), hud_text));
+			}
+		});
+
+	commands
+		.spawn(NodeBundle {
+			style: Style {
+				position_type: PositionType::Absolute,
+				left: Val::Px(10.),
+				top: Val::Px(10.),
+				flex_direction: FlexDirection::Column,
+				..default()
+			},
+			..default()
+		})
+		.with_children(|parent| {
+			for hud_text in [HudText::PlayersAlive, HudText::Zone] {
+				parent.spawn((TextBundle::from_section("", text_style.clone()), hud_text));
+			}
+		});
+
+	commands
+		.spawn(NodeBundle {
+			style: Style {
+				position_type: PositionType::Absolute,
+				width: Val::Percent(100.),
+				height: Val::Percent(100.),
+				justify_content: JustifyContent::Center,
+				align_items: AlignItems::Center,
+				..default()
+			},
+			..default()
+		})
+		.with_children(|parent| {
+			parent.spawn((
+				TextBundle::from_section(
+					"",
+					TextStyle {
+						font_size: HUD_FONT_SIZE * 2.,
+						color: Color::WHITE,
+						..default()
+					},
+				),
+				ConnectionOverlay,
+			));
+		});
+
+	commands
+		.spawn(NodeBundle {
+			style: Style {
+				position_type: PositionType::Absolute,
+				top: Val::Px(10.),
+				width: Val::Percent(100.),
+				justify_content: JustifyContent::Center,
+				..default()
+			},
+			..default()
+		})
+		.with_children(|parent| {
+			parent.spawn((
+				TextBundle::from_section(
+					"",
+					TextStyle {
+						font_size: HUD_FONT_SIZE,
+						color: Color::RED,
+						..default()
+					},
+				),
+				NetworkErrorText(Timer::from_seconds(NETWORK_ERROR_SECONDS, TimerMode::Once)),
+			));
+		});
+
+	commands.spawn((
+		NodeBundle {
+			style: Style {
+				position_type: PositionType::Absolute,
+				right: Val::Px(10.),
+				top: Val::Px(10.),
+				flex_direction: FlexDirection::Column,
+				align_items: AlignItems::FlexEnd,
+				..default()
+			},
+			..default()
+		},
+		KillFeed,
+	));
+}
+
+fn update_ship_stats(
+	client_id: Res<ClientIdResource>,
+	player_query: Query<(&Player, &Health, &Ammo, &LinearVelocity, &Position, &Mass)>,
+	planet_query: Query<(&Position, &Mass), With<Planet>>,
+	mut text_query: Query<(&mut Text, &HudText)>,
+) {
+	let local_player = player_query
+		.iter()
+		.find(|(player, ..)| player.0 == client_id.0);
+
+	for (mut text, hud_text) in &mut text_query {
+		if matches!(hud_text, HudText::PlayersAlive | HudText::Zone) {
+			continue;
+		}
+
+		// Our ship is dead or hasn't spawned yet
+		let Some((_, health, ammo, linear_velocity, position, mass)) = local_player else {
+			text.sections[0].value.clear();
+			continue;
+		};
+
+		text.sections[0].value = match hud_text {
+			HudText::Health => format!("Health: {:.0}/{:.0}", health.current.max(0.), health.max),
+			HudText::Speed => format!("Speed: {:.0}", linear_velocity.length()),
+			HudText::Gravity => {
+				let strongest_pull = planet_query
+					.iter()
+					.map(|(planet_position, planet_mass)| {
+						gravitational_pull(mass.0, planet_mass.0, planet_position.0 - position.0)
+					})
+					.fold(0., f64::max);
+
+				format!("Gravity: {:.0}", strongest_pull)
+			}
+			HudText::Ammo => format!("Ammo: {}  Grenades: {}", ammo.bullets, ammo.grenades),
+			_ => continue,
+		};
+	}
+}
+
+fn update_match_stats(
+	zone: Res<SafeZone>,
+	players_alive: Res<PlayersAlive>,
+	mut text_query: Query<(&mut Text, &HudText)>,
+) {
+	for (mut text, hud_text) in &mut text_query {
+		text.sections[0].value = match hud_text {
+			HudText::PlayersAlive => format!("Alive: {}", players_alive.0),
+			HudText::Zone => {
+				let action = if zone.shrinking { "Zone closing" } else { "Zone closes in" };
+				format!("{}: {:.0}s", action, zone.countdown.max(0.))
+			}
+			_ => continue,
+		};
+	}
+}
+
+fn update_kill_feed(
+	mut commands: Commands,
+	time: Res<Time>,
+	mut kill_events: EventReader<PlayerKilled>,
+	mut announcements: EventReader<ServerAnnouncement>,
+	player_names: Res<PlayerNames>,
+	kill_feed_query: Query<(Entity, Option<&Children>), With<KillFeed>>,
+	mut entry_query: Query<&mut KillFeedEntry>,
+) {
+	let Ok((kill_feed, entries)) = kill_feed_query.get_single() else {
+		return;
+	};
+
+	let mut live_entries = 0;
+	for entry in entries.into_iter().flatten() {
+		let Ok(mut entry_timer) = entry_query.get_mut(*entry) else {
+			continue;
+		};
+
+		if entry_timer.0.tick(time.delta()).finished() {
+			commands.entity(*entry).despawn_recursive();
+		} else {
+			live_entries += 1;
+		}
+	}
+
+	let kills = kill_events.read().map(|kill| {
+		let victim = player_names.display_name(kill.victim);
+		let message = match kill.killer {
+			Some(killer) => format!("{} destroyed {}", player_names.display_name(killer), victim),
+			None => format!("{} was lost to the void", victim),
+		};
+		(message, Color::ORANGE_RED)
+	});
+	let announcements = announcements
+		.read()
+		.map(|ServerAnnouncement(message)| (format!("[server] {message}"), Color::GOLD));
+
+	let mut oldest_entries = entries.into_iter().flatten();
+	for (message, color) in kills.chain(announcements) {
+		// Drop the oldest entry so the feed doesn't grow past the screen
+		if live_entries >= KILL_FEED_LENGTH {
+			if let Some(oldest) = oldest_entries.next() {
+				commands.entity(*oldest).despawn_recursive();
+			}
+		} else {
+			live_entries += 1;
+		}
+
+		commands.entity(kill_feed).with_children(|parent| {
+			parent.spawn((
+				TextBundle::from_section(
+					message,
+					TextStyle {
+						font_size: HUD_FONT_SIZE,
+						color,
+						..default()
+					},
+				),
+				KillFeedEntry(Timer::from_seconds(KILL_FEED_ENTRY_SECONDS, TimerMode::Once)),
+			));
+		});
+	}
+}
+
+fn show_network_errors(
+	time: Res<Time>,
+	mut network_errors: EventReader<NetworkErrorEvent>,
+	mut text_query: Query<(&mut Text, &mut NetworkErrorText)>,
+) {
+	let Ok((mut text, mut error_text)) = text_query.get_single_mut() else {
+		return;
+	};
+
+	if let Some(NetworkErrorEvent(error)) = network_errors.read().last() {
+		text.sections[0].value = format!("Network error: {error}");
+		error_text.0.reset();
+	} else if error_text.0.tick(time.delta()).just_finished() {
+		text.sections[0].value.clear();
+	}
+}
+
+fn update_connection_overlay(
+	connection_state: Option<Res<State<ClientConnectionState>>>,
+	backoff: Option<Res<ReconnectBackoff>>,
+	mut text_query: Query<&mut Text, With<ConnectionOverlay>>,
+) {
+	let Some(connection_state) = connection_state else {
+		return;
+	};
+
+	let message = match connection_state.get() {
+		ClientConnectionState::Idle => String::new(),
+		ClientConnectionState::Connecting => String::from("Connecting..."),
+		ClientConnectionState::Connected => String::new(),
+		ClientConnectionState::Reconnecting => match backoff {
+			Some(backoff) if backoff.attempt > 0 => {
+				format!("Connection lost, reconnecting (attempt {})...", backoff.attempt)
+			}
+			_ => String::from("Connection lost, reconnecting..."),
+		},
+		ClientConnectionState::Disconnected(DisconnectReason::ClosedByServer) => {
+			String::from("Disconnected by the server")
+		}
+		ClientConnectionState::Disconnected(DisconnectReason::GaveUp) => {
+			String::from("Could not reach the server")
+		}
+		ClientConnectionState::Disconnected(DisconnectReason::Rejected(reason)) => {
+			format!("Connection refused: {reason}")
+		}
+	};
+
+	for mut text in &mut text_query {
+		if text.sections[0].value != message {
+			text.sections[0].value = message.clone();
+		}
+	}
+}
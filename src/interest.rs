@@ -0,0 +1,183 @@
+use bevy::{math::DVec2, prelude::*, utils::HashMap};
+use serde::{Deserialize, Serialize};
+
+use crate::network::events::server::ServerEventAppExt;
+use crate::network::helper::{server_running, ClientId};
+use crate::player::Player;
+use crate::snapshot::apply_snapshot;
+#[cfg(feature = "server")]
+use {
+	crate::{
+		minimap::RADAR_RANGE,
+		network::{
+			events::server::{SendMode, ToClients},
+			helper::{ServerSet, ServerSn, SERVER_ID},
+		},
+		snapshot::send_snapshot,
+	},
+	bevy::utils::{hashbrown::hash_map::Entry, HashSet},
+	bevy_xpbd_2d::prelude::*,
+};
+
+/// How much further than [`RADAR_RANGE`] a ship has to get before it leaves scope, so ships
+/// at the edge don't flicker in and out.
+#[cfg(feature = "server")]
+const SCOPE_HYSTERESIS: f64 = 500.0;
+
+/// Only replicates ships a client could see on its radar, which saves bandwidth and keeps
+/// far away enemies hidden from modified clients.
+pub struct InterestPlugin;
+
+impl Plugin for InterestPlugin {
+	fn build(&self, app: &mut App) {
+		app.add_server_event::<ScopeUpdate>().add_systems(
+			Update,
+			apply_scope_update.before(apply_snapshot).run_if(not(server_running())),
+		);
+
+		#[cfg(feature = "server")]
+		app.init_resource::<InterestScopes>().add_systems(
+			PostUpdate,
+			update_scopes
+				.after(PhysicsSet::Sync)
+				.before(send_snapshot)
+				.before(ServerSet::Send)
+				.run_if(resource_exists::<ServerSn>()),
+		);
+	}
+}
+
+/// Ships that came into or went out of a client's scope, the client spawns and despawns them.
+#[derive(Event, TypePath, Serialize, Deserialize, Clone, Debug)]
+pub struct ScopeUpdate {
+	/// Set on the first update of a connection, `entered` is then the whole scope and any other
+	/// ship the client still has is stale.
+	pub reset: bool,
+	pub entered: Vec<(Player, DVec2)>,
+	pub left: Vec<ClientId>,
+}
+
+/// Ships each connected client currently has in scope, by owner.
+#[cfg(feature = "server")]
+#[derive(Resource, Default, Debug)]
+pub struct InterestScopes(HashMap<ClientId, HashSet<ClientId>>);
+
+#[cfg(feature = "server")]
+impl InterestScopes {
+	/// Whether `observer` currently has `ship` in scope.
+	pub fn sees(&self, observer: ClientId, ship: ClientId) -> bool {
+		self.0.get(&observer).is_some_and(|scope| scope.contains(&ship))
+	}
+
+	/// Everyone who should hear about something `ship` did, for [`SendMode::ToMany`].
+	///
+	/// Includes [`SERVER_ID`], a host sees the whole simulation anyway.
+	pub fn observers_of(&self, ship: ClientId) -> Vec<ClientId> {
+		self.0
+			.iter()
+			.filter(|(_, scope)| scope.contains(&ship))
+			.map(|(observer, _)| *observer)
+			.chain([SERVER_ID])
+			.collect()
+	}
+}
+
+/// Moves ships in and out of each client's scope based on distance from its own ship.
+/// Clients without a ship are spectating and see everything.
+#[cfg(feature = "server")]
+fn update_scopes(
+	server: Res<ServerSn>,
+	mut scopes: ResMut<InterestScopes>,
+	player_query: Query<(&Player, &Position)>,
+	mut scope_updates: EventWriter<ToClients<ScopeUpdate>>,
+) {
+	scopes.0.retain(|client_id, _| server.client_connections.contains(client_id));
+
+	for observer in &server.client_connections {
+		let eye = player_query
+			.iter()
+			.find(|(player, _)| player.0 == *observer)
+			.map(|(_, position)| position.0);
+
+		let (reset, scope) = match scopes.0.entry(*observer) {
+			Entry::Occupied(entry) => (false, entry.into_mut()),
+			Entry::Vacant(entry) => (true, entry.insert(HashSet::new())),
+		};
+
+		let mut entered = Vec::new();
+		let mut left = Vec::new();
+		for (player, position) in &player_query {
+			let in_scope = scope.contains(&player.0);
+			let range = if in_scope { RADAR_RANGE + SCOPE_HYSTERESIS } else { RADAR_RANGE };
+
+			if !eye.is_some_and(|eye| eye.distance(position.0) > range) {
+				if !in_scope {
+					scope.insert(player.0);
+					entered.push((*player, position.0));
+				}
+			} else if in_scope {
+				scope.remove(&player.0);
+				left.push(player.0);
+			}
+		}
+
+		// Ships that were destroyed or whose session expired
+		scope.retain(|ship| {
+			let exists = player_query.iter().any(|(player, _)| player.0 == *ship);
+			if !exists {
+				left.push(*ship);
+			}
+			exists
+		});
+
+		if reset || !entered.is_empty() || !left.is_empty() {
+			scope_updates.send(ToClients {
+				mode: SendMode::Direct(*observer),
+				event: ScopeUpdate { reset, entered, left },
+			});
+		}
+	}
+}
+
+/// Spawns ships that came into scope and despawns the ones that left, snapshots then only
+/// update ships we already have.
+fn apply_scope_update(
+	mut commands: Commands,
+	mut scope_updates: EventReader<ScopeUpdate>,
+	player_query: Query<(Entity, &Player)>,
+) {
+	// Several updates can arrive in one frame, only their combined outcome matters
+	let mut reset = false;
+	let mut changes: HashMap<ClientId, Option<(Player, DVec2)>> = HashMap::new();
+	for update in scope_updates.read() {
+		if update.reset {
+			reset = true;
+			changes.clear();
+		}
+		for ship in &update.left {
+			changes.insert(*ship, None);
+		}
+		for (player, position) in &update.entered {
+			changes.insert(player.0, Some((*player, *position)));
+		}
+	}
+
+	if !reset && changes.is_empty() {
+		return;
+	}
+
+	for (entity, player) in &player_query {
+		let keep = match changes.remove(&player.0) {
+			Some(change) => change.is_some(),
+			None => !reset,
+		};
+
+		if !keep {
+			commands.entity(entity).despawn_recursive();
+		}
+	}
+
+	for (player, position) in changes.into_values().flatten() {
+		commands.spawn((player, Transform::from_xyz(position.x as f32, position.y as f32, 0.)));
+	}
+}
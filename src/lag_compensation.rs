@@ -0,0 +1,143 @@
+use std::collections::VecDeque;
+
+use bevy::prelude::*;
+use bevy_xpbd_2d::prelude::*;
+
+#[cfg(feature = "server")]
+use crate::network::{helper::ServerSn, tick::increment_tick};
+use crate::network::tick::RepliconTick;
+#[cfg(feature = "server")]
+use crate::player::Player;
+
+/// Furthest back a shot may rewind targets, about half a second at the default tick rate.
+pub const MAX_REWIND_TICKS: u32 = 30;
+
+/// Records where every ship was on recent ticks so hits can be judged against what the shooter saw.
+pub struct LagCompensationPlugin;
+
+impl Plugin for LagCompensationPlugin {
+	#[cfg_attr(not(feature = "server"), allow(unused_variables))]
+	fn build(&self, app: &mut App) {
+		// Physics steps in `FixedUpdate` too, so each pose is the one the ship had at the end of its tick
+		#[cfg(feature = "server")]
+		app.add_systems(
+			FixedUpdate,
+			(attach_pose_history, record_pose_history)
+				.chain()
+				.after(increment_tick)
+				.after(PhysicsSet::Sync)
+				.run_if(resource_exists::<ServerSn>()),
+		);
+	}
+}
+
+/// A ship's collision shape and where it was on a given tick.
+#[derive(Clone, Debug)]
+pub struct Pose {
+	pub position: Position,
+	pub rotation: Rotation,
+	pub collider: Collider,
+}
+
+/// Ring buffer of a ship's [`Pose`] over the last [`MAX_REWIND_TICKS`] ticks, oldest first.
+#[derive(Component, Default, Debug)]
+pub struct PoseHistory(VecDeque<(RepliconTick, Pose)>);
+
+impl PoseHistory {
+	#[cfg(feature = "server")]
+	fn record(&mut self, tick: RepliconTick, pose: Pose) {
+		self.0.push_back((tick, pose));
+
+		let mut oldest = tick;
+		oldest.increment_by(MAX_REWIND_TICKS.wrapping_neg());
+		while self.0.front().is_some_and(|(recorded_tick, _)| *recorded_tick < oldest) {
+			self.0.pop_front();
+		}
+	}
+
+	/// Pose the ship had on `view_tick`, clamped to the rewind window.
+	///
+	/// Picks the newest recorded pose at or before `view_tick`, or the oldest one we still have.
+	pub fn at(&self, view_tick: RepliconTick) -> Option<&Pose> {
+		self.0
+			.iter()
+			.rev()
+			.find(|(recorded_tick, _)| *recorded_tick <= view_tick)
+			.or_else(|| self.0.front())
+			.map(|(_, pose)| pose)
+	}
+}
+
+#[cfg(feature = "server")]
+fn attach_pose_history(mut commands: Commands, new_players: Query<Entity, (With<Player>, Without<PoseHistory>)>) {
+	for entity in &new_players {
+		commands.entity(entity).insert(PoseHistory::default());
+	}
+}
+
+#[cfg(feature = "server")]
+fn record_pose_history(
+	tick: Res<RepliconTick>,
+	mut player_query: Query<(&Position, &Rotation, &Collider, &mut PoseHistory)>,
+) {
+	for (position, rotation, collider, mut history) in &mut player_query {
+		history.record(
+			*tick,
+			Pose {
+				position: *position,
+				rotation: *rotation,
+				collider: collider.clone(),
+			},
+		);
+	}
+}
+
+#[cfg(all(test, feature = "server"))]
+mod tests {
+	use bevy::math::DVec2;
+
+	use super::*;
+
+	fn pose(x: f64) -> Pose {
+		Pose {
+			position: Position(DVec2::new(x, 0.)),
+			rotation: Rotation::default(),
+			collider: Collider::ball(1.),
+		}
+	}
+
+	fn history(ticks: impl IntoIterator<Item = u32>) -> PoseHistory {
+		let mut history = PoseHistory::default();
+		for tick in ticks {
+			history.record(RepliconTick(tick), pose(tick as f64));
+		}
+		history
+	}
+
+	fn x_at(history: &PoseHistory, tick: u32) -> Option<f64> {
+		history.at(RepliconTick(tick)).map(|pose| pose.position.0.x)
+	}
+
+	#[test]
+	fn picks_the_newest_pose_at_or_before_the_view_tick() {
+		let history = history([10, 11, 13]);
+		assert_eq!(x_at(&history, 11), Some(11.));
+		assert_eq!(x_at(&history, 12), Some(11.));
+		assert_eq!(x_at(&history, 20), Some(13.));
+		assert_eq!(x_at(&PoseHistory::default(), 11), None);
+	}
+
+	#[test]
+	fn clamps_to_the_rewind_window() {
+		let history = history(0..100);
+		assert_eq!(history.0.len(), MAX_REWIND_TICKS as usize + 1);
+		assert_eq!(x_at(&history, 5), Some((99 - MAX_REWIND_TICKS) as f64));
+	}
+
+	#[test]
+	fn survives_tick_wrapping() {
+		let history = history([u32::MAX - 1, u32::MAX, 0, 1]);
+		assert_eq!(x_at(&history, u32::MAX), Some(u32::MAX as f64));
+		assert_eq!(x_at(&history, 0), Some(0.));
+	}
+}
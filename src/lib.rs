@@ -0,0 +1,148 @@
+//! Game plugins, shared by the binary in `main.rs` and the integration tests in `tests/`.
+
+pub mod helper;
+// mod replicon_components;
+
+pub mod admin;
+pub mod camera;
+pub mod chat;
+pub mod hud;
+pub mod interest;
+pub mod lag_compensation;
+pub mod map;
+pub mod minimap;
+pub mod nameplate;
+pub mod net_overlay;
+pub mod network;
+pub mod player;
+pub mod server_browser;
+pub mod snapshot;
+pub mod stats;
+pub mod weapon;
+pub mod zone;
+
+use bevy::prelude::*;
+use clap::Parser;
+use network::conditioner::{parse_link_conditions, LinkConditions};
+#[cfg(feature = "client")]
+use network::helper::parse_server_address;
+#[cfg(feature = "server")]
+use network::helper::DEFAULT_PORT;
+use network::tick::{DEFAULT_SNAPSHOT_RATE, DEFAULT_TICK_RATE};
+#[cfg(feature = "server")]
+use network::violations::{DEFAULT_BAN_AFTER_KICKS, DEFAULT_KICK_SCORE};
+#[cfg(feature = "server")]
+use std::net::SocketAddr;
+use std::path::PathBuf;
+
+#[derive(Parser, Resource, Debug, Clone)]
+#[command(version, about)]
+pub struct Cli {
+	/// Display name shown above your ship.
+	#[arg(short, long, default_value = "Pilot")]
+	pub name: String,
+
+	/// Directory the server writes end-of-match summaries to.
+	#[arg(long, default_value = "match_stats")]
+	pub stats_dir: PathBuf,
+
+	/// Server to join right away, as `host[:port]` or a `ws://` URL. Without it the client opens
+	/// the server browser.
+	#[cfg(feature = "client")]
+	#[arg(long, value_parser = parse_server_address)]
+	pub connect: Option<url::Url>,
+
+	/// Address the server accepts players on. Info queries are answered on the next port up, and
+	/// the server announces itself on the LAN unless this is a loopback address.
+	#[cfg(feature = "server")]
+	#[arg(long, default_value_t = SocketAddr::from(([127, 0, 0, 1], DEFAULT_PORT)))]
+	pub listen: SocketAddr,
+
+	/// Name the server shows up as in the server browser.
+	#[cfg(feature = "server")]
+	#[arg(long, default_value = "Space Royale")]
+	pub server_name: String,
+
+	/// Shared secret (hex) clients must present to connect. Leave unset to accept anyone.
+	#[arg(long, value_parser = parse_auth_secret)]
+	pub auth_secret: Option<u128>,
+
+	/// Seed the server generates the map from, clients always use the server's.
+	#[arg(long)]
+	pub seed: Option<u64>,
+
+	/// Password for admin commands sent from a client. The server refuses remote admin commands
+	/// while it is unset, a client sends it along with every command.
+	#[arg(long)]
+	pub admin_password: Option<String>,
+
+	/// File the server appends every admin command and its outcome to.
+	#[cfg(feature = "server")]
+	#[arg(long, default_value = "admin_audit.log")]
+	pub admin_audit_log: PathBuf,
+
+	/// File the server appends every anti-cheat violation and its outcome to.
+	#[cfg(feature = "server")]
+	#[arg(long, default_value = "violations.log")]
+	pub violation_log: PathBuf,
+
+	/// Violation score at which a client is kicked, see `Violations`.
+	#[cfg(feature = "server")]
+	#[arg(long, default_value_t = DEFAULT_KICK_SCORE)]
+	pub kick_score: f32,
+
+	/// Kicks after which a client is banned until the server restarts, 0 never bans. Bans are by
+	/// client id, which a restarted client picks anew.
+	#[cfg(feature = "server")]
+	#[arg(long, default_value_t = DEFAULT_BAN_AFTER_KICKS)]
+	pub ban_after_kicks: u32,
+
+	/// Print every registered network channel and exit.
+	#[arg(long)]
+	pub dump_channels: bool,
+
+	/// Host the server inside this process and play on it without opening any socket.
+	#[cfg(all(feature = "client", feature = "server"))]
+	#[arg(long)]
+	pub offline: bool,
+
+	/// Run a listen server and play on it directly, other players join over the network. Without
+	/// it (or `--offline`) the game is only a client.
+	#[cfg(all(feature = "client", feature = "server"))]
+	#[arg(long, conflicts_with = "offline")]
+	pub host: bool,
+
+	/// Simulation ticks per second, inputs are sampled and applied once per tick.
+	#[arg(long, default_value_t = DEFAULT_TICK_RATE, value_parser = parse_rate)]
+	pub tick_rate: f64,
+
+	/// Snapshots per second the server sends to each client.
+	#[arg(long, default_value_t = DEFAULT_SNAPSHOT_RATE, value_parser = parse_rate)]
+	pub snapshot_rate: f64,
+
+	/// Simulated conditions for traffic arriving at this process, e.g.
+	/// `latency=100,jitter=20,loss=0.05,reorder=0.1` (times in milliseconds). F9 toggles it.
+	#[arg(long, default_value = "", value_parser = parse_link_conditions)]
+	pub sim_inbound: LinkConditions,
+
+	/// Same as `--sim-inbound`, but for traffic leaving this process.
+	#[arg(long, default_value = "", value_parser = parse_link_conditions)]
+	pub sim_outbound: LinkConditions,
+}
+
+fn parse_rate(rate: &str) -> Result<f64, String> {
+	match rate.parse::<f64>() {
+		Ok(rate) if rate > 0.0 && rate.is_finite() => Ok(rate),
+		Ok(_) => Err(String::from("rate should be a positive number of times per second")),
+		Err(error) => Err(format!("invalid rate: {error}")),
+	}
+}
+
+fn parse_auth_secret(secret: &str) -> Result<u128, String> {
+	u128::from_str_radix(secret.trim_start_matches("0x"), 16)
+		.map_err(|error| format!("auth secret should be up to 32 hex digits: {error}"))
+}
+
+/// The client id this app connected with, used to find the locally controlled ship.
+#[derive(Resource)]
+pub struct ClientIdResource(pub u128);
@@ -2,169 +2,67 @@
 // cargo run --no-default-features --features server
 // CARGO_TARGET_WASM32_UNKNOWN_UNKNOWN_RUNNER=wasm-server-runner RUSTFLAGS=--cfg=web_sys_unstable_apis cargo run --target wasm32-unknown-unknown --no-default-features --features client
 
-use bevy::{prelude::*, transform::commands};
-use bevy::transform::TransformSystem;
+use bevy::{diagnostic::FrameTimeDiagnosticsPlugin, prelude::*};
 use bevy_particle_systems::ParticleSystemPlugin;
 
-mod helper;
-// mod replicon_components;
-
-mod map;
-mod network;
-mod player;
-
-// use network::*;
-
-use map::MapPlugin;
-use network::{NetworkPlugin, events::server::ServerEventAppExt};
-use player::{Player, PlayerPlugin, PhysicsBundle};
+use space_royale::admin::AdminPlugin;
+use space_royale::camera::CameraPlugin;
+use space_royale::chat::ChatPlugin;
+use space_royale::hud::HudPlugin;
+use space_royale::interest::InterestPlugin;
+use space_royale::lag_compensation::LagCompensationPlugin;
+use space_royale::map::MapPlugin;
+use space_royale::minimap::MinimapPlugin;
+use space_royale::nameplate::NameplatePlugin;
+use space_royale::net_overlay::NetOverlayPlugin;
+use space_royale::network::diagnostics::NetworkDiagnosticsPlugin;
+use space_royale::network::NetworkPlugin;
+use space_royale::player::PlayerPlugin;
+use space_royale::server_browser::ServerBrowserPlugin;
+use space_royale::snapshot::SnapshotPlugin;
+use space_royale::stats::StatsPlugin;
+use space_royale::weapon::WeaponPlugin;
+use space_royale::zone::ZonePlugin;
+use space_royale::Cli;
 // use replicon_components::RepliconComponentsPlugin;
 
-// use bevy_replicon::{
-//     prelude::*,
-//     renet::{
-//         transport::{
-//             ClientAuthentication, NetcodeClientTransport, NetcodeServerTransport,
-//             ServerAuthentication, ServerConfig,
-//         },
-//         ClientId, ConnectionConfig, ServerEvent,
-//     },
-// };
 use bevy_xpbd_2d::prelude::*;
-use serde::{Serialize, Deserialize};
-
-// #[derive(Parser, PartialEq, Resource)]
-// enum Cli {
-//     Server {
-//         #[arg(short, long, default_value_t = PORT)]
-//         port: u16,
-//     },
-//     Client {
-//         #[arg(short, long, default_value_t = Ipv4Addr::LOCALHOST.into())]
-//         ip: IpAddr,
-
-//         #[	arg(short, long, default_value_t = PORT)]
-//         port: u16,
-//     },
-// }
-
-// impl Default for Cli {
-//     fn default() -> Self {
-//         Self::parse()
-//     }
-// }
+use clap::Parser;
 
 fn main() {
+	let cli = Cli::parse();
+
 	App::new()
 		// .init_resource::<Cli>() // Parse CLI before creating window.
+		.insert_resource(Time::<Fixed>::from_hz(cli.tick_rate))
+		// One physics step per tick, so a tick's inputs, poses and snapshots all agree
+		.insert_resource(PhysicsTimestep::FixedOnce(1.0 / cli.tick_rate))
+		.insert_resource(cli)
 		.add_plugins((
 			DefaultPlugins,
-			ParticleSystemPlugin::default(),
-			PhysicsPlugins::default(),
+			ParticleSystemPlugin,
+			PhysicsPlugins::new(FixedUpdate),
 			NetworkPlugin,
 		))
 		// .add_plugins(WorldInspectorPlugin::new())
-		.add_systems(Startup, setup)
-		.add_systems(
-			PostUpdate,
-			update_camera
-				.after(PhysicsSet::Sync)
-				.before(TransformSystem::TransformPropagate),
-		)
-		.add_plugins((MapPlugin, PlayerPlugin))
-		// .add_plugins((
-		// 	LogDiagnosticsPlugin::default(),
-		// 	FrameTimeDiagnosticsPlugin::default(),
-		// ))
-		// .insert_resource(Time::<Fixed>::from_seconds(1. / 60.))
+		.add_plugins((
+			CameraPlugin,
+			MapPlugin,
+			PlayerPlugin,
+			ZonePlugin,
+			HudPlugin,
+			NetOverlayPlugin,
+			MinimapPlugin,
+			NameplatePlugin,
+			StatsPlugin,
+			SnapshotPlugin,
+			InterestPlugin,
+			LagCompensationPlugin,
+			WeaponPlugin,
+			AdminPlugin,
+			ChatPlugin,
+		))
+		.add_plugins((FrameTimeDiagnosticsPlugin, NetworkDiagnosticsPlugin, ServerBrowserPlugin))
 		// .add_systems(Update, server_event_system)
-		.add_server_event::<GameState>()
 		.run();
 }
-
-#[derive(Resource)]
-struct ClientIdResource(pub u128);
-
-#[derive(Bundle)]
-struct PlayerCameraBundle {
-	player_camera: PlayerCamera,
-	camera: Camera2dBundle,
-}
-
-#[derive(Component)]
-pub struct PlayerCamera;
-
-fn setup(mut commands: Commands) {
-	commands.spawn(PlayerCameraBundle {
-		player_camera: PlayerCamera,
-		camera: Camera2dBundle {
-			transform: Transform::from_xyz(0., 0., 1.),
-			projection: OrthographicProjection {
-				scale: 6.5,
-				..default()
-			},
-			..default()
-		},
-	});
-}
-
-// fn update_camera(
-// 	player_position_query: Query<&Position, With<Player>>,
-// 	mut camera_query: Query<&mut Transform, With<PlayerCamera>>,
-// 	time: Res<Time>,
-// ) {
-// 	let player_position = player_position_query.get_single().unwrap().extend(1.);
-// 	let mut transform = camera_query.get_single_mut().unwrap();
-
-// 	transform.translation = transform
-// 		.translation
-// 		.lerp(player_position.as_vec3(), time.delta_seconds() * 10.);
-// }
-
-fn update_camera(
-	player_position_query: Query<(&Position, &Player)>,
-	mut camera_query: Query<&mut Transform, With<PlayerCamera>>,
-	time: Res<Time>,
-	// client_id: Res<ClientIdResource>,
-) {
-	for (player_position, _player) in &player_position_query {
-		let mut transform = camera_query.get_single_mut().unwrap();
-
-		transform.translation = transform.translation.lerp(
-			player_position.extend(1.).as_vec3(),
-			time.delta_seconds() * 10.,
-		);
-	}
-}
-
-#[derive(Serialize, Deserialize, Clone, Debug)]
-pub struct PlayerState {
-	pub player: Player,
-	pub name: String,
-	pub transform: Transform,
-	pub physics: PhysicsBundle,
-	pub engine_on: bool,
-
-}
-
-#[derive(Serialize, Deserialize, Event, Clone, Debug)]
-pub struct GameState {
-	pub players: Vec<PlayerState>,
-	// bullets: Vec<PlayerState>,
-	// players: Vec<PlayerState>,
-}
-
-// fn send_current_gamestate(
-// 	commands: Commands,
-// 	players: Query<(&Player, Entity)>,
-// 	mut game_state_event: EventWriter<GameState>,
-// ) {
-// 	let mut game_state = GameState { players: Vec::new() };
-
-// 	for (player_tag, player_entity) in players.iter_mut() {
-// 		commands.entity(player_entity).log_components();
-// 		// commands.entity(player_entity).
-
-// 	}
-// 	// commands.get_entity(entity)
-// }
\ No newline at end of file
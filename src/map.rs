@@ -1,13 +1,80 @@
 use bevy::{math::DVec2, prelude::*};
 use bevy_xpbd_2d::prelude::*;
+use serde::{Deserialize, Serialize};
 
 use crate::helper;
+use crate::network::channels_config::ChannelConfig;
+use crate::network::events::server::ServerEventAppExt;
+use crate::network::helper::server_running;
+use crate::Cli;
+#[cfg(feature = "server")]
+use crate::network::{
+	events::server::{SendMode, ToClients},
+	helper::ServerSn,
+	EventClientConnected,
+};
 pub struct MapPlugin;
 
 impl Plugin for MapPlugin {
 	fn build(&self, app: &mut App) {
-		app.add_systems(Startup, spawn_map)
-			.add_systems(FixedUpdate, apply_gravity);
+		app.init_resource::<MapSeed>()
+			.add_server_event_with::<MapSeed>(ChannelConfig::for_event::<MapSeed>().latest_only())
+			.add_systems(
+				Update,
+				(
+					apply_map_seed.run_if(not(server_running())),
+					spawn_map.run_if(resource_changed::<MapSeed>()),
+				)
+					.chain(),
+			)
+			.add_systems(FixedUpdate, apply_gravity.before(PhysicsSet::Prepare));
+
+		#[cfg(feature = "server")]
+		app.add_systems(Update, send_map_seed.run_if(resource_exists::<ServerSn>()));
+	}
+}
+
+/// Seed the planets are generated from. Owned by the server, mirrored on clients.
+#[derive(Resource, Event, TypePath, Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub struct MapSeed(pub u64);
+
+impl FromWorld for MapSeed {
+	fn from_world(world: &mut World) -> Self {
+		let seed = world.get_resource::<Cli>().and_then(|cli| cli.seed);
+		Self(seed.unwrap_or(DEFAULT_MAP_SEED))
+	}
+}
+
+const DEFAULT_MAP_SEED: u64 = u64::from_be_bytes([b'Z', b'a', b'c', b'k', b'C', b'o', b'o', b'l']);
+
+/// Tells new clients which map we are playing on, and everyone once it changes.
+#[cfg(feature = "server")]
+fn send_map_seed(
+	map_seed: Res<MapSeed>,
+	mut connection_events: EventReader<EventClientConnected>,
+	mut seed_events: EventWriter<ToClients<MapSeed>>,
+) {
+	if map_seed.is_changed() && !map_seed.is_added() {
+		connection_events.clear();
+		seed_events.send(ToClients {
+			mode: SendMode::Broadcast,
+			event: *map_seed,
+		});
+		return;
+	}
+
+	for EventClientConnected(client_id, _) in connection_events.read() {
+		seed_events.send(ToClients {
+			mode: SendMode::Direct(*client_id),
+			event: *map_seed,
+		});
+	}
+}
+
+fn apply_map_seed(mut map_seed: ResMut<MapSeed>, mut seed_events: EventReader<MapSeed>) {
+	if let Some(seed) = seed_events.read().last() {
+		// Regenerating the same map would only make the planets flicker
+		map_seed.set_if_neq(*seed);
 	}
 }
 
@@ -22,9 +89,19 @@ struct PlanetBundle {
 	friction: Friction,
 }
 
+/// Width and height of the generated map, which is centered on the origin.
+pub const WORLD_SIZE: f64 = 3e4;
+
 #[derive(Component)]
 pub struct AffectedByGravity;
 
+const GRAVITATIONAL_CONSTANT: f64 = 4000.0;
+
+/// Magnitude of the pull between two bodies `offset` apart, before scaling by the timestep.
+pub fn gravitational_pull(body_mass: f64, planet_mass: f64, offset: DVec2) -> f64 {
+	GRAVITATIONAL_CONSTANT * ((body_mass * planet_mass) / offset.length_squared())
+}
+
 fn apply_gravity(
 	time_step: Res<Time<Fixed>>,
 	mut gravity_affected_query: Query<(&AffectedByGravity, &Position, &Mass, &mut ExternalForce)>,
@@ -35,8 +112,7 @@ fn apply_gravity(
 			let grav_direction = planet_position.0 - player_position.0;
 
 			let force = time_step.timestep().as_secs_f64()
-				* 4000.0 * ((player_mass.0 * planet_mass.0)
-				/ (grav_direction).length_squared());
+				* gravitational_pull(player_mass.0, planet_mass.0, grav_direction);
 
 			let direction_norm = grav_direction.normalize();
 			let force_vec = direction_norm * force;
@@ -47,16 +123,27 @@ fn apply_gravity(
 }
 
 #[derive(Component)]
-pub struct Planet;
+pub struct Planet {
+	pub planet_type: PlanetTypesGen,
+	pub radius: f64,
+}
 
+/// Replaces whatever planets there are with the ones generated from [`MapSeed`].
 fn spawn_map(
 	mut commands: Commands,
+	map_seed: Res<MapSeed>,
+	planet_query: Query<Entity, With<Planet>>,
 	mut meshes: ResMut<Assets<Mesh>>,
 	mut materials: ResMut<Assets<ColorMaterial>>,
 ) {
-	let world_size = 3e4;
+	for planet in &planet_query {
+		commands.entity(planet).despawn_recursive();
+	}
+
+	let world_size = WORLD_SIZE;
 	let planet_density: f64 = 3.65 * 1e-4;
 	let gened_world = generate_world(
+		map_seed.0,
 		(world_size * -0.5, world_size * -0.5),
 		world_size,
 		(world_size.powf(2.0) * (planet_density).powf(2.0)) as i32,
@@ -68,7 +155,10 @@ fn spawn_map(
 
 	for planet in gened_world {
 		commands.spawn(PlanetBundle {
-			planet: Planet,
+			planet: Planet {
+				planet_type: planet.planet_type,
+				radius: planet.radius,
+			},
 			mesh: ColorMesh2dBundle {
 				mesh: meshes
 					.add(shape::Circle::new(planet.radius as f32).into())
@@ -78,8 +168,8 @@ fn spawn_map(
 			},
 			rigid_body: RigidBody::Static,
 			collider: Collider::ball((planet.radius as f32).into()),
-			position: Position(DVec2::new(planet.x, planet.y).into()),
-			mass: Mass((planet.mass).into()),
+			position: Position(DVec2::new(planet.x, planet.y)),
+			mass: Mass(planet.mass),
 			friction: Friction::new(0.9),
 		});
 	}
@@ -137,12 +227,12 @@ impl PlanetTypesGen {
 	}
 
 	fn get_density(&self) -> f64 {
-		return match &self {
+		match &self {
 			PlanetTypesGen::Alien => 475.0,
 			PlanetTypesGen::Terestrial => 450.0,
 			PlanetTypesGen::GasGiant => 300.0,
 			PlanetTypesGen::BlackHole => 10000.0,
-		};
+		}
 	}
 
 	pub fn get_color(&self) -> Color {
@@ -191,11 +281,9 @@ fn check_planet_valid(size: f64, planet_list: &Vec<PlanetGen>, canidate: &Planet
 
 use rand_chacha;
 
-pub fn generate_world(offset: (f64, f64), size: f64, planet_count: i32) -> Vec<PlanetGen> {
+pub fn generate_world(seed: u64, offset: (f64, f64), size: f64, planet_count: i32) -> Vec<PlanetGen> {
 	assert!(size > 0.0);
-	let mut rng = rand_chacha::ChaChaRng::seed_from_u64(u64::from_be_bytes([
-		b'Z', b'a', b'c', b'k', b'C', b'o', b'o', b'l',
-	]));
+	let mut rng = rand_chacha::ChaChaRng::seed_from_u64(seed);
 
 	let mut planets: Vec<PlanetGen> = vec![];
 	let mut planet_frequencies: Vec<(PlanetTypesGen, f64)> = vec![];
@@ -0,0 +1,249 @@
+use bevy::{math::DVec2, prelude::*, window::PrimaryWindow};
+use bevy_xpbd_2d::prelude::*;
+
+use crate::camera::PlayerCamera;
+use crate::map::{Planet, PlanetTypesGen, WORLD_SIZE};
+use crate::player::Player;
+use crate::zone::SafeZone;
+use crate::ClientIdResource;
+
+pub struct MinimapPlugin;
+
+impl Plugin for MinimapPlugin {
+	fn build(&self, app: &mut App) {
+		app.add_systems(Startup, spawn_minimap).add_systems(
+			Update,
+			(
+				add_planet_blips,
+				(update_dynamic_blips, update_edge_indicators)
+					.run_if(resource_exists::<ClientIdResource>()),
+			),
+		);
+	}
+}
+
+/// Side length of the minimap in logical pixels.
+pub const MINIMAP_SIZE: f32 = 200.0;
+/// Enemies further than this from the local ship don't show up on radar.
+pub const RADAR_RANGE: f64 = 6000.0;
+/// Black holes further than this don't get an edge indicator.
+const BLACK_HOLE_WARNING_RANGE: f64 = 4000.0;
+const ZONE_OUTLINE_SEGMENTS: usize = 48;
+const EDGE_INDICATOR_SIZE: f32 = 12.0;
+const EDGE_INDICATOR_MARGIN: f32 = 20.0;
+
+#[derive(Component)]
+struct Minimap;
+
+/// Minimap entries that are rebuilt every frame (ships and the zone outline).
+#[derive(Component)]
+struct DynamicBlip;
+
+/// Minimap entry of a planet, rebuilt when the map is regenerated.
+#[derive(Component)]
+struct PlanetBlip;
+
+#[derive(Component)]
+struct EdgeIndicatorRoot;
+
+#[derive(Component)]
+struct EdgeIndicator;
+
+fn spawn_minimap(mut commands: Commands) {
+	commands.spawn((
+		NodeBundle {
+			style: Style {
+				position_type: PositionType::Absolute,
+				right: Val::Px(10.),
+				bottom: Val::Px(10.),
+				width: Val::Px(MINIMAP_SIZE),
+				height: Val::Px(MINIMAP_SIZE),
+				overflow: Overflow::clip(),
+				..default()
+			},
+			background_color: Color::rgba(0., 0., 0.1, 0.7).into(),
+			..default()
+		},
+		Minimap,
+	));
+
+	commands.spawn((
+		NodeBundle {
+			style: Style {
+				position_type: PositionType::Absolute,
+				width: Val::Percent(100.),
+				height: Val::Percent(100.),
+				..default()
+			},
+			..default()
+		},
+		EdgeIndicatorRoot,
+	));
+}
+
+/// Maps a world position onto minimap pixel coordinates (origin in the top left corner).
+fn world_to_minimap(position: DVec2) -> Vec2 {
+	let normalized = (position / WORLD_SIZE + 0.5).as_vec2();
+	Vec2::new(normalized.x, 1. - normalized.y) * MINIMAP_SIZE
+}
+
+fn blip(center: Vec2, size: f32, color: Color) -> NodeBundle {
+	NodeBundle {
+		style: Style {
+			position_type: PositionType::Absolute,
+			left: Val::Px(center.x - size / 2.),
+			top: Val::Px(center.y - size / 2.),
+			width: Val::Px(size),
+			height: Val::Px(size),
+			..default()
+		},
+		background_color: color.into(),
+		..default()
+	}
+}
+
+fn add_planet_blips(
+	mut commands: Commands,
+	minimap_query: Query<Entity, With<Minimap>>,
+	planet_query: Query<(&Planet, &Position), Added<Planet>>,
+	blip_query: Query<Entity, With<PlanetBlip>>,
+	mut removed_planets: RemovedComponents<Planet>,
+) {
+	let Ok(minimap) = minimap_query.get_single() else {
+		return;
+	};
+
+	// Planets only go away when the whole map is replaced
+	if removed_planets.read().next().is_some() {
+		for blip in &blip_query {
+			commands.entity(blip).despawn_recursive();
+		}
+	}
+
+	for (planet, position) in &planet_query {
+		// Black holes are tiny but too important to disappear at minimap scale
+		let size = ((planet.radius * 2. / WORLD_SIZE) as f32 * MINIMAP_SIZE).max(3.);
+		let color = match planet.planet_type {
+			PlanetTypesGen::BlackHole => Color::PURPLE,
+			planet_type => planet_type.get_color(),
+		};
+
+		commands.entity(minimap).with_children(|parent| {
+			parent.spawn((blip(world_to_minimap(position.0), size, color), PlanetBlip));
+		});
+	}
+}
+
+fn update_dynamic_blips(
+	mut commands: Commands,
+	client_id: Res<ClientIdResource>,
+	zone: Res<SafeZone>,
+	minimap_query: Query<Entity, With<Minimap>>,
+	blip_query: Query<Entity, With<DynamicBlip>>,
+	player_query: Query<(&Player, &Position)>,
+) {
+	let Ok(minimap) = minimap_query.get_single() else {
+		return;
+	};
+
+	for blip in &blip_query {
+		commands.entity(blip).despawn_recursive();
+	}
+
+	let local_position = player_query
+		.iter()
+		.find(|(player, _)| player.0 == client_id.0)
+		.map(|(_, position)| position.0);
+
+	commands.entity(minimap).with_children(|parent| {
+		for segment in 0..ZONE_OUTLINE_SEGMENTS {
+			let angle = segment as f64 / ZONE_OUTLINE_SEGMENTS as f64 * std::f64::consts::TAU;
+			let point = zone.center + DVec2::from_angle(angle) * zone.radius;
+
+			parent.spawn((blip(world_to_minimap(point), 2., Color::CYAN), DynamicBlip));
+		}
+
+		for (player, position) in &player_query {
+			if player.0 == client_id.0 {
+				parent.spawn((blip(world_to_minimap(position.0), 5., Color::WHITE), DynamicBlip));
+				continue;
+			}
+
+			let in_radar_range = local_position
+				.is_some_and(|local_position| local_position.distance(position.0) <= RADAR_RANGE);
+
+			if in_radar_range {
+				parent.spawn((blip(world_to_minimap(position.0), 4., Color::RED), DynamicBlip));
+			}
+		}
+	});
+}
+
+#[allow(clippy::too_many_arguments)]
+fn update_edge_indicators(
+	mut commands: Commands,
+	client_id: Res<ClientIdResource>,
+	window_query: Query<&Window, With<PrimaryWindow>>,
+	camera_query: Query<(&Transform, &OrthographicProjection), With<PlayerCamera>>,
+	indicator_root_query: Query<Entity, With<EdgeIndicatorRoot>>,
+	indicator_query: Query<Entity, With<EdgeIndicator>>,
+	player_query: Query<(&Player, &Position)>,
+	planet_query: Query<(&Planet, &Position)>,
+) {
+	let (Ok(window), Ok((camera_transform, projection)), Ok(indicator_root)) = (
+		window_query.get_single(),
+		camera_query.get_single(),
+		indicator_root_query.get_single(),
+	) else {
+		return;
+	};
+
+	for indicator in &indicator_query {
+		commands.entity(indicator).despawn_recursive();
+	}
+
+	let Some(local_position) = player_query
+		.iter()
+		.find(|(player, _)| player.0 == client_id.0)
+		.map(|(_, position)| position.0)
+	else {
+		return;
+	};
+
+	let enemies = player_query
+		.iter()
+		.filter(|(player, position)| {
+			player.0 != client_id.0 && local_position.distance(position.0) <= RADAR_RANGE
+		})
+		.map(|(_, position)| (position.0, Color::RED));
+
+	let black_holes = planet_query
+		.iter()
+		.filter(|(planet, position)| {
+			planet.planet_type == PlanetTypesGen::BlackHole
+				&& local_position.distance(position.0) <= BLACK_HOLE_WARNING_RANGE
+		})
+		.map(|(_, position)| (position.0, Color::PURPLE));
+
+	let half_screen = Vec2::new(window.width(), window.height()) / 2.;
+	let camera_center = camera_transform.translation.truncate();
+
+	commands.entity(indicator_root).with_children(|parent| {
+		for (threat_position, color) in enemies.chain(black_holes) {
+			let offset = (threat_position.as_vec2() - camera_center) / projection.scale;
+
+			// Still on screen, no need to point at it
+			if offset.x.abs() < half_screen.x && offset.y.abs() < half_screen.y {
+				continue;
+			}
+
+			// Push the indicator along the threat direction until it touches the screen edge
+			let bounds = half_screen - EDGE_INDICATOR_MARGIN;
+			let edge_scale = (bounds.x / offset.x.abs()).min(bounds.y / offset.y.abs());
+			let edge_offset = offset * edge_scale;
+			let screen_position = Vec2::new(half_screen.x + edge_offset.x, half_screen.y - edge_offset.y);
+
+			parent.spawn((blip(screen_position, EDGE_INDICATOR_SIZE, color), EdgeIndicator));
+		}
+	});
+}
@@ -0,0 +1,95 @@
+use bevy::prelude::*;
+use bevy_xpbd_2d::prelude::*;
+
+use crate::camera::PlayerCamera;
+use crate::player::{Player, PlayerName, SHIP_RADIUS};
+
+pub struct NameplatePlugin;
+
+impl Plugin for NameplatePlugin {
+	fn build(&self, app: &mut App) {
+		app.add_systems(
+			Update,
+			(spawn_nameplates, update_nameplate_text, despawn_nameplates),
+		)
+		.add_systems(PostUpdate, follow_ships.after(PhysicsSet::Sync));
+	}
+}
+
+const NAMEPLATE_FONT_SIZE: f32 = 18.0;
+/// Screen space gap between the ship and its nameplate, scaled by camera zoom.
+const NAMEPLATE_OFFSET: f32 = 14.0;
+
+/// Floating label that tracks the ship it names. Kept as a separate entity
+/// so it doesn't rotate along with the ship.
+#[derive(Component)]
+struct Nameplate(Entity);
+
+fn spawn_nameplates(mut commands: Commands, player_query: Query<(Entity, &PlayerName), Added<PlayerName>>) {
+	for (ship, name) in &player_query {
+		commands.spawn((
+			Text2dBundle {
+				text: Text::from_section(
+					name.0.clone(),
+					TextStyle {
+						font_size: NAMEPLATE_FONT_SIZE,
+						color: Color::WHITE,
+						..default()
+					},
+				),
+				..default()
+			},
+			Nameplate(ship),
+		));
+	}
+}
+
+fn update_nameplate_text(
+	player_query: Query<&PlayerName, Changed<PlayerName>>,
+	mut nameplate_query: Query<(&Nameplate, &mut Text)>,
+) {
+	for (nameplate, mut text) in &mut nameplate_query {
+		if let Ok(name) = player_query.get(nameplate.0) {
+			text.sections[0].value = name.0.clone();
+		}
+	}
+}
+
+fn despawn_nameplates(
+	mut commands: Commands,
+	player_query: Query<(), With<Player>>,
+	nameplate_query: Query<(Entity, &Nameplate)>,
+) {
+	for (entity, nameplate) in &nameplate_query {
+		if player_query.get(nameplate.0).is_err() {
+			commands.entity(entity).despawn_recursive();
+		}
+	}
+}
+
+fn follow_ships(
+	camera_query: Query<&OrthographicProjection, With<PlayerCamera>>,
+	player_query: Query<&Position, With<Player>>,
+	mut nameplate_query: Query<(&Nameplate, &mut Transform)>,
+) {
+	// Nameplates stay the same size on screen no matter how far we zoom out
+	let zoom = camera_query
+		.get_single()
+		.map(|projection| projection.scale)
+		.unwrap_or(1.);
+
+	for (nameplate, mut transform) in &mut nameplate_query {
+		let Ok(position) = player_query.get(nameplate.0) else {
+			continue;
+		};
+
+		let position = position.as_vec2();
+
+		transform.translation = Vec3::new(
+			position.x,
+			position.y + SHIP_RADIUS + NAMEPLATE_OFFSET * zoom,
+			0.5,
+		);
+		transform.scale = Vec3::splat(zoom);
+	}
+}
@@ -0,0 +1,126 @@
+use bevy::diagnostic::{DiagnosticId, DiagnosticsStore, FrameTimeDiagnosticsPlugin};
+use bevy::prelude::*;
+
+use crate::minimap::MINIMAP_SIZE;
+use crate::network::channels_config::ChannelManager;
+use crate::network::diagnostics::{
+	CLIENT_AVERAGE_PAYLOAD, CLIENT_BYTES_IN, CLIENT_BYTES_OUT, CLIENT_MESSAGES_IN, CLIENT_MESSAGES_OUT, CLIENT_RTT,
+	CLIENT_SNAPSHOT_AGE,
+};
+use crate::network::helper::ClientSn;
+
+/// Network statistics drawn over the game, toggled with F3.
+pub struct NetOverlayPlugin;
+
+impl Plugin for NetOverlayPlugin {
+	fn build(&self, app: &mut App) {
+		app.add_systems(Startup, spawn_net_overlay)
+			.add_systems(Update, (toggle_net_overlay, update_net_overlay).chain());
+	}
+}
+
+const OVERLAY_FONT_SIZE: f32 = 16.0;
+/// Busiest channels listed in the overlay.
+const OVERLAY_CHANNELS: usize = 5;
+/// Gap between the overlay and the screen edge, and the minimap it sits on top of.
+const OVERLAY_MARGIN: f32 = 10.0;
+
+#[derive(Component)]
+struct NetOverlay;
+
+fn spawn_net_overlay(mut commands: Commands) {
+	commands
+		.spawn((
+			NodeBundle {
+				style: Style {
+					position_type: PositionType::Absolute,
+					// Stacked on the minimap, the kill feed grows down from the top right corner
+					right: Val::Px(OVERLAY_MARGIN),
+					bottom: Val::Px(MINIMAP_SIZE + 2. * OVERLAY_MARGIN),
+					padding: UiRect::all(Val::Px(6.)),
+					..default()
+				},
+				background_color: Color::rgba(0., 0., 0., 0.6).into(),
+				visibility: Visibility::Hidden,
+				..default()
+			},
+			NetOverlay,
+		))
+		.with_children(|parent| {
+			parent.spawn(TextBundle::from_section(
+				"",
+				TextStyle {
+					font_size: OVERLAY_FONT_SIZE,
+					color: Color::WHITE,
+					..default()
+				},
+			));
+		});
+}
+
+fn toggle_net_overlay(keys: Res<Input<KeyCode>>, mut overlay_query: Query<&mut Visibility, With<NetOverlay>>) {
+	if !keys.just_pressed(KeyCode::F3) {
+		return;
+	}
+
+	for mut visibility in &mut overlay_query {
+		*visibility = match *visibility {
+			Visibility::Hidden => Visibility::Inherited,
+			_ => Visibility::Hidden,
+		};
+	}
+}
+
+fn update_net_overlay(
+	diagnostics: Res<DiagnosticsStore>,
+	channel_manager: Res<ChannelManager>,
+	client: Option<Res<ClientSn>>,
+	overlay_query: Query<(&Visibility, &Children), With<NetOverlay>>,
+	mut text_query: Query<&mut Text>,
+) {
+	let Ok((visibility, children)) = overlay_query.get_single() else {
+		return;
+	};
+	if *visibility == Visibility::Hidden {
+		return;
+	}
+	let Some(mut text) = children.first().and_then(|child| text_query.get_mut(*child).ok()) else {
+		return;
+	};
+
+	let value = |id: DiagnosticId| {
+		diagnostics
+			.get(id)
+			.and_then(|diagnostic| diagnostic.smoothed())
+			.map_or(String::from("-"), |value| format!("{value:.0}"))
+	};
+
+	let mut lines = vec![
+		format!("fps {}", value(FrameTimeDiagnosticsPlugin::FPS)),
+		format!("rtt {} ms", value(CLIENT_RTT)),
+		format!("snapshot age {} ms", value(CLIENT_SNAPSHOT_AGE)),
+		format!("in {} B/s, {} msg/s", value(CLIENT_BYTES_IN), value(CLIENT_MESSAGES_IN)),
+		format!("out {} B/s, {} msg/s", value(CLIENT_BYTES_OUT), value(CLIENT_MESSAGES_OUT)),
+		format!("avg payload {} B", value(CLIENT_AVERAGE_PAYLOAD)),
+	];
+
+	if let Some(client) = client {
+		let mut channels: Vec<_> = client.stats.channels.iter().collect();
+		channels.sort_by_key(|(_, traffic)| std::cmp::Reverse(traffic.bytes_in + traffic.bytes_out));
+
+		for (channel_id, traffic) in channels.into_iter().take(OVERLAY_CHANNELS) {
+			let key = channel_manager
+				.server_channel(*channel_id)
+				.or_else(|| channel_manager.client_channel(*channel_id))
+				.map_or("?", |config| config.key);
+			let key = key.rsplit("::").next().unwrap_or(key);
+
+			lines.push(format!(
+				"{key}: {} / {} msgs, {} / {} B",
+				traffic.messages_in, traffic.messages_out, traffic.bytes_in, traffic.bytes_out
+			));
+		}
+	}
+
+	text.sections[0].value = lines.join("\n");
+}
@@ -1,4 +1,7 @@
+use std::collections::BTreeMap;
+
 use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
 
 use super::helper::ChannelId;
 
@@ -6,109 +9,243 @@ use super::helper::ChannelId;
     
 // }
 
-/// ID of the server replication channel.
-///
-/// See also [`NetworkChannels`].
+// ID of the server replication channel.
+//
+// See also [`NetworkChannels`].
 // pub const REPLICATION_CHANNEL_ID: u8 = 0;
 
-pub const CONNECTION_EVENT_CHANNEL_ID: ChannelId = 2;
+/// Default for [`ChannelConfig::max_bytes`], comfortably above anything we send today.
+pub const DEFAULT_MAX_BYTES: usize = 64 * 1024;
+
+/// How the message layer treats queued messages on a channel.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum DeliveryMode {
+	/// Every message is delivered, in the order it was sent. For one-off events like deaths.
+	ReliableOrdered,
+	/// Only the newest message matters. Older ones still waiting to be sent or read are dropped
+	/// instead of queued, for state that is resent continuously like inputs and snapshots.
+	LatestOnly,
+}
 
-/// A resource to configure and setup channels for [`ConnectionConfig`](bevy_renet::renet::ConnectionConfig)
-#[derive(Clone, Resource)]
-pub struct ChannelManager {
-	// Stores delivery guarantee and maximum usage bytes (if set) for each server channel.
-	// server: Vec<Option<usize>>,
-	server: u8,
-
-	// Same as [`Self::server`], but for client.
-	// client: Vec<(SendType, Option<usize>)>,
-	client: u8,
-	// Stores default max memory usage bytes for all channels.
-	//
-	// This value will be used instead of `None`.
-	// default_max_bytes: usize,
+/// Settings for a single event channel.
+#[derive(Clone, Copy, Debug)]
+pub struct ChannelConfig {
+	/// Hashed into the channel id, see [`ChannelManager`].
+	pub key: &'static str,
+	pub delivery: DeliveryMode,
+	/// Messages whose payload is larger than this are dropped on both ends.
+	pub max_bytes: usize,
+	/// Messages per second the server accepts from each client on a client channel, see
+	/// [`RateLimiter`](super::violations::RateLimiter). Not part of the channel table hash.
+	pub rate_limit: Option<f32>,
 }
 
-/// Stores only replication channel by default.
-impl Default for ChannelManager {
-	fn default() -> Self {
+impl ChannelConfig {
+	/// Reliable ordered channel with an explicit key that survives renaming or moving the event.
+	pub fn new(key: &'static str) -> Self {
 		Self {
-			server: 1,
-			client: 1,
-			// default_max_bytes: 5 * 1024 * 1024, // Value from `DefaultChannel::config()`.
+			key,
+			delivery: DeliveryMode::ReliableOrdered,
+			max_bytes: DEFAULT_MAX_BYTES,
+			rate_limit: None,
 		}
 	}
+
+	/// Reliable ordered channel keyed by `T`'s [`TypePath`], which unlike
+	/// `std::any::type_name` is the same for every compiler.
+	pub fn for_event<T: TypePath>() -> Self {
+		Self::new(T::type_path())
+	}
+
+	pub fn latest_only(mut self) -> Self {
+		self.delivery = DeliveryMode::LatestOnly;
+		self
+	}
+
+	pub fn with_max_bytes(mut self, max_bytes: usize) -> Self {
+		self.max_bytes = max_bytes;
+		self
+	}
+
+	pub fn with_rate_limit(mut self, per_second: f32) -> Self {
+		self.rate_limit = Some(per_second);
+		self
+	}
 }
 
-impl ChannelManager {
-	// /// Returns server channel configs that can be used to create [`ConnectionConfig`](bevy_renet::renet::ConnectionConfig).
-	// pub fn get_server_configs(&self) -> Vec<ChannelConfig> {
-	//     self.get_configs(&self.server)
-	// }
-
-	// /// Same as [`Self::get_server_configs`], but for client.
-	// pub fn get_client_configs(&self) -> Vec<ChannelConfig> {
-	//     self.get_configs(&self.client)
-	// }
-
-	// /// Sets maximum usage bytes for specific client channel.
-	// ///
-	// /// [`REPLICATION_CHANNEL_ID`] or [`EventChannel<T>`](crate::network_event::EventChannel) can be passed as `id`.
-	// /// Without calling this function, the default value will be used.
-	// /// See also [`Self::set_default_max_bytes`].
-	// pub fn set_server_max_bytes(&mut self, id: impl Into<u8>, max_bytes: usize) {
-	//     let id = id.into() as usize;
-	//     let (_, bytes) = self
-	//         .server
-	//         .get_mut(id)
-	//         .unwrap_or_else(|| panic!("there is no server channel with id {id}"));
-
-	//     *bytes = Some(max_bytes);
-	// }
-
-	// /// Same as [`Self::set_server_max_bytes`], but for client.
-	// pub fn set_client_max_bytes(&mut self, id: impl Into<u8>, max_bytes: usize) {
-	//     let id = id.into();
-	//     let (_, bytes) = self
-	//         .client
-	//         .get_mut(id as usize)
-	//         .unwrap_or_else(|| panic!("there is no client channel with id {id}"));
-
-	//     *bytes = Some(max_bytes);
-	// }
-
-	// /// Sets maximum usage bytes that will be used by default for all channels if not set.
-	// pub fn set_default_max_bytes(&mut self, max_bytes: usize) {
-	//     self.default_max_bytes = max_bytes;
-	// }
-
-	pub(super) fn create_client_channel(&mut self) -> u8 {
-		if self.client == u8::MAX {
-			panic!("number of client channels shouldn't exceed u8::MAX");
+/// A resource holding the [`ChannelConfig`] of every registered event channel.
+///
+/// Channel IDs are hashed from each event's key (its type path unless registered with an explicit
+/// key), so client and server agree on them no matter the order events are registered in.
+#[derive(Clone, Resource, Default)]
+pub struct ChannelManager {
+	/// Config of the event registered on each server channel.
+	server: BTreeMap<ChannelId, ChannelConfig>,
+
+	/// Same as [`Self::server`], but for client.
+	client: BTreeMap<ChannelId, ChannelConfig>,
+}
+
+/// One registered channel as the other end of the connect handshake sees it.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct ChannelFingerprint {
+	/// `server` or `client`, then the event key.
+	pub name: String,
+	/// Hash of the channel's id and settings.
+	pub settings: u64,
+}
+
+/// How a client's channels differ from the server's, see [`ChannelManager::compare`].
+#[derive(Serialize, Deserialize, Clone, Debug, Default, Hash, PartialEq, Eq)]
+pub struct ChannelDiff {
+	/// Registered on the server only.
+	pub missing: Vec<String>,
+	/// Registered on the client only.
+	pub unknown: Vec<String>,
+	/// Registered on both, with different settings.
+	pub different: Vec<String>,
+}
+
+impl ChannelDiff {
+	pub fn is_empty(&self) -> bool {
+		self.missing.is_empty() && self.unknown.is_empty() && self.different.is_empty()
+	}
+}
+
+impl std::fmt::Display for ChannelDiff {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		let sections = [
+			("missing", &self.missing),
+			("unknown to the server", &self.unknown),
+			("different", &self.different),
+		];
+
+		let mut first = true;
+		for (label, names) in sections.into_iter().filter(|(_, names)| !names.is_empty()) {
+			if !first {
+				write!(f, "; ")?;
+			}
+			first = false;
+			write!(f, "{label}: {}", names.join(", "))?;
+		}
+
+		Ok(())
+	}
+}
+
+/// 32 bit FNV-1a, stable across builds and targets unlike `DefaultHasher`.
+fn fnv1a_32(bytes: &[u8]) -> u32 {
+	const FNV_OFFSET: u32 = 0x811c9dc5;
+	const FNV_PRIME: u32 = 0x01000193;
+
+	bytes.iter().fold(FNV_OFFSET, |hash, byte| (hash ^ *byte as u32).wrapping_mul(FNV_PRIME))
+}
+
+/// 64 bit FNV-1a, see [`fnv1a_32`].
+fn fnv1a_64(bytes: &[u8]) -> u64 {
+	const FNV_OFFSET: u64 = 0xcbf29ce484222325;
+	const FNV_PRIME: u64 = 0x100000001b3;
+
+	bytes.iter().fold(FNV_OFFSET, |hash, byte| (hash ^ *byte as u64).wrapping_mul(FNV_PRIME))
+}
+
+/// Registers `config` in `channels`, panicking if its key is already there or hashes onto another key.
+fn create_channel(
+	channels: &mut BTreeMap<ChannelId, ChannelConfig>,
+	side: &str,
+	config: ChannelConfig,
+) -> ChannelId {
+	let key = config.key;
+	let channel_id = fnv1a_32(key.as_bytes());
+
+	match channels.get(&channel_id).map(|existing| existing.key) {
+		Some(existing) if existing == key => {
+			panic!("{side} event `{key}` is registered twice");
+		}
+		Some(existing) => panic!(
+			"{side} events `{existing}` and `{key}` both hash to channel {channel_id:08x}, \
+			 register one of them with an explicit key"
+		),
+		None => {
+			channels.insert(channel_id, config);
 		}
+	}
+
+	channel_id
+}
+
+impl ChannelManager {
+	/// Config of a channel registered with `add_client_event`.
+	pub fn client_channel(&self, channel_id: ChannelId) -> Option<&ChannelConfig> {
+		self.client.get(&channel_id)
+	}
+
+	/// Config of a channel registered with `add_server_event`.
+	pub fn server_channel(&self, channel_id: ChannelId) -> Option<&ChannelConfig> {
+		self.server.get(&channel_id)
+	}
+
+	pub(super) fn create_client_channel(&mut self, config: ChannelConfig) -> ChannelId {
+		create_channel(&mut self.client, "client", config)
+	}
+
+	pub(super) fn create_server_channel(&mut self, config: ChannelConfig) -> ChannelId {
+		create_channel(&mut self.server, "server", config)
+	}
 
-		self.client += 1;
-		self.client
+	/// Every registered channel with a hash of its settings, exchanged in the connect handshake
+	/// so mismatched builds are turned away instead of decoding garbage.
+	pub fn fingerprints(&self) -> Vec<ChannelFingerprint> {
+		self.channels()
+			.map(|(side, channel_id, config)| {
+				let ChannelConfig { key, delivery, max_bytes, .. } = config;
+				ChannelFingerprint {
+					name: format!("{side} {key}"),
+					settings: fnv1a_64(format!("{channel_id:08x} {delivery:?} {max_bytes}").as_bytes()),
+				}
+			})
+			.collect()
 	}
 
-	pub(super) fn create_server_channel(&mut self) -> u8 {
-		if self.server == u8::MAX {
-			panic!("number of server channels shouldn't exceed u8::MAX");
+	/// Which channels of a client with `fingerprints` don't match ours.
+	pub fn compare(&self, fingerprints: &[ChannelFingerprint]) -> ChannelDiff {
+		let ours = self.fingerprints();
+		let mut diff = ChannelDiff::default();
+
+		for fingerprint in &ours {
+			match fingerprints.iter().find(|theirs| theirs.name == fingerprint.name) {
+				None => diff.missing.push(fingerprint.name.clone()),
+				Some(theirs) if theirs.settings != fingerprint.settings => {
+					diff.different.push(fingerprint.name.clone())
+				}
+				Some(_) => {}
+			}
 		}
 
-		self.server += 1;
-		self.server
-	}
-
-	// fn get_configs(&self, channels: &[(SendType, Option<usize>)]) -> Vec<ChannelConfig> {
-	//     let mut channel_configs = Vec::with_capacity(channels.len());
-	//     for (index, (send_type, max_bytes)) in channels.iter().enumerate() {
-	//         channel_configs.push(ChannelConfig {
-	//             channel_id: index as u8,
-	//             max_memory_usage_bytes: max_bytes.unwrap_or(self.default_max_bytes),
-	//             send_type: send_type.clone(),
-	//         });
-	//     }
-	//     channel_configs
-	// }
+		diff.unknown = fingerprints
+			.iter()
+			.filter(|theirs| !ours.iter().any(|fingerprint| fingerprint.name == theirs.name))
+			.map(|theirs| theirs.name.clone())
+			.collect();
+
+		diff
+	}
+
+	/// Human readable listing of every channel, one per line, sorted by id.
+	pub fn channel_table(&self) -> String {
+		self.channels()
+			.map(|(side, channel_id, config)| {
+				let ChannelConfig { key, delivery, max_bytes, .. } = config;
+				format!("{side} {channel_id:08x} {delivery:?} {max_bytes} {key}\n")
+			})
+			.collect()
+	}
+
+	/// Server channels, then client channels, each sorted by id.
+	fn channels(&self) -> impl Iterator<Item = (&'static str, &ChannelId, &ChannelConfig)> {
+		let server = self.server.iter().map(|(channel_id, config)| ("server", channel_id, config));
+		let client = self.client.iter().map(|(channel_id, config)| ("client", channel_id, config));
+
+		server.chain(client)
+	}
 }
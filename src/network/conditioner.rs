@@ -0,0 +1,273 @@
+use std::time::Duration;
+
+use bevy::prelude::*;
+
+use super::helper::{ClientMsg, MessageBatch, ServerMsg};
+#[cfg(feature = "server")]
+use super::helper::ClientId;
+use crate::Cli;
+
+/// Fake network conditions applied to one direction of traffic.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct LinkConditions {
+	pub latency: Duration,
+	/// Random extra delay, anywhere between zero and this.
+	pub jitter: Duration,
+	/// Chance for a batch to be lost on the wire, from 0 to 1. The websocket underneath
+	/// retransmits it, so it only arrives late and holds back every batch behind it.
+	pub loss: f64,
+	/// Chance for a batch to be held back so the ones after it overtake it, from 0 to 1.
+	pub reorder: f64,
+}
+
+impl LinkConditions {
+	/// `true` if these conditions would leave traffic untouched.
+	pub fn is_ideal(&self) -> bool {
+		*self == Self::default()
+	}
+}
+
+/// Parses `latency=100,jitter=20,loss=0.05,reorder=0.1`, times in milliseconds.
+/// Every key is optional.
+pub fn parse_link_conditions(conditions: &str) -> Result<LinkConditions, String> {
+	let mut parsed = LinkConditions::default();
+
+	for pair in conditions.split(',').filter(|pair| !pair.trim().is_empty()) {
+		let (key, value) = pair
+			.split_once('=')
+			.ok_or_else(|| format!("expected key=value, got `{pair}`"))?;
+		let value: f64 = value
+			.trim()
+			.parse()
+			.map_err(|error| format!("invalid value for `{key}`: {error}"))?;
+		if !value.is_finite() {
+			return Err(format!("`{key}` should be a finite number"));
+		}
+
+		match key.trim() {
+			"latency" | "jitter" if value < 0.0 => return Err(format!("`{key}` can't be negative")),
+			"loss" | "reorder" if !(0.0..=1.0).contains(&value) => {
+				return Err(format!("`{key}` should be between 0 and 1"))
+			}
+			"latency" => parsed.latency = parse_delay(key, value)?,
+			"jitter" => parsed.jitter = parse_delay(key, value)?,
+			"loss" => parsed.loss = value,
+			"reorder" => parsed.reorder = value,
+			key => return Err(format!("unknown key `{key}`, expected latency, jitter, loss or reorder")),
+		}
+	}
+
+	Ok(parsed)
+}
+
+/// Longest latency or jitter, anything longer is surely a typo and would overflow the due times.
+const MAX_LINK_DELAY: Duration = Duration::from_secs(60);
+
+/// Converts a delay in milliseconds, `value` is already known to be finite and non-negative.
+fn parse_delay(key: &str, value: f64) -> Result<Duration, String> {
+	Duration::try_from_secs_f64(value / 1000.0)
+		.ok()
+		.filter(|delay| *delay <= MAX_LINK_DELAY)
+		.ok_or_else(|| format!("`{key}` should be at most {} ms", MAX_LINK_DELAY.as_millis()))
+}
+
+/// Shortest hold for a reordered batch, so it gets overtaken even without latency.
+const MIN_REORDER_HOLD: Duration = Duration::from_millis(20);
+/// Shortest time before a lost batch is sent again, TCP's minimum retransmission timeout.
+const MIN_RETRANSMIT_DELAY: Duration = Duration::from_millis(200);
+
+/// Delays, loses and reorders items travelling in one direction.
+#[derive(Debug)]
+pub struct LinkSimulator<T> {
+	pub conditions: LinkConditions,
+	/// Items with the time they are due, in arrival order.
+	in_flight: Vec<(Duration, T)>,
+	/// When the last lost item is retransmitted, nothing sent after it arrives earlier.
+	blocked_until: Duration,
+}
+
+impl<T> LinkSimulator<T> {
+	pub fn new(conditions: LinkConditions) -> Self {
+		Self {
+			conditions,
+			in_flight: Vec::new(),
+			blocked_until: Duration::ZERO,
+		}
+	}
+
+	/// Puts `item` on the simulated wire, `now` being the real time since startup.
+	///
+	/// Nothing is ever dropped: batches mix reliable channels with latest-only ones, and the
+	/// real transport doesn't lose either.
+	pub fn send(&mut self, now: Duration, item: T) {
+		let LinkConditions { latency, jitter, loss, reorder } = self.conditions;
+
+		let mut due = now + latency + jitter.mul_f64(rand::random::<f64>());
+		if rand::random::<f64>() < loss {
+			// The sender notices after a round trip at the earliest
+			due += (latency * 2).max(MIN_RETRANSMIT_DELAY);
+			self.blocked_until = self.blocked_until.max(due);
+		}
+		due = due.max(self.blocked_until);
+
+		if rand::random::<f64>() < reorder {
+			due += latency.max(MIN_REORDER_HOLD);
+		}
+
+		self.in_flight.push((due, item));
+	}
+
+	/// Takes every item that is due by `now`, earliest first.
+	pub fn receive(&mut self, now: Duration) -> Vec<T> {
+		let (mut arrived, in_flight): (Vec<_>, Vec<_>) =
+			self.in_flight.drain(..).partition(|(due, _)| *due <= now);
+		self.in_flight = in_flight;
+
+		arrived.sort_by_key(|(due, _)| *due);
+		arrived.into_iter().map(|(_, item)| item).collect()
+	}
+
+	/// Takes everything still in flight, used when the simulator is switched off.
+	pub fn drain(&mut self) -> Vec<T> {
+		self.receive(Duration::MAX)
+	}
+}
+
+/// Network simulation for the client's connection, see [`Cli::sim_inbound`] and [`Cli::sim_outbound`].
+///
+/// Toggled at runtime with F9.
+#[derive(Resource)]
+pub struct ClientConditioner {
+	pub enabled: bool,
+	pub inbound: LinkSimulator<MessageBatch<ServerMsg>>,
+	pub outbound: LinkSimulator<MessageBatch<ClientMsg>>,
+}
+
+impl FromWorld for ClientConditioner {
+	fn from_world(world: &mut World) -> Self {
+		let cli = world.resource::<Cli>();
+		Self {
+			enabled: !(cli.sim_inbound.is_ideal() && cli.sim_outbound.is_ideal()),
+			inbound: LinkSimulator::new(cli.sim_inbound),
+			outbound: LinkSimulator::new(cli.sim_outbound),
+		}
+	}
+}
+
+/// Same as [`ClientConditioner`], but for every connection on the server.
+#[cfg(feature = "server")]
+#[derive(Resource)]
+pub struct ServerConditioner {
+	pub enabled: bool,
+	pub inbound: LinkSimulator<(ClientId, MessageBatch<ClientMsg>)>,
+	pub outbound: LinkSimulator<(ClientId, MessageBatch<ServerMsg>)>,
+}
+
+#[cfg(feature = "server")]
+impl FromWorld for ServerConditioner {
+	fn from_world(world: &mut World) -> Self {
+		let cli = world.resource::<Cli>();
+
+		// Offline play has both ends of the one link in this process, the client's conditioner
+		// already simulates it
+		#[cfg(feature = "client")]
+		if cli.offline {
+			return Self {
+				enabled: false,
+				inbound: LinkSimulator::new(LinkConditions::default()),
+				outbound: LinkSimulator::new(LinkConditions::default()),
+			};
+		}
+
+		Self {
+			enabled: !(cli.sim_inbound.is_ideal() && cli.sim_outbound.is_ideal()),
+			inbound: LinkSimulator::new(cli.sim_inbound),
+			outbound: LinkSimulator::new(cli.sim_outbound),
+		}
+	}
+}
+
+/// Switches the network simulation on and off with F9, on both ends when hosting.
+pub fn toggle_conditioner(
+	keys: Res<Input<KeyCode>>,
+	client: Option<ResMut<ClientConditioner>>,
+	#[cfg(feature = "server")] server: Option<ResMut<ServerConditioner>>,
+) {
+	if !keys.just_pressed(KeyCode::F9) {
+		return;
+	}
+
+	let mut enabled = None;
+	if let Some(mut client) = client {
+		client.enabled = !client.enabled;
+		enabled = Some(client.enabled);
+	}
+	#[cfg(feature = "server")]
+	if let Some(mut server) = server {
+		server.enabled = enabled.unwrap_or(!server.enabled);
+		enabled = Some(server.enabled);
+	}
+
+	if let Some(enabled) = enabled {
+		info!("network simulation {}", if enabled { "enabled" } else { "disabled" });
+	}
+}
+
+#[cfg(all(test, feature = "server"))]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn lost_items_arrive_late_and_in_order() {
+		let mut link = LinkSimulator::new(LinkConditions {
+			latency: Duration::from_millis(50),
+			loss: 1.0,
+			..default()
+		});
+		link.send(Duration::ZERO, 1);
+		link.conditions.loss = 0.0;
+		link.send(Duration::from_millis(10), 2);
+
+		assert!(link.receive(Duration::from_millis(100)).is_empty());
+		assert_eq!(link.receive(Duration::from_millis(250)), vec![1, 2]);
+	}
+
+	#[test]
+	fn parses_link_conditions() {
+		assert_eq!(parse_link_conditions(""), Ok(LinkConditions::default()));
+		assert_eq!(
+			parse_link_conditions("latency=100, jitter=20,loss=0.05,reorder=0.1"),
+			Ok(LinkConditions {
+				latency: Duration::from_millis(100),
+				jitter: Duration::from_millis(20),
+				loss: 0.05,
+				reorder: 0.1,
+			})
+		);
+		assert_eq!(
+			parse_link_conditions("loss=1,"),
+			Ok(LinkConditions {
+				loss: 1.0,
+				..default()
+			})
+		);
+	}
+
+	#[test]
+	fn rejects_invalid_link_conditions() {
+		assert!(parse_link_conditions("latency").is_err());
+		assert!(parse_link_conditions("latency=fast").is_err());
+		assert!(parse_link_conditions("latency=-5").is_err());
+		assert!(parse_link_conditions("loss=1.5").is_err());
+		assert!(parse_link_conditions("bandwidth=10").is_err());
+	}
+
+	#[test]
+	fn rejects_non_finite_and_huge_link_conditions() {
+		assert!(parse_link_conditions("latency=nan").is_err());
+		assert!(parse_link_conditions("jitter=inf").is_err());
+		assert!(parse_link_conditions("loss=NaN").is_err());
+		assert!(parse_link_conditions("latency=1e300").is_err());
+		assert!(parse_link_conditions("jitter=1e300").is_err());
+	}
+}
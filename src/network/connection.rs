@@ -0,0 +1,464 @@
+use std::time::Duration;
+
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use super::helper::ClientId;
+
+#[cfg(feature = "client")]
+use super::helper::{connect_client, ClientSn, ServerUrl};
+#[cfg(feature = "client")]
+use super::InternalConnectionEvent;
+#[cfg(feature = "client")]
+use crate::{Cli, ClientIdResource};
+
+#[cfg(feature = "server")]
+use super::helper::{ConnectMsg, ServerSn, PROTOCOL_VERSION, SERVER_ID};
+#[cfg(feature = "server")]
+use bevy::utils::HashMap;
+use super::channels_config::{ChannelDiff, ChannelManager};
+
+/// Secret handed out by the server so a client can reclaim its ship after a dropped connection.
+pub type SessionToken = u128;
+
+/// Sent directly to a client once the server accepted its connection.
+#[derive(Event, TypePath, Serialize, Deserialize, Clone, Copy, Debug)]
+pub struct SessionAssigned(pub SessionToken);
+
+/// Token the client presents when it reconnects, assigned by the server via [`SessionAssigned`].
+#[derive(Resource, Clone, Copy, Debug)]
+pub struct ClientSession(pub SessionToken);
+
+#[derive(States, Default, Clone, Debug, Hash, PartialEq, Eq)]
+pub enum ClientConnectionState {
+	/// Not connected to any server yet, e.g. while picking one in the server browser.
+	#[default]
+	Idle,
+	Connecting,
+	Connected,
+	/// Lost the connection and trying to get it back, see [`ReconnectBackoff`].
+	Reconnecting,
+	Disconnected(DisconnectReason),
+}
+
+#[derive(Clone, Debug, Hash, PartialEq, Eq)]
+pub enum DisconnectReason {
+	/// The server closed the connection, e.g. because we were kicked.
+	ClosedByServer,
+	/// The server refused the connect handshake.
+	Rejected(RejectReason),
+	/// Ran out of reconnect attempts.
+	GaveUp,
+}
+
+/// Why the server turned a connecting client away.
+#[derive(Serialize, Deserialize, Clone, Debug, Hash, PartialEq, Eq)]
+pub enum RejectReason {
+	ProtocolMismatch { server: u32, client: u32 },
+	/// Client and server registered different events with `add_client_event`/`add_server_event`.
+	ChannelMismatch(ChannelDiff),
+	/// Another connection owns this client id.
+	IdInUse,
+	/// Asked for [`SERVER_ID`](super::helper::SERVER_ID), which belongs to a listen server's own player.
+	ReservedId,
+	/// Removed from the match, e.g. after too many [`Violation`](super::violations::Violation)s.
+	Kicked,
+	/// Not allowed back on this server until it restarts.
+	Banned,
+}
+
+impl std::fmt::Display for RejectReason {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		match self {
+			RejectReason::ProtocolMismatch { server, client } => write!(
+				f,
+				"protocol version mismatch (server {server}, client {client}), please update your game"
+			),
+			RejectReason::ChannelMismatch(diff) => write!(
+				f,
+				"network event table mismatch, client and server were built differently ({diff})"
+			),
+			RejectReason::IdInUse => write!(f, "this client id is already in use"),
+			RejectReason::ReservedId => write!(f, "this client id is reserved for the host"),
+			RejectReason::Kicked => write!(f, "kicked by the server"),
+			RejectReason::Banned => write!(f, "banned from this server"),
+		}
+	}
+}
+
+/// Sent directly to a client right before the server closes its connection.
+#[derive(Event, TypePath, Serialize, Deserialize, Clone, Debug)]
+pub struct ConnectionRejected(pub RejectReason);
+
+/// Checks a connecting client's handshake against our own build.
+#[cfg(feature = "server")]
+pub fn validate_connect_msg(
+	client_id: ClientId,
+	connect_msg: &ConnectMsg,
+	channel_manager: &ChannelManager,
+) -> Result<(), RejectReason> {
+	// Clients pick their own id, and this one would get host treatment everywhere
+	if client_id == SERVER_ID {
+		return Err(RejectReason::ReservedId);
+	}
+
+	if connect_msg.protocol_version != PROTOCOL_VERSION {
+		return Err(RejectReason::ProtocolMismatch {
+			server: PROTOCOL_VERSION,
+			client: connect_msg.protocol_version,
+		});
+	}
+
+	let diff = channel_manager.compare(&connect_msg.channels);
+	if !diff.is_empty() {
+		return Err(RejectReason::ChannelMismatch(diff));
+	}
+
+	Ok(())
+}
+
+pub fn apply_connection_rejected(
+	mut rejected_events: EventReader<ConnectionRejected>,
+	mut next_state: Option<ResMut<NextState<ClientConnectionState>>>,
+) {
+	if let Some(ConnectionRejected(reason)) = rejected_events.read().last() {
+		error!("server rejected connection: {reason}");
+
+		if let Some(next_state) = &mut next_state {
+			next_state.set(match reason {
+				// Our old session is still around and expires soon, keep trying until it does
+				RejectReason::IdInUse => ClientConnectionState::Reconnecting,
+				reason => ClientConnectionState::Disconnected(DisconnectReason::Rejected(reason.clone())),
+			});
+		}
+	}
+}
+
+/// Reconnect attempts before the client gives up.
+#[cfg(feature = "client")]
+const MAX_RECONNECT_ATTEMPTS: u32 = 8;
+const INITIAL_RECONNECT_DELAY: Duration = Duration::from_millis(500);
+#[cfg_attr(not(feature = "client"), allow(dead_code))]
+const MAX_RECONNECT_DELAY: Duration = Duration::from_secs(15);
+
+/// Exponential backoff between reconnect attempts.
+#[derive(Resource, Debug)]
+pub struct ReconnectBackoff {
+	pub attempt: u32,
+	#[cfg_attr(not(feature = "client"), allow(dead_code))]
+	timer: Timer,
+}
+
+impl Default for ReconnectBackoff {
+	fn default() -> Self {
+		Self {
+			attempt: 0,
+			timer: Timer::new(INITIAL_RECONNECT_DELAY, TimerMode::Once),
+		}
+	}
+}
+
+impl ReconnectBackoff {
+	#[cfg_attr(not(feature = "client"), allow(dead_code))]
+	fn schedule_next(&mut self) {
+		self.attempt += 1;
+		let delay = INITIAL_RECONNECT_DELAY
+			.saturating_mul(2u32.saturating_pow(self.attempt - 1))
+			.min(MAX_RECONNECT_DELAY);
+		self.timer = Timer::new(delay, TimerMode::Once);
+	}
+}
+
+pub fn apply_session_assigned(
+	mut commands: Commands,
+	mut session_events: EventReader<SessionAssigned>,
+) {
+	if let Some(SessionAssigned(token)) = session_events.read().last() {
+		commands.insert_resource(ClientSession(*token));
+	}
+}
+
+/// Rebuilds the websocket client once the old one died and the backoff elapsed.
+#[cfg(feature = "client")]
+#[allow(clippy::too_many_arguments)]
+pub fn reconnect_system(
+	mut commands: Commands,
+	time: Res<Time>,
+	cli: Res<Cli>,
+	channel_manager: Res<ChannelManager>,
+	client: Res<ClientSn>,
+	client_id: Res<ClientIdResource>,
+	server_url: Res<ServerUrl>,
+	session: Option<Res<ClientSession>>,
+	mut backoff: ResMut<ReconnectBackoff>,
+	mut next_state: ResMut<NextState<ClientConnectionState>>,
+) {
+	if !client.transport.is_dead() || !backoff.timer.tick(time.delta()).finished() {
+		return;
+	}
+
+	if backoff.attempt >= MAX_RECONNECT_ATTEMPTS {
+		warn!("giving up after {} reconnect attempts", backoff.attempt);
+		next_state.set(ClientConnectionState::Disconnected(DisconnectReason::GaveUp));
+		return;
+	}
+
+	backoff.schedule_next();
+	info!("reconnect attempt {}", backoff.attempt);
+
+	commands.insert_resource(connect_client(
+		client_id.0,
+		&server_url.0,
+		&cli,
+		&channel_manager,
+		session.map(|session| session.0),
+	));
+}
+
+/// Starts the backoff over once the server accepted us rather than when the socket opens, so
+/// being turned away with [`RejectReason::IdInUse`] still counts as a failed attempt.
+#[cfg(feature = "client")]
+pub fn reset_backoff(
+	mut connection_events: EventReader<InternalConnectionEvent>,
+	client_id: Res<ClientIdResource>,
+	mut backoff: ResMut<ReconnectBackoff>,
+) {
+	let accepted = connection_events
+		.read()
+		.any(|event| matches!(event, InternalConnectionEvent::Connected(connected) if *connected == client_id.0));
+
+	if accepted {
+		*backoff = ReconnectBackoff::default();
+	}
+}
+
+/// How long a disconnected player's ship waits for its owner to come back.
+#[cfg(feature = "server")]
+pub const SESSION_GRACE_PERIOD: Duration = Duration::from_secs(30);
+
+#[cfg(feature = "server")]
+#[derive(Debug)]
+pub struct Session {
+	pub token: SessionToken,
+	/// Counts down while the client is disconnected, `None` while connected.
+	pub expires_in: Option<Timer>,
+	/// Set when the client was kicked, the token can't be used to come back then.
+	pub revoked: bool,
+}
+
+/// Server side record of every client that still owns a ship.
+#[cfg(feature = "server")]
+#[derive(Resource, Default, Debug)]
+pub struct Sessions(pub HashMap<ClientId, Session>);
+
+/// What the server decided to do with a connecting client.
+#[cfg(feature = "server")]
+pub enum SessionOutcome {
+	/// First connection, the client gets a fresh token.
+	New(SessionToken),
+	/// The client presented the right token and takes back its ship.
+	Resumed,
+	/// Same as [`Self::Resumed`], but the old connection still looked alive, e.g. because it
+	/// dropped without the server noticing yet. The new connection replaces it.
+	TookOver,
+	/// Someone else already holds this client id.
+	Rejected,
+}
+
+#[cfg(feature = "server")]
+impl Sessions {
+	/// Claims `client_id` for a connecting client. An id can only be taken back with the token
+	/// it was issued, and not at all once its owner was kicked.
+	pub fn connect(&mut self, client_id: ClientId, token: Option<SessionToken>) -> SessionOutcome {
+		match self.0.get_mut(&client_id) {
+			Some(session) if token == Some(session.token) && !session.revoked => {
+				match session.expires_in.take() {
+					Some(_) => SessionOutcome::Resumed,
+					None => SessionOutcome::TookOver,
+				}
+			}
+			// A stolen id, or a client that never got its token
+			Some(_) => SessionOutcome::Rejected,
+			None => {
+				let token: SessionToken = rand::random();
+				self.0.insert(client_id, Session { token, expires_in: None, revoked: false });
+				SessionOutcome::New(token)
+			}
+		}
+	}
+
+	pub fn disconnect(&mut self, client_id: ClientId) {
+		if let Some(session) = self.0.get_mut(&client_id) {
+			let grace_period = if session.revoked { Duration::ZERO } else { SESSION_GRACE_PERIOD };
+			session.expires_in = Some(Timer::new(grace_period, TimerMode::Once));
+		}
+	}
+
+	/// Ends the session of a client that is being kicked, it expires as soon as the connection
+	/// is gone instead of waiting for the client to come back.
+	pub fn revoke(&mut self, client_id: ClientId) {
+		if let Some(session) = self.0.get_mut(&client_id) {
+			session.revoked = true;
+			if session.expires_in.is_some() {
+				session.expires_in = Some(Timer::new(Duration::ZERO, TimerMode::Once));
+			}
+		}
+	}
+}
+
+/// Sent on the server when a disconnected client didn't come back in time.
+#[derive(Event, Debug, Clone, Copy)]
+pub struct EventSessionExpired(pub ClientId);
+
+#[cfg(feature = "server")]
+pub fn expire_sessions_system(
+	time: Res<Time>,
+	mut sessions: ResMut<Sessions>,
+	mut server: Option<ResMut<ServerSn>>,
+	mut expired_events: EventWriter<EventSessionExpired>,
+) {
+	sessions.0.retain(|client_id, session| {
+		let expired = session
+			.expires_in
+			.as_mut()
+			.is_some_and(|timer| timer.tick(time.delta()).finished());
+
+		if expired {
+			info!("session for client {client_id} expired");
+			// Teams outlive a dropped connection, but not the session
+			if let Some(server) = &mut server {
+				server.teams.remove(client_id);
+			}
+			expired_events.send(EventSessionExpired(*client_id));
+		}
+
+		!expired
+	});
+}
+
+#[cfg(all(test, feature = "server"))]
+mod tests {
+	use super::*;
+	use crate::network::channels_config::ChannelConfig;
+
+	fn connect_msg(channel_manager: &ChannelManager) -> ConnectMsg {
+		ConnectMsg {
+			protocol_version: PROTOCOL_VERSION,
+			channels: channel_manager.fingerprints(),
+			client_build: String::from("test"),
+			name: String::from("Pilot"),
+			session_token: None,
+		}
+	}
+
+	#[test]
+	fn matching_handshake_is_accepted() {
+		let channel_manager = ChannelManager::default();
+		assert_eq!(validate_connect_msg(42, &connect_msg(&channel_manager), &channel_manager), Ok(()));
+	}
+
+	#[test]
+	fn server_id_is_reserved() {
+		let channel_manager = ChannelManager::default();
+		assert_eq!(
+			validate_connect_msg(SERVER_ID, &connect_msg(&channel_manager), &channel_manager),
+			Err(RejectReason::ReservedId)
+		);
+	}
+
+	#[test]
+	fn protocol_mismatch_is_rejected() {
+		let channel_manager = ChannelManager::default();
+		let connect_msg = ConnectMsg {
+			protocol_version: PROTOCOL_VERSION + 1,
+			..connect_msg(&channel_manager)
+		};
+
+		assert_eq!(
+			validate_connect_msg(42, &connect_msg, &channel_manager),
+			Err(RejectReason::ProtocolMismatch {
+				server: PROTOCOL_VERSION,
+				client: PROTOCOL_VERSION + 1,
+			})
+		);
+	}
+
+	#[test]
+	fn channel_mismatch_names_the_channels() {
+		let mut server_channels = ChannelManager::default();
+		server_channels.create_server_channel(ChannelConfig::new("shared"));
+		server_channels.create_server_channel(ChannelConfig::new("server_only"));
+		server_channels.create_client_channel(ChannelConfig::new("tuned"));
+
+		let mut client_channels = ChannelManager::default();
+		client_channels.create_server_channel(ChannelConfig::new("shared"));
+		client_channels.create_server_channel(ChannelConfig::new("client_only"));
+		client_channels.create_client_channel(ChannelConfig::new("tuned").latest_only());
+
+		assert_eq!(
+			validate_connect_msg(42, &connect_msg(&client_channels), &server_channels),
+			Err(RejectReason::ChannelMismatch(ChannelDiff {
+				missing: vec![String::from("server server_only")],
+				unknown: vec![String::from("server client_only")],
+				different: vec![String::from("client tuned")],
+			}))
+		);
+	}
+
+	#[test]
+	fn kicked_session_cannot_be_resumed() {
+		let mut sessions = Sessions::default();
+		let SessionOutcome::New(token) = sessions.connect(42, None) else {
+			panic!("first connection should get a new session");
+		};
+
+		sessions.revoke(42);
+		sessions.disconnect(42);
+
+		assert!(matches!(sessions.connect(42, Some(token)), SessionOutcome::Rejected));
+		assert!(sessions.0[&42].expires_in.as_ref().is_some_and(|timer| timer.duration().is_zero()));
+	}
+
+	#[test]
+	fn backoff_doubles_up_to_the_limit() {
+		let mut backoff = ReconnectBackoff::default();
+		let delays: Vec<Duration> = (0..7)
+			.map(|_| {
+				backoff.schedule_next();
+				backoff.timer.duration()
+			})
+			.collect();
+
+		assert_eq!(backoff.attempt, 7);
+		assert_eq!(
+			delays,
+			[500, 1000, 2000, 4000, 8000, 15000, 15000].map(Duration::from_millis).to_vec()
+		);
+	}
+
+	#[test]
+	fn session_resumes_with_its_token() {
+		let mut sessions = Sessions::default();
+		let SessionOutcome::New(token) = sessions.connect(42, None) else {
+			panic!("first connection should get a new session");
+		};
+
+		sessions.disconnect(42);
+		assert!(matches!(sessions.connect(42, Some(token.wrapping_add(1))), SessionOutcome::Rejected));
+		assert!(matches!(sessions.connect(42, None), SessionOutcome::Rejected));
+		assert!(matches!(sessions.connect(42, Some(token)), SessionOutcome::Resumed));
+		assert!(sessions.0[&42].expires_in.is_none());
+	}
+
+	#[test]
+	fn token_takes_over_a_live_session() {
+		let mut sessions = Sessions::default();
+		let SessionOutcome::New(token) = sessions.connect(42, None) else {
+			panic!("first connection should get a new session");
+		};
+
+		assert!(matches!(sessions.connect(42, None), SessionOutcome::Rejected));
+		assert!(matches!(sessions.connect(42, Some(token)), SessionOutcome::TookOver));
+		assert!(sessions.0[&42].expires_in.is_none());
+	}
+}
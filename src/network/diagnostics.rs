@@ -0,0 +1,402 @@
+use std::time::Duration;
+
+use bevy::diagnostic::{Diagnostic, DiagnosticId, Diagnostics, RegisterDiagnostic};
+use bevy::prelude::*;
+use bevy::utils::HashMap;
+use serde::{Deserialize, Serialize};
+
+use super::channels_config::ChannelConfig;
+use super::events::client::ClientEventAppExt;
+use super::events::server::ServerEventAppExt;
+use super::helper::{ChannelId, ClientId};
+#[cfg(feature = "client")]
+use super::helper::{client_connected, ClientSn};
+#[cfg(feature = "server")]
+use super::{
+	events::{
+		client::FromClient,
+		server::{SendMode, ToClients},
+	},
+	helper::{ServerSn, SERVER_ID},
+};
+
+pub const CLIENT_RTT: DiagnosticId = DiagnosticId::from_u128(0x4f1a_6a53_2c1e_4f7e_9d1b_7a0e_3b51_0001);
+pub const CLIENT_SNAPSHOT_AGE: DiagnosticId = DiagnosticId::from_u128(0x4f1a_6a53_2c1e_4f7e_9d1b_7a0e_3b51_0002);
+pub const CLIENT_BYTES_IN: DiagnosticId = DiagnosticId::from_u128(0x4f1a_6a53_2c1e_4f7e_9d1b_7a0e_3b51_0003);
+pub const CLIENT_BYTES_OUT: DiagnosticId = DiagnosticId::from_u128(0x4f1a_6a53_2c1e_4f7e_9d1b_7a0e_3b51_0004);
+pub const CLIENT_MESSAGES_IN: DiagnosticId = DiagnosticId::from_u128(0x4f1a_6a53_2c1e_4f7e_9d1b_7a0e_3b51_0005);
+pub const CLIENT_MESSAGES_OUT: DiagnosticId = DiagnosticId::from_u128(0x4f1a_6a53_2c1e_4f7e_9d1b_7a0e_3b51_0006);
+pub const CLIENT_AVERAGE_PAYLOAD: DiagnosticId = DiagnosticId::from_u128(0x4f1a_6a53_2c1e_4f7e_9d1b_7a0e_3b51_0007);
+
+pub const SERVER_BYTES_IN: DiagnosticId = DiagnosticId::from_u128(0x4f1a_6a53_2c1e_4f7e_9d1b_7a0e_3b51_0101);
+pub const SERVER_BYTES_OUT: DiagnosticId = DiagnosticId::from_u128(0x4f1a_6a53_2c1e_4f7e_9d1b_7a0e_3b51_0102);
+pub const SERVER_MESSAGES_IN: DiagnosticId = DiagnosticId::from_u128(0x4f1a_6a53_2c1e_4f7e_9d1b_7a0e_3b51_0103);
+pub const SERVER_MESSAGES_OUT: DiagnosticId = DiagnosticId::from_u128(0x4f1a_6a53_2c1e_4f7e_9d1b_7a0e_3b51_0104);
+pub const SERVER_CLIENTS: DiagnosticId = DiagnosticId::from_u128(0x4f1a_6a53_2c1e_4f7e_9d1b_7a0e_3b51_0105);
+
+/// Diagnostics the server prints, see [`NetworkDiagnosticsPlugin`].
+pub const SERVER_DIAGNOSTICS: [DiagnosticId; 5] =
+	[SERVER_BYTES_IN, SERVER_BYTES_OUT, SERVER_MESSAGES_IN, SERVER_MESSAGES_OUT, SERVER_CLIENTS];
+
+const PING_INTERVAL_SECONDS: f32 = 1.0;
+/// The server gives up on an unanswered [`ServerPing`] after this long and sends a new one.
+#[cfg(feature = "server")]
+const PING_TIMEOUT_SECONDS: f64 = 5.0;
+/// Pings per second the server answers, well above what [`PING_INTERVAL_SECONDS`] needs.
+const PING_RATE_LIMIT: f32 = 4.0;
+/// How often rates are sampled into [`Diagnostics`].
+const MEASURE_INTERVAL_SECONDS: f32 = 1.0;
+/// How often the server logs a line per client.
+#[cfg(feature = "server")]
+const SERVER_LOG_INTERVAL_SECONDS: f32 = 10.0;
+
+/// Message and byte counts in both directions.
+#[derive(Clone, Copy, Default, Debug)]
+pub struct TrafficCounters {
+	pub messages_in: u64,
+	pub messages_out: u64,
+	pub bytes_in: u64,
+	pub bytes_out: u64,
+}
+
+impl TrafficCounters {
+	fn record_in(&mut self, bytes: usize) {
+		self.messages_in += 1;
+		self.bytes_in += bytes as u64;
+	}
+
+	fn record_out(&mut self, bytes: usize) {
+		self.messages_out += 1;
+		self.bytes_out += bytes as u64;
+	}
+
+	/// Average payload of every message counted so far, in bytes.
+	pub fn average_payload(&self) -> f64 {
+		let messages = self.messages_in + self.messages_out;
+		if messages == 0 {
+			return 0.0;
+		}
+
+		(self.bytes_in + self.bytes_out) as f64 / messages as f64
+	}
+
+	/// Counts since `earlier`.
+	fn since(&self, earlier: &Self) -> Self {
+		Self {
+			messages_in: self.messages_in - earlier.messages_in,
+			messages_out: self.messages_out - earlier.messages_out,
+			bytes_in: self.bytes_in - earlier.bytes_in,
+			bytes_out: self.bytes_out - earlier.bytes_out,
+		}
+	}
+}
+
+#[derive(Default, Debug)]
+pub struct ConnectionStats {
+	pub traffic: TrafficCounters,
+	/// Round trip time, as measured by the server with [`ServerPing`].
+	pub rtt: Option<Duration>,
+	/// When the unanswered [`ServerPing`] was sent, in server real time seconds.
+	#[cfg(feature = "server")]
+	ping_sent_at: Option<f64>,
+}
+
+/// Traffic counters kept by [`ClientSn`] and [`ServerSn`]. Payload sizes only, framing isn't counted.
+#[derive(Default, Debug)]
+pub struct NetworkStats {
+	pub total: TrafficCounters,
+	pub channels: HashMap<ChannelId, TrafficCounters>,
+	/// Per connection, only filled on the server. Clients track theirs in [`Self::total`].
+	pub connections: HashMap<ClientId, ConnectionStats>,
+	/// Latest round trip to the server, only measured on clients.
+	pub rtt: Option<Duration>,
+	/// Real time the newest snapshot arrived at, only used on clients.
+	pub last_snapshot_at: Option<Duration>,
+}
+
+impl NetworkStats {
+	/// Counts a received message, `client_id` being the sender when we are the server.
+	pub fn record_in(&mut self, client_id: Option<ClientId>, channel_id: ChannelId, bytes: usize) {
+		self.total.record_in(bytes);
+		self.channels.entry(channel_id).or_default().record_in(bytes);
+		if let Some(client_id) = client_id {
+			self.connections.entry(client_id).or_default().traffic.record_in(bytes);
+		}
+	}
+
+	/// Counts a sent message, `client_id` being the recipient when we are the server.
+	pub fn record_out(&mut self, client_id: Option<ClientId>, channel_id: ChannelId, bytes: usize) {
+		self.total.record_out(bytes);
+		self.channels.entry(channel_id).or_default().record_out(bytes);
+		if let Some(client_id) = client_id {
+			self.connections.entry(client_id).or_default().traffic.record_out(bytes);
+		}
+	}
+}
+
+/// Sent by clients to measure round trip time.
+#[derive(Event, TypePath, Serialize, Deserialize, Clone, Copy, Debug)]
+pub struct Ping {
+	/// Client real time in seconds, echoed back in [`Pong`].
+	pub sent_at: f64,
+}
+
+#[derive(Event, TypePath, Serialize, Deserialize, Clone, Copy, Debug)]
+pub struct Pong {
+	pub sent_at: f64,
+}
+
+/// Sent by the server to measure round trip time itself, rather than trusting what clients report.
+#[derive(Event, TypePath, Serialize, Deserialize, Clone, Copy, Debug)]
+pub struct ServerPing {
+	/// Server real time in seconds, echoed back in [`ServerPong`].
+	pub sent_at: f64,
+}
+
+#[derive(Event, TypePath, Serialize, Deserialize, Clone, Copy, Debug)]
+pub struct ServerPong {
+	pub sent_at: f64,
+}
+
+/// Collects [`NetworkStats`] into Bevy's [`Diagnostics`] and measures round trip times.
+pub struct NetworkDiagnosticsPlugin;
+
+impl Plugin for NetworkDiagnosticsPlugin {
+	fn build(&self, app: &mut App) {
+		app.add_client_event_with::<Ping>(
+			ChannelConfig::for_event::<Ping>().latest_only().with_rate_limit(PING_RATE_LIMIT),
+		)
+			.add_server_event_with::<Pong>(ChannelConfig::for_event::<Pong>().latest_only())
+			.add_server_event_with::<ServerPing>(ChannelConfig::for_event::<ServerPing>().latest_only())
+			.add_client_event_with::<ServerPong>(
+				ChannelConfig::for_event::<ServerPong>().latest_only().with_rate_limit(PING_RATE_LIMIT),
+			);
+
+		#[cfg(feature = "client")]
+		app.register_diagnostic(Diagnostic::new(CLIENT_RTT, "network/rtt", 20).with_suffix("ms"))
+			.register_diagnostic(Diagnostic::new(CLIENT_SNAPSHOT_AGE, "network/snapshot_age", 20).with_suffix("ms"))
+			.register_diagnostic(Diagnostic::new(CLIENT_BYTES_IN, "network/bytes_in", 20).with_suffix("B/s"))
+			.register_diagnostic(Diagnostic::new(CLIENT_BYTES_OUT, "network/bytes_out", 20).with_suffix("B/s"))
+			.register_diagnostic(Diagnostic::new(CLIENT_MESSAGES_IN, "network/messages_in", 20).with_suffix("/s"))
+			.register_diagnostic(Diagnostic::new(CLIENT_MESSAGES_OUT, "network/messages_out", 20).with_suffix("/s"))
+			.register_diagnostic(Diagnostic::new(CLIENT_AVERAGE_PAYLOAD, "network/average_payload", 20).with_suffix("B"))
+			.add_systems(
+				Update,
+				(send_ping, receive_pong, answer_server_ping, measure_client).run_if(client_connected()),
+			);
+
+		#[cfg(feature = "server")]
+		app.register_diagnostic(Diagnostic::new(SERVER_BYTES_IN, "server/bytes_in", 20).with_suffix("B/s"))
+			.register_diagnostic(Diagnostic::new(SERVER_BYTES_OUT, "server/bytes_out", 20).with_suffix("B/s"))
+			.register_diagnostic(Diagnostic::new(SERVER_MESSAGES_IN, "server/messages_in", 20).with_suffix("/s"))
+			.register_diagnostic(Diagnostic::new(SERVER_MESSAGES_OUT, "server/messages_out", 20).with_suffix("/s"))
+			.register_diagnostic(Diagnostic::new(SERVER_CLIENTS, "server/clients", 20))
+			.add_plugins(bevy::diagnostic::LogDiagnosticsPlugin {
+				wait_duration: Duration::from_secs_f32(SERVER_LOG_INTERVAL_SECONDS),
+				filter: Some(SERVER_DIAGNOSTICS.to_vec()),
+				..default()
+			})
+			.add_systems(
+				Update,
+				(answer_ping, send_server_ping, receive_server_pong, measure_server, log_connections)
+					.run_if(resource_exists::<ServerSn>()),
+			);
+	}
+}
+
+#[cfg(feature = "client")]
+fn send_ping(
+	time: Res<Time<Real>>,
+	mut timer: Local<Option<Timer>>,
+	mut pings: EventWriter<Ping>,
+) {
+	let timer = timer.get_or_insert_with(|| Timer::from_seconds(PING_INTERVAL_SECONDS, TimerMode::Repeating));
+	if !timer.tick(time.delta()).just_finished() {
+		return;
+	}
+
+	pings.send(Ping {
+		sent_at: time.elapsed_seconds_f64(),
+	});
+}
+
+#[cfg(feature = "client")]
+fn receive_pong(time: Res<Time<Real>>, mut client: ResMut<ClientSn>, mut pongs: EventReader<Pong>) {
+	if let Some(pong) = pongs.read().last() {
+		let rtt = (time.elapsed_seconds_f64() - pong.sent_at).max(0.0);
+		client.stats.rtt = Some(Duration::from_secs_f64(rtt));
+	}
+}
+
+#[cfg(feature = "client")]
+fn answer_server_ping(mut pings: EventReader<ServerPing>, mut pongs: EventWriter<ServerPong>) {
+	if let Some(ping) = pings.read().last() {
+		pongs.send(ServerPong { sent_at: ping.sent_at });
+	}
+}
+
+#[cfg(feature = "client")]
+fn measure_client(
+	time: Res<Time<Real>>,
+	client: Res<ClientSn>,
+	mut diagnostics: Diagnostics,
+	mut previous: Local<Option<(Timer, TrafficCounters)>>,
+) {
+	let (timer, previous) = previous.get_or_insert_with(|| {
+		(
+			Timer::from_seconds(MEASURE_INTERVAL_SECONDS, TimerMode::Repeating),
+			client.stats.total,
+		)
+	});
+
+	let stats = &client.stats;
+	if let Some(rtt) = stats.rtt {
+		diagnostics.add_measurement(CLIENT_RTT, || rtt.as_secs_f64() * 1000.0);
+	}
+	if let Some(last_snapshot_at) = stats.last_snapshot_at {
+		diagnostics.add_measurement(CLIENT_SNAPSHOT_AGE, || {
+			time.elapsed().saturating_sub(last_snapshot_at).as_secs_f64() * 1000.0
+		});
+	}
+
+	if !timer.tick(time.delta()).just_finished() {
+		return;
+	}
+
+	// A reconnect starts a fresh `ClientSn` with fresh counters
+	if stats.total.messages_in < previous.messages_in || stats.total.messages_out < previous.messages_out {
+		*previous = TrafficCounters::default();
+	}
+
+	let window = stats.total.since(previous);
+	let seconds = MEASURE_INTERVAL_SECONDS as f64;
+	diagnostics.add_measurement(CLIENT_BYTES_IN, || window.bytes_in as f64 / seconds);
+	diagnostics.add_measurement(CLIENT_BYTES_OUT, || window.bytes_out as f64 / seconds);
+	diagnostics.add_measurement(CLIENT_MESSAGES_IN, || window.messages_in as f64 / seconds);
+	diagnostics.add_measurement(CLIENT_MESSAGES_OUT, || window.messages_out as f64 / seconds);
+	diagnostics.add_measurement(CLIENT_AVERAGE_PAYLOAD, || window.average_payload());
+	*previous = stats.total;
+}
+
+#[cfg(feature = "server")]
+fn answer_ping(
+	mut pings: EventReader<FromClient<Ping>>,
+	mut pongs: EventWriter<ToClients<Pong>>,
+) {
+	for FromClient { client_id, event: ping, .. } in pings.read() {
+		// Our own client measures against the server it shares an app with
+		if *client_id == SERVER_ID {
+			continue;
+		}
+
+		pongs.send(ToClients {
+			mode: SendMode::Direct(*client_id),
+			event: Pong { sent_at: ping.sent_at },
+		});
+	}
+}
+
+#[cfg(feature = "server")]
+fn send_server_ping(
+	time: Res<Time<Real>>,
+	mut server: ResMut<ServerSn>,
+	mut timer: Local<Option<Timer>>,
+	mut pings: EventWriter<ToClients<ServerPing>>,
+) {
+	let timer = timer.get_or_insert_with(|| Timer::from_seconds(PING_INTERVAL_SECONDS, TimerMode::Repeating));
+	if !timer.tick(time.delta()).just_finished() {
+		return;
+	}
+
+	let now = time.elapsed_seconds_f64();
+	let ServerSn { client_connections, stats, .. } = &mut *server;
+
+	for client_id in client_connections.iter() {
+		let connection = stats.connections.entry(*client_id).or_default();
+		if connection.ping_sent_at.is_some_and(|sent_at| now - sent_at < PING_TIMEOUT_SECONDS) {
+			continue;
+		}
+
+		connection.ping_sent_at = Some(now);
+		pings.send(ToClients {
+			mode: SendMode::Direct(*client_id),
+			event: ServerPing { sent_at: now },
+		});
+	}
+}
+
+#[cfg(feature = "server")]
+fn receive_server_pong(
+	time: Res<Time<Real>>,
+	mut server: ResMut<ServerSn>,
+	mut pongs: EventReader<FromClient<ServerPong>>,
+) {
+	let now = time.elapsed_seconds_f64();
+
+	for FromClient { client_id, event: pong, .. } in pongs.read() {
+		let Some(connection) = server.stats.connections.get_mut(client_id) else {
+			continue;
+		};
+
+		// Only an echo of the ping we are waiting on counts, so a client can't make up a shorter round trip
+		if connection.ping_sent_at != Some(pong.sent_at) {
+			continue;
+		}
+
+		connection.ping_sent_at = None;
+		connection.rtt = Some(Duration::from_secs_f64((now - pong.sent_at).max(0.0)));
+	}
+}
+
+#[cfg(feature = "server")]
+fn measure_server(
+	time: Res<Time<Real>>,
+	server: Res<ServerSn>,
+	mut diagnostics: Diagnostics,
+	mut previous: Local<Option<(Timer, TrafficCounters)>>,
+) {
+	let (timer, previous) = previous.get_or_insert_with(|| {
+		(
+			Timer::from_seconds(MEASURE_INTERVAL_SECONDS, TimerMode::Repeating),
+			server.stats.total,
+		)
+	});
+
+	if !timer.tick(time.delta()).just_finished() {
+		return;
+	}
+
+	let window = server.stats.total.since(previous);
+	let seconds = MEASURE_INTERVAL_SECONDS as f64;
+	diagnostics.add_measurement(SERVER_BYTES_IN, || window.bytes_in as f64 / seconds);
+	diagnostics.add_measurement(SERVER_BYTES_OUT, || window.bytes_out as f64 / seconds);
+	diagnostics.add_measurement(SERVER_MESSAGES_IN, || window.messages_in as f64 / seconds);
+	diagnostics.add_measurement(SERVER_MESSAGES_OUT, || window.messages_out as f64 / seconds);
+	diagnostics.add_measurement(SERVER_CLIENTS, || server.client_connections.len() as f64);
+	*previous = server.stats.total;
+}
+
+/// Prints one line per connected client every [`SERVER_LOG_INTERVAL_SECONDS`].
+#[cfg(feature = "server")]
+fn log_connections(time: Res<Time<Real>>, server: Res<ServerSn>, mut timer: Local<Option<Timer>>) {
+	let timer = timer.get_or_insert_with(|| Timer::from_seconds(SERVER_LOG_INTERVAL_SECONDS, TimerMode::Repeating));
+	if !timer.tick(time.delta()).just_finished() {
+		return;
+	}
+
+	for client_id in &server.client_connections {
+		let Some(ConnectionStats { traffic, rtt, .. }) = server.stats.connections.get(client_id) else {
+			continue;
+		};
+
+		info!(
+			"client {client_id}: rtt {}, in {} msgs / {} B, out {} msgs / {} B, avg payload {:.0} B",
+			rtt.map_or(String::from("?"), |rtt| format!("{}ms", rtt.as_millis())),
+			traffic.messages_in,
+			traffic.bytes_in,
+			traffic.messages_out,
+			traffic.bytes_out,
+			traffic.average_payload(),
+		);
+	}
+}
@@ -0,0 +1,87 @@
+use std::fmt;
+
+use bevy::prelude::*;
+
+use super::helper::{ChannelId, ClientId};
+
+/// Everything that can go wrong while moving events between the client and server.
+#[derive(Debug)]
+pub enum NetworkError {
+	/// An outgoing event couldn't be encoded.
+	Serialize {
+		channel_id: ChannelId,
+		source: bincode::Error,
+	},
+	/// An incoming message didn't decode into the type registered for its channel.
+	Deserialize {
+		channel_id: ChannelId,
+		source: bincode::Error,
+	},
+	/// A payload exceeded its channel's `max_bytes` and was dropped.
+	MessageTooLarge {
+		channel_id: ChannelId,
+		size: usize,
+		max_bytes: usize,
+	},
+	/// A message arrived on a channel nobody registered.
+	UnknownChannel(ChannelId),
+	/// The transport refused a batch of `messages`, usually because the connection is closing.
+	/// `None` when sending from the client to the server.
+	SendFailed {
+		client_id: Option<ClientId>,
+		messages: usize,
+	},
+	/// A request from this client never made it to the server.
+	RequestSendFailed,
+	/// The server rejected a request from this client.
+	Rejected,
+	/// The server never answered a request before the connection dropped.
+	ResponseLost,
+}
+
+impl fmt::Display for NetworkError {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		match self {
+			NetworkError::Serialize { channel_id, source } => {
+				write!(f, "failed to serialize event for channel {channel_id}: {source}")
+			}
+			NetworkError::Deserialize { channel_id, source } => {
+				write!(f, "failed to deserialize event on channel {channel_id}: {source}")
+			}
+			NetworkError::MessageTooLarge { channel_id, size, max_bytes } => write!(
+				f,
+				"message of {size} bytes on channel {channel_id} exceeds the {max_bytes} byte limit"
+			),
+			NetworkError::UnknownChannel(channel_id) => {
+				write!(f, "received message on unknown channel {channel_id}")
+			}
+			NetworkError::SendFailed {
+				client_id: Some(client_id),
+				messages,
+			} => write!(f, "failed to send {messages} messages to client {client_id}"),
+			NetworkError::SendFailed {
+				client_id: None,
+				messages,
+			} => write!(f, "failed to send {messages} messages to the server"),
+			NetworkError::RequestSendFailed => write!(f, "failed to send request to the server"),
+			NetworkError::Rejected => write!(f, "request rejected by the server"),
+			NetworkError::ResponseLost => write!(f, "response lost before the server answered"),
+		}
+	}
+}
+
+impl std::error::Error for NetworkError {
+	fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+		match self {
+			NetworkError::Serialize { source, .. } | NetworkError::Deserialize { source, .. } => {
+				Some(source.as_ref())
+			}
+			_ => None,
+		}
+	}
+}
+
+/// Emitted on the client whenever the network layer hits a [`NetworkError`],
+/// so the UI can tell the player instead of the game crashing.
+#[derive(Event, Debug)]
+pub struct NetworkErrorEvent(pub NetworkError);
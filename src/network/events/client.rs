@@ -1,15 +1,29 @@
-use crate::network::channels_config::ChannelManager;
+use crate::network::channels_config::{ChannelConfig, ChannelManager, DeliveryMode};
+use crate::network::error::NetworkErrorEvent;
 use crate::network::helper::*;
 use bevy::prelude::*;
-use bincode::{DefaultOptions, Options};
 use serde::{de::DeserializeOwned, Serialize};
 
 pub trait ClientEventAppExt {
-	fn add_client_event<T: Event + Serialize + DeserializeOwned>(&mut self) -> &mut Self;
+	/// Registers event `T` that will be emitted as [`FromClient<T>`] on the server after sending `T`
+	/// on a client. The channel is keyed by `T`'s type path.
+	fn add_client_event<T: Event + TypePath + Serialize + DeserializeOwned>(&mut self) -> &mut Self {
+		self.add_client_event_with::<T>(ChannelConfig::for_event::<T>())
+	}
+
+	/// Same as [`Self::add_client_event`], but with an explicit channel key that survives
+	/// renaming or moving `T`.
+	fn add_client_event_with_key<T: Event + Serialize + DeserializeOwned>(&mut self, key: &'static str) -> &mut Self {
+		self.add_client_event_with::<T>(ChannelConfig::new(key))
+	}
+
+	/// Same as [`Self::add_client_event`], but with full control over the channel's key,
+	/// delivery mode and size limit.
+	fn add_client_event_with<T: Event + Serialize + DeserializeOwned>(&mut self, config: ChannelConfig) -> &mut Self;
 }
 
 impl ClientEventAppExt for App {
-	fn add_client_event<T: Event + Serialize + DeserializeOwned>(&mut self) -> &mut Self {
+	fn add_client_event_with<T: Event + Serialize + DeserializeOwned>(&mut self, config: ChannelConfig) -> &mut Self {
 		// Create Event Channel Resource
 		// init resouce Events<FromClient<T>>
 		// Add event <T>
@@ -18,13 +32,13 @@ impl ClientEventAppExt for App {
 		let channel_id = self
 			.world
 			.resource_mut::<ChannelManager>()
-			.create_client_channel();
+			.create_client_channel(config);
 
-		dbg!("client channel", std::any::type_name::<T>(), channel_id);
+		debug!("client channel {channel_id:08x} registered for {} ({:?})", config.key, config.delivery);
 
 		self.add_event::<T>()
 			.init_resource::<Events<FromClient<T>>>()
-			.insert_resource(EventChannel::<T>::new(channel_id));
+			.insert_resource(EventChannel::<T>::new(channel_id, &config));
 
 		#[cfg(feature = "server")]
 		self.add_systems(
@@ -52,6 +66,9 @@ impl ClientEventAppExt for App {
 pub struct FromClient<T> {
 	pub client_id: u128,
 	pub event: T,
+	/// `true` if this process's own player sent it through [`local_resending_system`]. Unlike
+	/// `client_id`, which clients pick themselves, a remote client can't fake this.
+	pub local: bool,
 }
 
 #[cfg(feature = "server")]
@@ -60,27 +77,42 @@ fn receiving_system<T: Event + DeserializeOwned>(
 	mut client_events: EventWriter<FromClient<T>>,
 	channel: Res<EventChannel<T>>,
 ) {
-	if let Some(message_queue) = server.message_channel_buckets.get_mut(&channel.channel_id) {
-		for (client_id, client_message) in message_queue.drain(0..) {
-			client_events.send(FromClient { client_id, event: client_message.get_event::<T>() })
+	let Some(message_queue) = server.message_channel_buckets.get_mut(&channel.channel_id) else {
+		return;
+	};
+
+	let messages: Vec<(ClientId, ClientMsg)> = std::mem::take(message_queue);
+	for (client_id, client_message) in messages {
+		match client_message.get_event::<T>() {
+			Ok(event) => client_events.send(FromClient { client_id, event, local: false }),
+			Err(error) => server.report_malformed(client_id, error),
 		}
 	}
 }
 
 fn sending_system<T: Event + Serialize>(
 	mut events: EventReader<T>,
-	client: ResMut<ClientSn>,
+	mut client: ResMut<ClientSn>,
 	channel: Res<EventChannel<T>>,
+	mut network_errors: EventWriter<NetworkErrorEvent>,
 ) {
-	for event in events.read() {
-		let message = DefaultOptions::new()
-			.serialize(&event)
-			.expect("client event should be serializable");
-
-		client
-			.simplenet
-			.send(ClientMsg { channel_id: channel.channel_id, event: message })
-			.unwrap();
+	let events: Vec<&T> = match channel.delivery {
+		DeliveryMode::ReliableOrdered => events.read().collect(),
+		// Everything before the last event of the frame is already stale
+		DeliveryMode::LatestOnly => events.read().last().into_iter().collect(),
+	};
+
+	for event in events {
+		let message = match channel.serialize(event) {
+			Ok(message) => message,
+			Err(error) => {
+				network_errors.send(NetworkErrorEvent(error));
+				continue;
+			}
+		};
+
+		client.stats.record_out(None, channel.channel_id, message.len());
+		client.outbox.push(ClientMsg { channel_id: channel.channel_id, event: message });
 	}
 }
 
@@ -94,6 +126,7 @@ fn local_resending_system<T: Event>(
 		client_events.send(FromClient {
 			client_id: SERVER_ID,
 			event,
+			local: true,
 		})
 	}
 }
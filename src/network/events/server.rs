@@ -1,95 +1,99 @@
 use bevy::prelude::*;
-use bincode::{DefaultOptions, Options};
-// use ordered_multimap::ListOrderedMultimap;
+use bevy::utils::{HashMap, HashSet};
 use serde::{de::DeserializeOwned, Serialize};
 use std::fmt::Debug;
 use crate::network::{
-	channels_config::ChannelManager,
-	helper::{client_connected, ClientId, ClientSet, ClientSn, EventChannel, ServerMsg, SERVER_ID, GetData},
-	// tick::{LastRepliconTick, MinRepliconTick, RepliconTick, self},
+	channels_config::{ChannelConfig, ChannelManager},
+	error::NetworkErrorEvent,
+	helper::{ClientId, ClientSet, ClientSn, EventChannel, TeamId, SERVER_ID, GetData},
 };
+#[cfg(feature = "server")]
+use crate::network::{channels_config::DeliveryMode, error::NetworkError, helper::ServerMsg};
 
 #[cfg(feature = "server")]
 use crate::network::helper::{ServerSet, ServerSn};
+#[cfg(all(feature = "client", feature = "server"))]
+use crate::network::helper::hosting;
 
 /// An extension trait for [`App`] for creating server events.
 pub trait ServerEventAppExt {
 	/// Registers event `T` that will be emitted on client after sending [`ToClients<T>`] on server.
-	fn add_server_event<T: Event + Serialize + DeserializeOwned + Clone + Debug>(&mut self) -> &mut Self;
+	/// The channel is keyed by `T`'s type path.
+	fn add_server_event<T: Event + TypePath + Serialize + DeserializeOwned + Clone + Debug>(&mut self) -> &mut Self {
+		self.add_server_event_with::<T>(ChannelConfig::for_event::<T>())
+	}
+
+	/// Same as [`Self::add_server_event`], but with an explicit channel key that survives
+	/// renaming or moving `T`.
+	fn add_server_event_with_key<T: Event + Serialize + DeserializeOwned + Clone + Debug>(
+		&mut self,
+		key: &'static str,
+	) -> &mut Self {
+		self.add_server_event_with::<T>(ChannelConfig::new(key))
+	}
+
+	/// Same as [`Self::add_server_event`], but with full control over the channel's key,
+	/// delivery mode and size limit.
+	fn add_server_event_with<T: Event + Serialize + DeserializeOwned + Clone + Debug>(
+		&mut self,
+		config: ChannelConfig,
+	) -> &mut Self;
 }
 
 impl ServerEventAppExt for App {
-	fn add_server_event<T: Event + Serialize + DeserializeOwned + Clone + Debug>(&mut self) -> &mut Self {
-		// self.add_server_event_with::<T, _, _>(, )
-
+	fn add_server_event_with<T: Event + Serialize + DeserializeOwned + Clone + Debug>(
+		&mut self,
+		config: ChannelConfig,
+	) -> &mut Self {
 		let channel_id = self
 			.world
 			.resource_mut::<ChannelManager>()
-			.create_server_channel();
+			.create_server_channel(config);
 
-		dbg!("server channel", std::any::type_name::<T>(), channel_id);
+		debug!("server channel {channel_id:08x} registered for {} ({:?})", config.key, config.delivery);
 
 		self.add_event::<T>()
-			.init_resource::<Events<ToClient<T>>>()
-			// .init_resource::<ServerEventQueue<T>>()
-			.insert_resource(EventChannel::<T>::new(channel_id))
+			.init_resource::<Events<ToClients<T>>>()
+			.insert_resource(EventChannel::<T>::new(channel_id, &config))
 			.add_systems(
 				PreUpdate,
 				receiving_system::<T>
-					// .after(ClientPlugin::replication_receiving_system)
 					.in_set(ClientSet::Receive)
-					.run_if(client_connected()),
+					// Not `client_connected`, messages that arrived right before a disconnect
+					// (like the reason we were rejected) still need to be read
+					.run_if(resource_exists::<ClientSn>()),
 			);
 
 		#[cfg(feature = "server")]
 		self.add_systems(
 			PostUpdate,
-			(
-				(sending_system::<T>
-					.run_if(resource_exists::<ServerSn>()),)
-					.chain()
-					// .before(ServerPlugin::replication_sending_system)
-					.in_set(ServerSet::Send),
-				// reset_system::<T>.run_if(resource_removed::<ClientSn>()),
-			),
+			sending_system::<T>
+				.in_set(ServerSet::Send)
+				.run_if(resource_exists::<ServerSn>()),
+		);
+
+		#[cfg(all(feature = "client", feature = "server"))]
+		self.add_systems(
+			PostUpdate,
+			local_resending_system::<T>.in_set(ServerSet::Send).run_if(hosting()),
 		);
 
 		self
 	}
 }
 
-/// Applies all queued events if their tick is less or equal to [`LastRepliconTick`].
-// fn queue_system<T: Event>(
-//     last_tick: Res<LastRepliconTick>,
-//     mut server_events: EventWriter<T>,
-//     mut client: ResMut<ClientSn>,
-// ) {
-//     while event_queue
-//         .front()
-//         .filter(|(&tick, _)| tick <= **last_tick)
-//         .is_some()
-//     {
-//         let (_, event) = event_queue.pop_front().unwrap();
-//         server_events.send(event);
-//     }
-// }
-
-// fn deserialize_event_messages<T: DeserializeOwned>(message: Vec<u8>) -> T {
-//     DefaultOptions::new()
-//         .deserialize(&message)
-//         .expect("server should send valid events")
-// }
-
 fn receiving_system<T: Event + DeserializeOwned + Debug>(
-	mut server_events: EventWriter<ToClient<T>>,
+	mut server_events: EventWriter<T>,
 	mut client: ResMut<ClientSn>,
-	// last_tick: Res<LastRepliconTick>,
 	channel: Res<EventChannel<T>>,
+	mut network_errors: EventWriter<NetworkErrorEvent>,
 ) {
 	if let Some(server_messages) = client.message_channel_buckets.get_mut(&channel.channel_id) {
 		for server_msg in server_messages.drain(..) {
-			dbg!(server_msg.get_event::<T>());
-			server_events.send(ToClient { mode: None, event: server_msg.get_event::<T>() });
+			match server_msg.get_event::<T>() {
+				Ok(event) => server_events.send(event),
+				Err(error) => network_errors.send(NetworkErrorEvent(error)),
+			}
 		}
 	}
 }
@@ -97,155 +101,161 @@ fn receiving_system<T: Event + DeserializeOwned + Debug>(
 #[cfg(feature = "server")]
 fn sending_system<T: Event + Serialize + Clone + Debug>(
 	mut server: ResMut<ServerSn>,
-	mut server_events: EventReader<ToClient<T>>,
-	// tick: Res<RepliconTick>,
+	mut server_events: EventReader<ToClients<T>>,
 	channel: Res<EventChannel<T>>,
 ) {
-	for ToClient { event, mode } in server_events.read() {
-		send_server_event::<T>(&mut server, channel.channel_id, mode.unwrap(), event.clone());
+	let mut outgoing: Vec<(&SendMode, &T)> = Vec::new();
+	for ToClients { event, mode } in server_events.read() {
+		match channel.delivery {
+			DeliveryMode::ReliableOrdered => outgoing.push((mode, event)),
+			// A newer event for the same recipients supersedes the queued one
+			DeliveryMode::LatestOnly => match outgoing.iter_mut().find(|(queued, _)| *queued == mode) {
+				Some(queued) => queued.1 = event,
+				None => outgoing.push((mode, event)),
+			},
+		}
+	}
+
+	for (mode, event) in outgoing {
+		if let Err(error) = send_server_event::<T>(&mut server, &channel, mode, event) {
+			error!("{error}");
+		}
+	}
+}
+
+/// Transforms [`ToClients<T>`] events addressed to the host into `T` events, so a listen
+/// server's own player reads them like any other client. See [`hosting`].
+#[cfg(all(feature = "client", feature = "server"))]
+fn local_resending_system<T: Event + Clone>(
+	server: Res<ServerSn>,
+	mut server_events: EventReader<ToClients<T>>,
+	mut local_events: EventWriter<T>,
+) {
+	for ToClients { mode, event } in server_events.read() {
+		if mode.includes(SERVER_ID, &server.teams) {
+			local_events.send(event.clone());
+		}
 	}
 }
 
-/// Updates [`MinRepliconTick`] to force server to send replication message even if there were no world changes.
-///
-/// Needed because events on a client won't be emitted until the client acknowledges the event tick.
-/// See also [`ServerEventQueue`].
-// fn min_tick_update_system<T: Event>(
-// 	mut server_events: EventReader<ToClients<T>>,
-// 	mut min_tick: ResMut<MinRepliconTick>,
-// 	tick: Res<RepliconTick>,
-// ) {
-// 	if server_events.read().count() > 0 {
-// 		**min_tick = *tick;
-// 	}
-// }
-
-/// Transforms [`ToClients<T>`] events into `T` events to "emulate"
-/// message sending for offline mode or when server is also a player
-// fn local_resending_system<T: Event>(
-//     mut server_events: ResMut<Events<ToClients<T>>>,
-//     mut local_events: EventWriter<T>,
-// ) {
-//     for ToClients { event, mode } in server_events.drain() {
-//         match mode {
-//             SendMode::Broadcast => {
-//                 local_events.send(event);
-//             }
-//             SendMode::BroadcastExcept(client_id) => {
-//                 if client_id != SERVER_ID {
-//                     local_events.send(event);
-//                 }
-//             }
-//             SendMode::Direct(client_id) => {
-//                 if client_id == SERVER_ID {
-//                     local_events.send(event);
-//                 }
-//             }
-//         }
-//     }
-// }
-
-// fn reset_system<T: Event>(mut event_queue: ResMut<ServerEventQueue<T>>) {
-//     event_queue.clear();
-// }
-
-/// Sends serialized `message` to clients.
+/// Queues `message` for every client selected by `mode`, it goes out with the next batch.
 ///
-/// Helper for custom sending systems.
-// /// See also [`ServerEventAppExt::add_server_event_with`]
-use crate::network::helper::ChannelId;
-
+/// Helper for custom sending systems, see also [`ServerEventAppExt::add_server_event_with`].
 #[cfg(feature = "server")]
 pub fn send_server_event<T: Serialize + Debug>(
 	server: &mut ServerSn,
-	channel_id: ChannelId,
-	mode: SendMode,
-    // tick: Option<RepliconTick>,
-	message: T,
-) {
-	dbg!("Sending server event");
-	dbg!(&message);
-
-    let message = DefaultOptions::new()
-        .serialize(&message)
-        .expect("server event should be serializable");	
-
-    match mode {
-		SendMode::Broadcast => {
-			for client_id in server.client_connections.iter() {
-				server
-					.simplenet
-					.send(
-						*client_id,
-						ServerMsg {
-                            channel_id,
-                            event: message.clone(),
-                        }
-					)
-					.unwrap();
-			}
-		}
-		SendMode::BroadcastExcept(except_client_id) => {
-			for client_id in server.client_connections.iter() {
-				if *client_id == except_client_id {
-					continue;
-				}
-				server
-					.simplenet
-					.send(
-						*client_id,
-						ServerMsg {
-                            channel_id,
-                            // tick,
-                            event: message.clone(),
-                        }
-                    )
-					.unwrap();
-			}
-		}
-		SendMode::Direct(client_id) => {
-			if client_id != SERVER_ID {
-				server
-					.simplenet
-					.send(
-                        client_id,
-                        ServerMsg {
-                            channel_id,
-                            // tick,
-                            event: message.clone(),
-                        }
-                    )
-					.unwrap();
-			}
-		}
+	channel: &EventChannel<T>,
+	mode: &SendMode,
+	message: &T,
+) -> Result<(), NetworkError> {
+	let channel_id = channel.channel_id;
+	let message = channel.serialize(message)?;
+
+	for client_id in mode.recipients(&server.client_connections, &server.teams) {
+		server.stats.record_out(Some(client_id), channel_id, message.len());
+		server.outbox.entry(client_id).or_default().push(ServerMsg {
+			channel_id,
+			event: message.clone(),
+		});
 	}
+
+	Ok(())
 }
 
-/// An event that will be send to client(s).
-#[derive(Clone, Copy, Debug, Event)]
-pub struct ToClient<T> {
-	pub mode: Option<SendMode>,
+/// An event that will be send to client(s), who receive it as a plain `T`.
+#[derive(Clone, Debug, Event)]
+pub struct ToClients<T> {
+	pub mode: SendMode,
 	pub event: T,
 }
 
 /// Type of server message sending.
-#[derive(Clone, Copy, Debug)]
+#[derive(Clone, Debug, PartialEq, Eq)]
 #[allow(unused)]
 pub enum SendMode {
 	Broadcast,
 	BroadcastExcept(ClientId),
 	Direct(ClientId),
+	/// Every connected client on the team, see [`ServerSn::teams`](crate::network::helper::ServerSn).
+	ToTeam(TeamId),
+	ToMany(Vec<ClientId>),
+}
+
+impl SendMode {
+	/// Whether an event sent this way reaches `client_id`, `teams` being the team of each client.
+	pub fn includes(&self, client_id: ClientId, teams: &HashMap<ClientId, TeamId>) -> bool {
+		match self {
+			SendMode::Broadcast => true,
+			SendMode::BroadcastExcept(except_client_id) => client_id != *except_client_id,
+			SendMode::Direct(direct_client_id) => client_id == *direct_client_id,
+			SendMode::ToTeam(team) => teams.get(&client_id) == Some(team),
+			SendMode::ToMany(client_ids) => client_ids.contains(&client_id),
+		}
+	}
+
+	/// Clients an event sent this way goes out to over the network, each of them once.
+	///
+	/// Ids that aren't in `connections` (anymore) are dropped, nothing would flush their outbox.
+	/// [`SERVER_ID`] is never included, the host gets its events locally.
+	pub fn recipients(&self, connections: &HashSet<ClientId>, teams: &HashMap<ClientId, TeamId>) -> Vec<ClientId> {
+		let mut recipients: Vec<ClientId> = match self {
+			SendMode::Direct(client_id) => vec![*client_id],
+			SendMode::ToMany(client_ids) => client_ids.clone(),
+			_ => connections
+				.iter()
+				.copied()
+				.filter(|client_id| self.includes(*client_id, teams))
+				.collect(),
+		};
+
+		recipients.retain(|client_id| *client_id != SERVER_ID && connections.contains(client_id));
+		recipients.sort_unstable();
+		recipients.dedup();
+		recipients
+	}
 }
 
-// Stores all received events from server that arrived earlier then replication message with their tick.
-//
-// Stores data sorted by ticks and maintains order of arrival.
-// Needed to ensure that when an event is triggered, all the data that it affects or references already exists.
-// #[derive(Deref, DerefMut, Resource)]
-// pub struct ServerEventQueue<T>(ListOrderedMultimap<RepliconTick, T>);
-
-// impl<T> Default for ServerEventQueue<T> {
-// 	fn default() -> Self {
-// 		Self(Default::default())
-// 	}
-// }
+#[cfg(all(test, feature = "server"))]
+mod tests {
+	use super::*;
+
+	fn setup() -> (HashSet<ClientId>, HashMap<ClientId, TeamId>) {
+		let connections = HashSet::from_iter([1, 2, 3]);
+		let teams = HashMap::from_iter([(1, 7), (2, 7), (3, 8), (SERVER_ID, 7)]);
+		(connections, teams)
+	}
+
+	#[test]
+	fn broadcast_reaches_every_connection() {
+		let (connections, teams) = setup();
+		assert_eq!(SendMode::Broadcast.recipients(&connections, &teams), vec![1, 2, 3]);
+	}
+
+	#[test]
+	fn broadcast_except_skips_one() {
+		let (connections, teams) = setup();
+		assert_eq!(SendMode::BroadcastExcept(2).recipients(&connections, &teams), vec![1, 3]);
+	}
+
+	#[test]
+	fn direct_only_reaches_connected_clients() {
+		let (connections, teams) = setup();
+		assert_eq!(SendMode::Direct(3).recipients(&connections, &teams), vec![3]);
+		assert!(SendMode::Direct(9).recipients(&connections, &teams).is_empty());
+		assert!(SendMode::Direct(SERVER_ID).recipients(&connections, &teams).is_empty());
+	}
+
+	#[test]
+	fn to_team_reaches_connected_members() {
+		let (connections, teams) = setup();
+		assert_eq!(SendMode::ToTeam(7).recipients(&connections, &teams), vec![1, 2]);
+		assert!(SendMode::ToTeam(9).recipients(&connections, &teams).is_empty());
+	}
+
+	#[test]
+	fn to_many_is_deduplicated_and_drops_stale_ids() {
+		let (connections, teams) = setup();
+		let mode = SendMode::ToMany(vec![3, 1, 3, 9, SERVER_ID]);
+		assert_eq!(mode.recipients(&connections, &teams), vec![1, 3]);
+	}
+}
@@ -2,37 +2,98 @@ use std::marker::PhantomData;
 
 use bevy::ecs::schedule::SystemSet;
 use bevy::ecs::system::{Res, Resource};
+#[cfg(feature = "server")]
+use bevy::log::warn;
 
-use bevy::utils::{HashMap, HashSet};
-use bevy_simplenet::ChannelPack;
-
-use bevy_simplenet::Client;
-
+use bevy::utils::HashMap;
 #[cfg(feature = "server")]
-use bevy_simplenet::Server;
+use bevy::utils::HashSet;
+use bevy_simplenet::ChannelPack;
 use bincode::{DefaultOptions, Options as _};
 use serde::de::DeserializeOwned;
 use serde::{Deserialize, Serialize};
 
+#[cfg(feature = "client")]
+use super::channels_config::ChannelManager;
+use super::channels_config::{ChannelConfig, ChannelFingerprint, DeliveryMode};
+use super::connection::SessionToken;
+use super::diagnostics::NetworkStats;
+use super::error::NetworkError;
+use super::transport::ClientTransport;
+#[cfg(feature = "server")]
+use super::transport::ServerTransport;
+#[cfg(feature = "server")]
+use super::violations::RateLimiter;
+#[cfg(all(feature = "client", feature = "server"))]
+use super::transport::{ClientIdTaken, MemoryServer};
+#[cfg(feature = "client")]
+use crate::Cli;
+#[cfg(feature = "client")]
+use bevy_simplenet::{AuthRequest, ClientConfig, ClientFactory};
+
 pub const SERVER_ID: ClientId = 0;
 
+/// Id for a new client, never [`SERVER_ID`], which the server refuses.
+#[cfg(feature = "client")]
+pub fn random_client_id() -> ClientId {
+	loop {
+		let client_id: ClientId = rand::random();
+		if client_id != SERVER_ID {
+			return client_id;
+		}
+	}
+}
+
+/// Bumped whenever the wire format changes in a way the channel table hash can't catch.
+pub const PROTOCOL_VERSION: u32 = 6;
+
+/// Malformed messages a client may send before the server kicks it.
+#[cfg(feature = "server")]
+pub const MAX_MALFORMED_MESSAGES: u32 = 10;
+
 #[derive(Resource, Clone, Copy, Debug)]
 pub struct EventChannel<T> {
 	pub channel_id: ChannelId,
+	pub delivery: DeliveryMode,
+	pub max_bytes: usize,
 	pub marker: PhantomData<T>,
 }
 
 impl<T> EventChannel<T> {
-	pub fn new(channel_id: ChannelId) -> Self {
+	pub fn new(channel_id: ChannelId, config: &ChannelConfig) -> Self {
 		Self {
 			channel_id,
+			delivery: config.delivery,
+			max_bytes: config.max_bytes,
 			marker: PhantomData,
 		}
 	}
+
+	/// Serializes `event` for this channel, refusing payloads over [`Self::max_bytes`].
+	pub fn serialize(&self, event: &T) -> Result<Vec<u8>, NetworkError>
+	where
+		T: Serialize,
+	{
+		let message = DefaultOptions::new()
+			.serialize(event)
+			.map_err(|source| NetworkError::Serialize { channel_id: self.channel_id, source })?;
+
+		if message.len() > self.max_bytes {
+			return Err(NetworkError::MessageTooLarge {
+				channel_id: self.channel_id,
+				size: message.len(),
+				max_bytes: self.max_bytes,
+			});
+		}
+
+		Ok(message)
+	}
 }
 
 pub type ClientId = u128;
-pub type ChannelId = u8;
+pub type TeamId = u8;
+/// Hash of the event key, see [`ChannelManager`](super::channels_config::ChannelManager).
+pub type ChannelId = u32;
 
 /// Set with replication and event systems related to server.
 #[derive(SystemSet, Debug, Hash, PartialEq, Eq, Clone, Copy)]
@@ -64,7 +125,18 @@ pub enum ClientSet {
 
 // define a channel
 #[derive(Serialize, Deserialize, Clone, Debug)]
-pub struct ConnectMsg(pub String);
+pub struct ConnectMsg {
+	/// Must equal [`PROTOCOL_VERSION`] on the server.
+	pub protocol_version: u32,
+	/// Must match the server's [`ChannelManager::fingerprints`].
+	pub channels: Vec<ChannelFingerprint>,
+	/// Crate version of the client, only used for logging.
+	pub client_build: String,
+	/// Display name the player asked for, sanitized by the server.
+	pub name: String,
+	/// Token from a previous connection, used to take back our ship after reconnecting.
+	pub session_token: Option<SessionToken>,
+}
 
 #[derive(Serialize, Deserialize, Clone, Debug)]
 pub struct ServerMsg {
@@ -79,55 +151,55 @@ pub struct ClientMsg {
     pub event: Vec<u8>
 }
 
+/// Everything one side queued for the other during a tick, sent as a single websocket frame.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct MessageBatch<M>(pub Vec<M>);
+
 pub trait GetData {
-    fn get_bytes(&self) -> Vec<u8>;
-    fn from_bytes(message: Vec<u8>, channel_id: ChannelId) -> Self;
-    fn get_event<T: DeserializeOwned>(&self) -> T;
+	fn get_bytes(&self) -> Vec<u8>;
+	fn from_bytes(message: Vec<u8>, channel_id: ChannelId) -> Self;
+	fn get_event<T: DeserializeOwned>(&self) -> Result<T, NetworkError>;
 }
 
 impl GetData for ServerMsg {
-    fn get_bytes(&self) -> Vec<u8> {
-        DefaultOptions::new().serialize(&self.event.clone()).expect("serializable")
-    }
-
-    fn from_bytes(message: Vec<u8>, channel_id: ChannelId) -> Self {
-        let event = DefaultOptions::new()
-            .deserialize(&message)
-            .expect("server should send valid events");
+	fn get_bytes(&self) -> Vec<u8> {
+		self.event.clone()
+	}
 
-        Self { channel_id, event }
-    }
+	fn from_bytes(message: Vec<u8>, channel_id: ChannelId) -> Self {
+		Self { channel_id, event: message }
+	}
 
-    fn get_event<T: DeserializeOwned>(&self) -> T {
-        DefaultOptions::new()
-            .deserialize(&self.event)
-            .expect("server should send valid events")
-    }
+	fn get_event<T: DeserializeOwned>(&self) -> Result<T, NetworkError> {
+		DefaultOptions::new()
+			.deserialize(&self.event)
+			.map_err(|source| NetworkError::Deserialize { channel_id: self.channel_id, source })
+	}
 }
 
 impl GetData for ClientMsg {
-    fn get_bytes(&self) -> Vec<u8> {
-        self.event.clone()
-    }
+	fn get_bytes(&self) -> Vec<u8> {
+		self.event.clone()
+	}
 
-    fn from_bytes(message: Vec<u8>, channel_id: ChannelId) -> Self {
-        Self { channel_id, event: message }
-    }
+	fn from_bytes(message: Vec<u8>, channel_id: ChannelId) -> Self {
+		Self { channel_id, event: message }
+	}
 
-    fn get_event<T: DeserializeOwned>(&self) -> T {
-        DefaultOptions::new()
-            .deserialize(&self.event)
-            .expect("server should send valid events")
-    }
+	fn get_event<T: DeserializeOwned>(&self) -> Result<T, NetworkError> {
+		DefaultOptions::new()
+			.deserialize(&self.event)
+			.map_err(|source| NetworkError::Deserialize { channel_id: self.channel_id, source })
+	}
 }
 
 #[derive(Debug, Clone)]
 pub struct NetworkChannel;
 impl ChannelPack for NetworkChannel {
 	type ConnectMsg = ConnectMsg;
-	type ServerMsg = ServerMsg;
+	type ServerMsg = MessageBatch<ServerMsg>;
 	type ServerResponse = ();
-	type ClientMsg = ClientMsg;
+	type ClientMsg = MessageBatch<ClientMsg>;
 	type ClientRequest = ();
 }
 
@@ -150,21 +222,208 @@ impl ChannelPack for NetworkChannel {
 // Sn = Simplenet
 #[derive(Resource)]
 pub struct ClientSn {
-	pub simplenet: Client<NetworkChannel>,
+	pub transport: ClientTransport,
 	pub message_channel_buckets: HashMap<ChannelId, Vec<ServerMsg>>,
+	/// Messages queued during [`ClientSet::Send`], flushed as one [`MessageBatch`].
+	pub outbox: Vec<ClientMsg>,
+	pub stats: NetworkStats,
+}
+
+impl ClientSn {
+	pub fn new(transport: ClientTransport) -> Self {
+		Self {
+			transport,
+			message_channel_buckets: HashMap::new(),
+			outbox: Vec::new(),
+			stats: NetworkStats::default(),
+		}
+	}
+
+	/// Empties the outbox into a batch, `None` if nothing was queued.
+	pub fn take_batch(&mut self) -> Option<MessageBatch<ClientMsg>> {
+		if self.outbox.is_empty() {
+			return None;
+		}
+
+		Some(MessageBatch(std::mem::take(&mut self.outbox)))
+	}
+
+	/// Sends `batch` as a single frame.
+	pub fn send_batch(&mut self, batch: MessageBatch<ClientMsg>) -> Result<(), NetworkError> {
+		let messages = batch.0.len();
+		self.transport
+			.send(batch)
+			.map_err(|_| NetworkError::SendFailed { client_id: None, messages })?;
+
+		Ok(())
+	}
 }
 
 #[cfg(feature = "server")]
 #[derive(Resource)]
 pub struct ServerSn {
-	pub simplenet: Server<NetworkChannel>,
+	pub transport: ServerTransport,
 	pub message_channel_buckets: HashMap<ChannelId, Vec<(ClientId, ClientMsg)>>,
 	pub client_connections: HashSet<u128>,
+	/// Messages queued for each client during [`ServerSet::Send`], flushed as one [`MessageBatch`] each.
+	pub outbox: HashMap<ClientId, Vec<ServerMsg>>,
+	/// Number of malformed messages received from each client, see [`MAX_MALFORMED_MESSAGES`].
+	pub malformed_messages: HashMap<ClientId, u32>,
+	/// Limits each client's messages on channels with a [`ChannelConfig::rate_limit`].
+	pub rate_limiters: HashMap<(ClientId, ChannelId), RateLimiter>,
+	/// Team each client plays on, assigned by game code and used by [`SendMode::ToTeam`](super::events::server::SendMode::ToTeam).
+	pub teams: HashMap<ClientId, TeamId>,
+	pub stats: NetworkStats,
+}
+
+#[cfg(feature = "server")]
+impl ServerSn {
+	pub fn new(transport: ServerTransport) -> Self {
+		Self {
+			transport,
+			message_channel_buckets: HashMap::new(),
+			client_connections: HashSet::new(),
+			outbox: HashMap::new(),
+			malformed_messages: HashMap::new(),
+			rate_limiters: HashMap::new(),
+			teams: HashMap::new(),
+			stats: NetworkStats::default(),
+		}
+	}
+
+	/// Sends the messages queued for `client_id` right away as a single frame.
+	pub fn flush_client(&mut self, client_id: ClientId) -> Result<(), NetworkError> {
+		match self.outbox.remove(&client_id).filter(|messages| !messages.is_empty()) {
+			Some(messages) => self.send_batch(client_id, MessageBatch(messages)),
+			None => Ok(()),
+		}
+	}
+
+	/// Empties every client's outbox into one batch per client.
+	pub fn take_batches(&mut self) -> Vec<(ClientId, MessageBatch<ServerMsg>)> {
+		self.outbox
+			.drain()
+			.filter(|(_, messages)| !messages.is_empty())
+			.map(|(client_id, messages)| (client_id, MessageBatch(messages)))
+			.collect()
+	}
+
+	/// Sends `batch` to `client_id` as a single frame.
+	pub fn send_batch(&mut self, client_id: ClientId, batch: MessageBatch<ServerMsg>) -> Result<(), NetworkError> {
+		let messages = batch.0.len();
+		self.transport
+			.send(client_id, batch)
+			.map_err(|_| NetworkError::SendFailed { client_id: Some(client_id), messages })?;
+
+		Ok(())
+	}
+
+	/// Logs a bad message from `client_id` and disconnects the client once it sent too many.
+	pub fn report_malformed(&mut self, client_id: ClientId, error: NetworkError) {
+		let count = self.malformed_messages.entry(client_id).or_default();
+		*count += 1;
+
+		warn!("malformed message from client {client_id} ({count}/{MAX_MALFORMED_MESSAGES}): {error}");
+
+		if *count >= MAX_MALFORMED_MESSAGES {
+			warn!("kicking client {client_id} for sending too many malformed messages");
+			self.transport.disconnect_client(client_id);
+		}
+	}
+}
+
+/// Port servers listen on unless started with a different `--listen` address.
+pub const DEFAULT_PORT: u16 = 38727;
+
+/// Websocket URL of the server we are playing on, reused by every reconnect attempt.
+#[cfg(feature = "client")]
+#[derive(Resource, Debug, Clone)]
+pub struct ServerUrl(pub url::Url);
+
+/// Turns what a player typed into a server URL. Accepts a full `ws://` or `wss://` URL, or just
+/// `host[:port]`, which defaults to [`DEFAULT_PORT`].
+#[cfg(feature = "client")]
+pub fn parse_server_address(address: &str) -> Result<url::Url, String> {
+	let address = address.trim();
+	if address.is_empty() {
+		return Err(String::from("server address is empty"));
+	}
+
+	if address.contains("://") {
+		let url = url::Url::parse(address).map_err(|error| format!("invalid server url: {error}"))?;
+		return match url.scheme() {
+			"ws" | "wss" if url.host().is_some() => Ok(url),
+			"ws" | "wss" => Err(String::from("server url has no host")),
+			scheme => Err(format!("server url should be ws:// or wss://, not {scheme}://")),
+		};
+	}
+
+	let mut url = url::Url::parse(&format!("ws://{address}/ws"))
+		.map_err(|error| format!("invalid server address: {error}"))?;
+	if url.port().is_none() {
+		url.set_port(Some(DEFAULT_PORT)).map_err(|_| String::from("invalid server address"))?;
+	}
+
+	Ok(url)
+}
+
+/// Opens a new connection to the server at `url`. Reconnects reuse `client_id` so the server can
+/// match us to our previous session.
+#[cfg(feature = "client")]
+pub fn connect_client(
+	client_id: ClientId,
+	url: &url::Url,
+	cli: &Cli,
+	channel_manager: &ChannelManager,
+	session_token: Option<SessionToken>,
+) -> ClientSn {
+	let client = ClientFactory::<NetworkChannel>::new(env!("CARGO_PKG_VERSION")).new_client(
+		enfync::builtin::Handle::default(), //automatically selects native/WASM runtime
+		url.clone(),
+		match cli.auth_secret {
+			Some(secret) => AuthRequest::Secret { client_id, secret },
+			None => AuthRequest::None { client_id },
+		},
+		ClientConfig {
+			// Reconnects are handled by `reconnect_system` so we can back off
+			reconnect_on_disconnect: false,
+			reconnect_on_server_close: false,
+			..Default::default()
+		},
+		connect_msg(cli, channel_manager, session_token),
+	);
+
+	ClientSn::new(ClientTransport::Websocket(client))
+}
+
+/// Same as [`connect_client`], but to a server running in this process.
+#[cfg(all(feature = "client", feature = "server"))]
+pub fn connect_local_client(
+	server: &MemoryServer,
+	client_id: ClientId,
+	cli: &Cli,
+	channel_manager: &ChannelManager,
+	session_token: Option<SessionToken>,
+) -> Result<ClientSn, ClientIdTaken> {
+	let client = server.connect(client_id, connect_msg(cli, channel_manager, session_token))?;
+
+	Ok(ClientSn::new(ClientTransport::Memory(client)))
+}
+
+#[cfg(feature = "client")]
+fn connect_msg(cli: &Cli, channel_manager: &ChannelManager, session_token: Option<SessionToken>) -> ConnectMsg {
+	ConnectMsg {
+		protocol_version: PROTOCOL_VERSION,
+		channels: channel_manager.fingerprints(),
+		client_build: String::from(env!("CARGO_PKG_VERSION")),
+		name: cli.name.clone(),
+		session_token,
+	}
 }
 
 pub fn client_connected() -> impl FnMut(Option<Res<ClientSn>>) -> bool {
 	|client| match client {
-		Some(client) => client.simplenet.is_connected(),
+		Some(client) => client.transport.is_connected(),
 		None => false,
 	}
 }
@@ -172,13 +431,58 @@ pub fn client_connected() -> impl FnMut(Option<Res<ClientSn>>) -> bool {
 #[allow(unused)]
 pub fn client_disconnected() -> impl FnMut(Option<Res<ClientSn>>) -> bool {
 	|client| match client {
-		Some(client) => client.simplenet.is_connected(),
+		Some(client) => client.transport.is_connected(),
 		None => true,
 	}
 }
 
+/// Condition that returns `true` when this app is hosting a server.
+#[cfg(feature = "server")]
+pub fn server_running() -> impl FnMut(Option<Res<ServerSn>>) -> bool + Clone {
+	move |server| server.is_some()
+}
+
+#[cfg(not(feature = "server"))]
+pub fn server_running() -> impl FnMut() -> bool + Clone {
+	|| false
+}
+
+/// Condition that returns `true` when this app runs a listen server, playing on it as [`SERVER_ID`]
+/// instead of connecting to it.
+#[cfg(all(feature = "client", feature = "server"))]
+pub fn hosting() -> impl FnMut(Option<Res<ServerSn>>, Option<Res<ClientSn>>) -> bool + Clone {
+	move |server, client| server.is_some() && client.is_none()
+}
+
 /// Condition that returns `true` for server or in singleplayer and `false` for client.
 pub fn has_authority() -> impl FnMut(Option<Res<ClientSn>>) -> bool + Clone {
 	move |client| client.is_none()
 }
 
+
+#[cfg(all(test, feature = "client"))]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn server_address_defaults_to_websocket_on_the_default_port() {
+		let url = parse_server_address(" example.com ").unwrap();
+		assert_eq!(url.as_str(), format!("ws://example.com:{DEFAULT_PORT}/ws"));
+
+		let url = parse_server_address("127.0.0.1:4000").unwrap();
+		assert_eq!(url.as_str(), "ws://127.0.0.1:4000/ws");
+	}
+
+	#[test]
+	fn server_address_keeps_full_urls() {
+		let url = parse_server_address("wss://example.com/play").unwrap();
+		assert_eq!(url.as_str(), "wss://example.com/play");
+	}
+
+	#[test]
+	fn server_address_rejects_other_schemes_and_garbage() {
+		assert!(parse_server_address("").is_err());
+		assert!(parse_server_address("http://example.com").is_err());
+		assert!(parse_server_address("example.com:notaport").is_err());
+	}
+}
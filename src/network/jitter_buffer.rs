@@ -0,0 +1,176 @@
+use std::collections::VecDeque;
+
+use serde::{Deserialize, Serialize};
+
+use super::tick::RepliconTick;
+
+/// How far past the current tick a value may be stamped before it is thrown away.
+pub const MAX_FUTURE_TICKS: u32 = 32;
+
+/// What happened to the values fed into a [`JitterBuffer`] since the last report.
+#[derive(Serialize, Deserialize, Clone, Copy, Default, Debug, PartialEq, Eq)]
+pub struct BufferHealth {
+	/// Values waiting for a tick that hasn't been simulated yet.
+	pub buffered: u32,
+	/// Values that arrived after their tick was simulated.
+	pub late: u32,
+	/// Values for a tick that already had one.
+	pub duplicate: u32,
+	/// Values stamped more than [`MAX_FUTURE_TICKS`] ahead.
+	pub early: u32,
+	/// Ticks that had no value, so the previous one was repeated.
+	pub missing: u32,
+}
+
+/// Holds tick-stamped values from one sender until the tick they belong to is simulated.
+#[derive(Debug)]
+pub struct JitterBuffer<T> {
+	/// Sorted by tick, oldest first.
+	queued: VecDeque<(RepliconTick, T)>,
+	/// Last value handed out by [`Self::take`].
+	last: Option<T>,
+	health: BufferHealth,
+}
+
+impl<T> Default for JitterBuffer<T> {
+	fn default() -> Self {
+		Self {
+			queued: VecDeque::new(),
+			last: None,
+			health: BufferHealth::default(),
+		}
+	}
+}
+
+impl<T: Clone> JitterBuffer<T> {
+	/// Queues `value` for `tick`, `now` being the last tick that was simulated.
+	pub fn insert(&mut self, now: RepliconTick, tick: RepliconTick, value: T) {
+		if tick <= now {
+			self.health.late += 1;
+			return;
+		}
+
+		let mut horizon = now;
+		horizon.increment_by(MAX_FUTURE_TICKS);
+		if tick > horizon {
+			self.health.early += 1;
+			return;
+		}
+
+		match self.queued.iter().position(|(queued_tick, _)| *queued_tick >= tick) {
+			Some(index) if self.queued[index].0 == tick => self.health.duplicate += 1,
+			Some(index) => self.queued.insert(index, (tick, value)),
+			None => self.queued.push_back((tick, value)),
+		}
+	}
+
+	/// Value for `tick`, or the previous one repeated if it never arrived.
+	pub fn take(&mut self, tick: RepliconTick) -> Option<T> {
+		// Leftovers from ticks we skipped past are useless now
+		while self.queued.front().is_some_and(|(queued_tick, _)| *queued_tick < tick) {
+			self.queued.pop_front();
+			self.health.late += 1;
+		}
+
+		match self.queued.front() {
+			Some((queued_tick, _)) if *queued_tick == tick => {
+				let (_, value) = self.queued.pop_front().unwrap();
+				self.last = Some(value);
+			}
+			_ => self.health.missing += 1,
+		}
+
+		self.last.clone()
+	}
+
+	/// Last value handed out by [`Self::take`].
+	pub fn last(&self) -> Option<&T> {
+		self.last.as_ref()
+	}
+
+	/// Health since the previous call, resetting the counters.
+	pub fn take_health(&mut self) -> BufferHealth {
+		let health = BufferHealth {
+			buffered: self.queued.len() as u32,
+			..self.health
+		};
+		self.health = BufferHealth::default();
+
+		health
+	}
+}
+
+#[cfg(all(test, feature = "server"))]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn hands_out_values_on_their_tick() {
+		let mut buffer = JitterBuffer::default();
+		buffer.insert(RepliconTick(10), RepliconTick(12), "b");
+		buffer.insert(RepliconTick(10), RepliconTick(11), "a");
+
+		assert_eq!(buffer.take(RepliconTick(11)), Some("a"));
+		assert_eq!(buffer.take(RepliconTick(12)), Some("b"));
+		assert_eq!(buffer.take_health(), BufferHealth::default());
+	}
+
+	#[test]
+	fn repeats_the_last_value_when_one_is_missing() {
+		let mut buffer = JitterBuffer::default();
+		assert_eq!(buffer.take(RepliconTick(1)), None);
+
+		buffer.insert(RepliconTick(1), RepliconTick(2), "a");
+		assert_eq!(buffer.take(RepliconTick(2)), Some("a"));
+		assert_eq!(buffer.take(RepliconTick(3)), Some("a"));
+		assert_eq!(buffer.last(), Some(&"a"));
+		assert_eq!(buffer.take_health().missing, 2);
+	}
+
+	#[test]
+	fn counts_late_early_and_duplicate_values() {
+		let mut buffer = JitterBuffer::default();
+		let now = RepliconTick(100);
+		buffer.insert(now, RepliconTick(100), "late");
+		buffer.insert(now, RepliconTick(100 + MAX_FUTURE_TICKS + 1), "early");
+		buffer.insert(now, RepliconTick(101), "a");
+		buffer.insert(now, RepliconTick(101), "duplicate");
+		buffer.insert(now, RepliconTick(103), "c");
+
+		assert_eq!(
+			buffer.take_health(),
+			BufferHealth {
+				buffered: 2,
+				late: 1,
+				duplicate: 1,
+				early: 1,
+				missing: 0,
+			}
+		);
+		assert_eq!(buffer.take(RepliconTick(101)), Some("a"));
+		assert_eq!(buffer.take_health().buffered, 1);
+	}
+
+	#[test]
+	fn drops_values_for_skipped_ticks() {
+		let mut buffer = JitterBuffer::default();
+		buffer.insert(RepliconTick(0), RepliconTick(1), "a");
+		buffer.insert(RepliconTick(0), RepliconTick(3), "c");
+
+		assert_eq!(buffer.take(RepliconTick(3)), Some("c"));
+		assert_eq!(buffer.take_health().late, 1);
+	}
+
+	#[test]
+	fn survives_tick_wrapping() {
+		let mut buffer = JitterBuffer::default();
+		let now = RepliconTick(u32::MAX - 1);
+		buffer.insert(now, RepliconTick(1), "b");
+		buffer.insert(now, RepliconTick(u32::MAX), "a");
+
+		assert_eq!(buffer.take(RepliconTick(u32::MAX)), Some("a"));
+		assert_eq!(buffer.take(RepliconTick(0)), Some("a"));
+		assert_eq!(buffer.take(RepliconTick(1)), Some("b"));
+		assert_eq!(buffer.take_health().missing, 1);
+	}
+}
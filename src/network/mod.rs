@@ -1,35 +1,77 @@
 pub mod helper;
 // mod events;
 pub mod channels_config;
+pub mod conditioner;
+pub mod connection;
+pub mod diagnostics;
+pub mod error;
 pub mod events;
+pub mod jitter_buffer;
+pub mod tick;
+pub mod transport;
+#[cfg(feature = "server")]
+pub mod violations;
 
 use bevy::prelude::*;
 
-use bevy::utils::{HashMap, HashSet};
-use bevy_simplenet::{AuthRequest, Authenticator};
-
 #[cfg(feature = "client")]
-use bevy_simplenet::{ClientConfig, ClientFactory};
+use bevy_simplenet::ClientReport;
 
 #[cfg(feature = "server")]
-use bevy_simplenet::{AcceptorConfig, ServerConfig, ServerFactory, ServerReport};
+use bevy_simplenet::{AcceptorConfig, Authenticator, ServerConfig, ServerFactory, ServerReport};
 use serde::{Deserialize, Serialize};
 
+#[cfg(feature = "server")]
 use crate::network::helper::NetworkChannel;
 
-use self::channels_config::ChannelManager;
+use self::channels_config::{ChannelManager, DeliveryMode};
+use self::connection::{
+	apply_connection_rejected, apply_session_assigned, ConnectionRejected, SessionAssigned,
+};
+#[cfg(feature = "client")]
+use self::connection::{
+	reconnect_system, reset_backoff, ClientConnectionState, DisconnectReason, ReconnectBackoff,
+};
+#[cfg(feature = "server")]
+use self::connection::{
+	expire_sessions_system, validate_connect_msg, EventSessionExpired, RejectReason, SessionOutcome,
+	Sessions,
+};
+use self::error::{NetworkError, NetworkErrorEvent};
 use self::events::server::ServerEventAppExt;
-use self::helper::ClientId;
+use self::conditioner::toggle_conditioner;
+#[cfg(feature = "client")]
+use self::conditioner::ClientConditioner;
+#[cfg(feature = "server")]
+use self::conditioner::ServerConditioner;
+use self::helper::{ClientId, MessageBatch, ServerMsg};
+use crate::Cli;
+#[cfg(feature = "server")]
+use self::helper::{ClientMsg, ConnectMsg};
 #[cfg(feature = "client")]
-use self::helper::{ClientSet, ClientSn, ConnectMsg};
+use self::helper::{
+	client_connected, connect_client, random_client_id, ClientSet, ClientSn, ServerUrl,
+};
 
 #[cfg(feature = "server")]
-use self::helper::{ServerSet, ServerSn};
+use self::helper::{EventChannel, ServerSet, ServerSn};
+#[cfg(feature = "server")]
+use self::transport::ServerTransport;
+#[cfg(feature = "server")]
+use self::violations::{enforce_violations, ClientViolation, RateLimiter, Violation, ViolationLog, Violations};
+#[cfg(all(feature = "client", feature = "server"))]
+use self::{
+	helper::{connect_local_client, SERVER_ID},
+	transport::MemoryServer,
+};
 
-// use self::tick::{LastRepliconTick, MinRepliconTick, RepliconTick};
+use self::tick::{increment_tick, RepliconTick};
+#[cfg(feature = "client")]
+use self::tick::{increment_client_tick, reset_client_ticks, ClientTick, LastRepliconTick};
 
+/// Sent on the server when a client connects, along with the display name it asked for.
 #[derive(Event, Debug, Clone, Serialize, Deserialize)]
-pub struct EventClientConnected(pub ClientId);
+pub struct EventClientConnected(pub ClientId, pub String);
 #[derive(Event, Debug, Clone, Serialize, Deserialize)]
 pub struct EventClientDisconnected(pub ClientId);
 
@@ -39,14 +81,28 @@ impl Plugin for NetworkPlugin {
 	fn build(&self, app: &mut App) {
 		app.add_systems(PreStartup, Self::startup)
 			.init_resource::<ChannelManager>()
-            .add_server_event::<InternalConnectionEvent>();
+			.add_event::<NetworkErrorEvent>()
+			.init_resource::<RepliconTick>()
+			.add_systems(FixedUpdate, increment_tick)
+			.add_systems(Update, toggle_conditioner)
+			.add_server_event::<InternalConnectionEvent>()
+			.add_server_event::<SessionAssigned>()
+			.add_server_event::<ConnectionRejected>()
+			.add_systems(Update, (apply_session_assigned, apply_connection_rejected));
 
 		#[cfg(feature = "client")]
 		app
-			// .init_resource::<LastRepliconTick>()
+			.init_resource::<LastRepliconTick>()
+			.init_resource::<ClientTick>()
+			.init_resource::<ClientConditioner>()
+			.add_systems(FixedUpdate, increment_client_tick)
+			.add_state::<ClientConnectionState>()
+			.add_systems(OnEnter(ClientConnectionState::Connecting), reset_client_ticks)
+			.add_systems(OnEnter(ClientConnectionState::Connected), reset_client_ticks)
+			.init_resource::<ReconnectBackoff>()
 			.configure_sets(
 				PreUpdate,
-				ClientSet::Receive, //.after(NetcodeClientPlugin::update_system),
+				(ClientSet::PreReceive, ClientSet::Receive).chain(), //.after(NetcodeClientPlugin::update_system),
 			)
 			.configure_sets(
 				PostUpdate,
@@ -57,158 +113,529 @@ impl Plugin for NetworkPlugin {
 				Self::client_reciving_messages_bucketer
 					.in_set(ClientSet::PreReceive)
 					.run_if(resource_exists::<ClientSn>()),
-			);
+			)
+			.add_systems(
+				PostUpdate,
+				Self::client_flush_system
+					.after(ClientSet::Send)
+					.run_if(client_connected()),
+			)
+			.add_systems(
+				Update,
+				reconnect_system.run_if(in_state(ClientConnectionState::Reconnecting)),
+			)
+			.add_systems(Update, reset_backoff.run_if(resource_exists::<crate::ClientIdResource>()));
 
 		#[cfg(feature = "server")]
 		app
-			// .init_resource::<RepliconTick>()
-			// .init_resource::<MinRepliconTick>()
+			.init_resource::<Sessions>()
+			.init_resource::<ServerConditioner>()
+			.init_resource::<Violations>()
+			.init_resource::<ViolationLog>()
+			.add_event::<ClientViolation>()
+			.configure_sets(PreUpdate, (ServerSet::PreRecieve, ServerSet::Receive).chain())
 			.add_systems(
 				PreUpdate,
 				Self::server_reciving_messages_bucketer
 					.in_set(ServerSet::PreRecieve)
 					.run_if(resource_exists::<ServerSn>()),
 			)
+			.add_systems(
+				PostUpdate,
+				Self::server_flush_system
+					.after(ServerSet::Send)
+					.run_if(resource_exists::<ServerSn>()),
+			)
+			.add_systems(
+				PostUpdate,
+				enforce_violations
+					.before(ServerSet::Send)
+					.run_if(resource_exists::<ServerSn>()),
+			)
+			.add_systems(Update, expire_sessions_system)
 			.add_event::<EventClientConnected>()
-			.add_event::<EventClientDisconnected>();
+			.add_event::<EventClientDisconnected>()
+			.add_event::<EventSessionExpired>();
+
+		#[cfg(all(feature = "client", feature = "server"))]
+		app.add_systems(Startup, join_as_host.run_if(|cli: Res<Cli>| cli.host));
 	}
 }
 
-#[derive(Debug, Deserialize, Event, Serialize, Clone)]
+/// Gives the host a ship, as if it had connected like everyone else.
+#[cfg(all(feature = "client", feature = "server"))]
+fn join_as_host(cli: Res<Cli>, mut client_connected_event: EventWriter<EventClientConnected>) {
+	info!("hosting as {}", cli.name);
+	client_connected_event.send(EventClientConnected(SERVER_ID, cli.name.clone()));
+}
+
+#[derive(Debug, Deserialize, Event, TypePath, Serialize, Clone)]
 pub enum InternalConnectionEvent {
     Connected(ClientId),
     Disconnected(ClientId),
 }
 
 impl NetworkPlugin {
-	fn startup(mut commands: Commands) {
-		#[cfg(feature = "server")]
-		{
-			let server = ServerFactory::<NetworkChannel>::new(env!("CARGO_PKG_VERSION"))
-				.new_server(
-					enfync::builtin::native::TokioHandle::default(),
-					"127.0.0.1:38727",
-					AcceptorConfig::Default,
-					Authenticator::None,
-					ServerConfig::default(),
-				);
-
-			dbg!(server.url());
-			dbg!(server.url().as_str());
-
-			let server = ServerSn {
-				simplenet: server,
-				message_channel_buckets: HashMap::new(),
-				client_connections: HashSet::new(),
-			};
-
-			commands.insert_resource(server);
+	fn startup(
+		mut commands: Commands,
+		cli: Res<Cli>,
+		channel_manager: Res<ChannelManager>,
+		#[cfg(feature = "client")] mut next_connection_state: ResMut<NextState<ClientConnectionState>>,
+	) {
+		if cli.dump_channels {
+			print!("{}", channel_manager.channel_table());
+			std::process::exit(0);
 		}
-		#[cfg(feature = "client")]
-		{
-			let client_id: u128 = rand::random();
-			let client = ClientFactory::<NetworkChannel>::new(env!("CARGO_PKG_VERSION"))
-				.new_client(
-					enfync::builtin::Handle::default(), //automatically selects native/WASM runtime
-					url::Url::parse("ws://127.0.0.1:38727/ws").unwrap(),
-					AuthRequest::None { client_id },
-					ClientConfig::default(),
-					ConnectMsg(String::from("hello")),
-				);
-
-			let client = ClientSn {
-				simplenet: client,
-				message_channel_buckets: HashMap::new(),
-			};
+		debug!("channel table:\n{}", channel_manager.channel_table());
 
+		// Offline play hosts the server in memory and joins it without opening any socket
+		#[cfg(all(feature = "client", feature = "server"))]
+		if cli.offline {
+			let server = MemoryServer::default();
+			let client_id = random_client_id();
+			let client = connect_local_client(&server, client_id, &cli, &channel_manager, None)
+				.expect("a new server should have no clients");
 			commands.insert_resource(client);
+			commands.insert_resource(crate::ClientIdResource(client_id));
+			commands.insert_resource(ServerSn::new(ServerTransport::Memory(server)));
+			info!("playing offline");
+			return;
+		}
+
+		// A dedicated server always listens, a game only when asked to host
+		#[cfg(all(feature = "server", not(feature = "client")))]
+		commands.insert_resource(Self::listen(&cli));
+
+		// A listen server's own player is `SERVER_ID` and never connects, see `join_as_host`
+		#[cfg(all(feature = "client", feature = "server"))]
+		if cli.host {
+			commands.insert_resource(Self::listen(&cli));
+			commands.insert_resource(crate::ClientIdResource(SERVER_ID));
+			return;
+		}
+
+		// Otherwise the player picks a server in the browser, see `ServerBrowserPlugin`
+		#[cfg(feature = "client")]
+		if let Some(url) = &cli.connect {
+			let client_id = random_client_id();
+			commands.insert_resource(connect_client(client_id, url, &cli, &channel_manager, None));
+			commands.insert_resource(ServerUrl(url.clone()));
+			commands.insert_resource(crate::ClientIdResource(client_id));
+			next_connection_state.set(ClientConnectionState::Connecting);
 		}
 	}
 
+	/// Opens the websocket server on `--listen`.
 	#[cfg(feature = "server")]
+	fn listen(cli: &Cli) -> ServerSn {
+		let server = ServerFactory::<NetworkChannel>::new(env!("CARGO_PKG_VERSION")).new_server(
+			enfync::builtin::native::TokioHandle::default(),
+			cli.listen,
+			AcceptorConfig::Default,
+			match cli.auth_secret {
+				Some(secret) => Authenticator::Secret { secret },
+				None => Authenticator::None,
+			},
+			ServerConfig::default(),
+		);
+
+		info!(
+			"server listening on {} ({})",
+			server.url(),
+			if cli.auth_secret.is_some() { "secret required" } else { "open" }
+		);
+
+		ServerSn::new(ServerTransport::Websocket(server))
+	}
+
+	#[cfg(feature = "server")]
+	#[allow(clippy::too_many_arguments)]
 	pub fn server_reciving_messages_bucketer(
-		mut server: ResMut<ServerSn>, 
+		mut server: ResMut<ServerSn>,
+		mut sessions: ResMut<Sessions>,
+		channel_manager: Res<ChannelManager>,
+		session_channel: Res<EventChannel<SessionAssigned>>,
+		rejected_channel: Res<EventChannel<ConnectionRejected>>,
+		connection_channel: Res<EventChannel<InternalConnectionEvent>>,
 		mut client_connected_event: EventWriter<EventClientConnected>,
 		mut client_disconnected_event: EventWriter<EventClientDisconnected>,
+		violations: Res<Violations>,
+		mut violation_events: EventWriter<ClientViolation>,
+		mut conditioner: ResMut<ServerConditioner>,
+		time: Res<Time<Real>>,
 	) {
-        use crate::network::{events::server::send_server_event, channels_config::CONNECTION_EVENT_CHANNEL_ID};
+		use crate::network::events::server::send_server_event;
 
-		while let Some((client_id, message)) = server.simplenet.next() {
+		let mut arrived = Vec::new();
+		while let Some((client_id, message)) = server.transport.next_event() {
 			match message {
 				bevy_simplenet::ServerEvent::Report(report) => {
-					dbg!(&report);
-					match report {
-						ServerReport::Connected(_env, _connection_msg) => {
-							assert!(server.client_connections.insert(client_id));
-							client_connected_event.send(EventClientConnected(client_id));
-                            send_server_event::<InternalConnectionEvent>(
-                                &mut server, 
-                                CONNECTION_EVENT_CHANNEL_ID, 
-                                events::server::SendMode::Broadcast, 
-                                InternalConnectionEvent::Connected(client_id)
-                            )
+					let connection_event = match report {
+						ServerReport::Connected(_env, connect_msg) => {
+							if violations.is_banned(client_id) {
+								reject_client(&mut server, &rejected_channel, client_id, RejectReason::Banned);
+								continue;
+							}
+
+							let outcome = validate_connect_msg(client_id, &connect_msg, &channel_manager)
+								.map(|_| sessions.connect(client_id, connect_msg.session_token));
+
+							let token = match outcome {
+								Ok(SessionOutcome::New(token)) => Some(token),
+								Ok(SessionOutcome::Resumed) => None,
+								Ok(SessionOutcome::TookOver) => {
+									// The transport only lets one socket use an id, so the old one is gone
+									// even though its disconnect never showed up, only our state is left
+									info!("client {client_id} replaced its old connection");
+									server.client_connections.remove(&client_id);
+									Self::forget_connection(&mut server, client_id);
+									None
+								}
+								Ok(SessionOutcome::Rejected) if server.client_connections.contains(&client_id) => {
+									// The transport refuses a second socket for an id that is in use, so
+									// this can't be told apart from the live player's own connection.
+									// Disconnecting by id would kick the player, not the impostor.
+									warn!("ignoring a second connection for live client {client_id}");
+									continue;
+								}
+								Ok(SessionOutcome::Rejected) => {
+									// The owner's socket is gone, the only one left for the id is the impostor's
+									reject_client(&mut server, &rejected_channel, client_id, RejectReason::IdInUse);
+									continue;
+								}
+								Err(reason) => {
+									reject_client(&mut server, &rejected_channel, client_id, reason);
+									continue;
+								}
+							};
+
+							let ConnectMsg { name, client_build, .. } = connect_msg;
+							info!("client {client_id} connected (build {client_build})");
+							if !server.client_connections.insert(client_id) {
+								warn!("client {client_id} connected twice");
+							}
+
+							if let Some(token) = token {
+								if let Err(error) = send_server_event(
+									&mut server,
+									&session_channel,
+									&events::server::SendMode::Direct(client_id),
+									&SessionAssigned(token),
+								) {
+									error!("{error}");
+								}
+							}
+
+							client_connected_event.send(EventClientConnected(client_id, name));
+							InternalConnectionEvent::Connected(client_id)
 						}
 						ServerReport::Disconnected => {
-							assert!(server.client_connections.remove(&client_id));
+							// Rejected connections were never added, keep the real owner's session alive
+							if !server.client_connections.remove(&client_id) {
+								continue;
+							}
+
+							info!("client {client_id} disconnected");
+							sessions.disconnect(client_id);
+							Self::forget_connection(&mut server, client_id);
 							client_disconnected_event.send(EventClientDisconnected(client_id));
-                            send_server_event::<InternalConnectionEvent>(
-                                &mut server, 
-                                CONNECTION_EVENT_CHANNEL_ID, 
-                                events::server::SendMode::Broadcast, 
-                                InternalConnectionEvent::Disconnected(client_id)
-                            )
+							InternalConnectionEvent::Disconnected(client_id)
 						}
+					};
+
+					if let Err(error) = send_server_event(
+						&mut server,
+						&connection_channel,
+						&events::server::SendMode::Broadcast,
+						&connection_event,
+					) {
+						error!("{error}");
 					}
 				}
-				bevy_simplenet::ServerEvent::Msg(message) => {
-					if server.message_channel_buckets.get(&message.channel_id).is_none() {
-						assert!(server
-							.message_channel_buckets
-							.insert(message.channel_id, Vec::new())
-							.is_none());
+				bevy_simplenet::ServerEvent::Msg(batch) => {
+					if conditioner.enabled {
+						conditioner.inbound.send(time.elapsed(), (client_id, batch));
+					} else {
+						arrived.push((client_id, batch));
 					}
-					server
-						.message_channel_buckets
-						.get_mut(&message.channel_id)
-						.unwrap()
-						.push((client_id, message));
 				}
-				bevy_simplenet::ServerEvent::Request(_, _) => todo!(),
+				bevy_simplenet::ServerEvent::Request(token, _) => {
+					// We don't use requests, a client sending them is misbehaving
+					warn!("rejecting unexpected request from client {client_id}");
+					server.transport.reject(token);
+				}
+			}
+		}
+
+		// Anything still held back goes first once the simulation is switched off
+		let delayed = if conditioner.enabled {
+			conditioner.inbound.receive(time.elapsed())
+		} else {
+			conditioner.inbound.drain()
+		};
+
+		for (client_id, MessageBatch(messages)) in delayed.into_iter().chain(arrived) {
+			// Late arrivals from a connection that is already gone
+			if !server.client_connections.contains(&client_id) {
+				continue;
+			}
+
+			for message in messages {
+				if let Err(violation) =
+					bucket_client_message(&mut server, &channel_manager, client_id, message, time.elapsed())
+				{
+					violation_events.send(ClientViolation { client_id, violation });
+				}
+			}
+		}
+	}
+
+	/// Drops what the server kept for one connection of `client_id`, its session and ship stay.
+	#[cfg(feature = "server")]
+	fn forget_connection(server: &mut ServerSn, client_id: ClientId) {
+		server.outbox.remove(&client_id);
+		server.malformed_messages.remove(&client_id);
+		server.rate_limiters.retain(|(limited_client_id, _), _| *limited_client_id != client_id);
+		server.stats.connections.remove(&client_id);
+	}
+
+	/// Sends everything the server queued this tick, one frame per client.
+	#[cfg(feature = "server")]
+	pub fn server_flush_system(
+		mut server: ResMut<ServerSn>,
+		mut conditioner: ResMut<ServerConditioner>,
+		time: Res<Time<Real>>,
+	) {
+		let batches = server.take_batches();
+		let outgoing = if conditioner.enabled {
+			for batch in batches {
+				conditioner.outbound.send(time.elapsed(), batch);
+			}
+			conditioner.outbound.receive(time.elapsed())
+		} else {
+			let mut outgoing = conditioner.outbound.drain();
+			outgoing.extend(batches);
+			outgoing
+		};
+
+		for (client_id, batch) in outgoing {
+			if let Err(error) = server.send_batch(client_id, batch) {
+				error!("{error}");
+			}
+		}
+	}
+
+	/// Sends everything the client queued this tick as one frame.
+	#[cfg(feature = "client")]
+	pub fn client_flush_system(
+		mut client: ResMut<ClientSn>,
+		mut conditioner: ResMut<ClientConditioner>,
+		time: Res<Time<Real>>,
+		mut network_errors: EventWriter<NetworkErrorEvent>,
+	) {
+		let batch = client.take_batch();
+		let outgoing = if conditioner.enabled {
+			if let Some(batch) = batch {
+				conditioner.outbound.send(time.elapsed(), batch);
+			}
+			conditioner.outbound.receive(time.elapsed())
+		} else {
+			let mut outgoing = conditioner.outbound.drain();
+			outgoing.extend(batch);
+			outgoing
+		};
+
+		for batch in outgoing {
+			if let Err(error) = client.send_batch(batch) {
+				network_errors.send(NetworkErrorEvent(error));
 			}
 		}
 	}
 
 	#[cfg(feature = "client")]
-	pub fn client_reciving_messages_bucketer(mut client: ResMut<ClientSn>) {
-		while let Some(message) = client.simplenet.next() {
+	pub fn client_reciving_messages_bucketer(
+		mut client: ResMut<ClientSn>,
+		channel_manager: Res<ChannelManager>,
+		connection_state: Res<State<ClientConnectionState>>,
+		mut next_connection_state: ResMut<NextState<ClientConnectionState>>,
+		mut network_errors: EventWriter<NetworkErrorEvent>,
+		mut conditioner: ResMut<ClientConditioner>,
+		time: Res<Time<Real>>,
+	) {
+		let mut arrived = Vec::new();
+		while let Some(message) = client.transport.next_event() {
 			match message {
-				bevy_simplenet::ClientEvent::Report(data) => {
-					dbg!(data);
+				bevy_simplenet::ClientEvent::Report(report) => {
+					info!("connection report: {report:?}");
+
+					let next_state = match report {
+						_ if matches!(connection_state.get(), ClientConnectionState::Disconnected(_)) => {
+							// Being kicked or rejected is final, and we keep the more specific reason
+							continue;
+						}
+						ClientReport::Connected => ClientConnectionState::Connected,
+						// Turned away for now, see `apply_connection_rejected`
+						ClientReport::ClosedByServer(_)
+							if *connection_state.get() == ClientConnectionState::Reconnecting =>
+						{
+							continue;
+						}
+						ClientReport::ClosedByServer(_) => {
+							ClientConnectionState::Disconnected(DisconnectReason::ClosedByServer)
+						}
+						ClientReport::ClosedBySelf => continue,
+						ClientReport::Disconnected | ClientReport::IsDead(..) => ClientConnectionState::Reconnecting,
+					};
+
+					next_connection_state.set(next_state);
 				}
-				bevy_simplenet::ClientEvent::Msg(message) => {
-					if client.message_channel_buckets.get(&message.channel_id).is_none() {
-						assert!(client
-							.message_channel_buckets
-							.insert(message.channel_id, Vec::new())
-							.is_none());
+				bevy_simplenet::ClientEvent::Msg(batch) => {
+					if conditioner.enabled {
+						conditioner.inbound.send(time.elapsed(), batch);
+					} else {
+						arrived.push(batch);
 					}
+				}
+				// We never send requests, so there is nothing to match acks and responses against
+				bevy_simplenet::ClientEvent::Response(_, request_id) | bevy_simplenet::ClientEvent::Ack(request_id) => {
+					debug!("ignoring reply to unknown request {request_id:?}");
+				}
+				bevy_simplenet::ClientEvent::Reject(_) => {
+					network_errors.send(NetworkErrorEvent(NetworkError::Rejected));
+				}
+				bevy_simplenet::ClientEvent::SendFailed(_) => {
+					network_errors.send(NetworkErrorEvent(NetworkError::RequestSendFailed));
+				}
+				bevy_simplenet::ClientEvent::ResponseLost(_) => {
+					network_errors.send(NetworkErrorEvent(NetworkError::ResponseLost));
+				}
+			}
+		}
+
+		let delayed = if conditioner.enabled {
+			conditioner.inbound.receive(time.elapsed())
+		} else {
+			conditioner.inbound.drain()
+		};
 
-					client
-						.message_channel_buckets
-						.get_mut(&message.channel_id)
-						.unwrap()
-						.push(message);
+		for MessageBatch(messages) in delayed.into_iter().chain(arrived) {
+			for message in messages {
+				if let Err(error) = bucket_server_message(&mut client, &channel_manager, message) {
+					network_errors.send(NetworkErrorEvent(error));
 				}
-				bevy_simplenet::ClientEvent::Response(_, _) => todo!(),
-				bevy_simplenet::ClientEvent::Ack(_) => todo!(),
-				bevy_simplenet::ClientEvent::Reject(_) => todo!(),
-				bevy_simplenet::ClientEvent::SendFailed(_) => todo!(),
-				bevy_simplenet::ClientEvent::ResponseLost(_) => todo!(),
 			}
 		}
 	}
 }
 
+/// Files a message from `client_id` under its channel for the matching `receiving_system`.
+///
+/// Messages over the channel's rate limit are dropped and returned as a [`Violation`].
+#[cfg(feature = "server")]
+fn bucket_client_message(
+	server: &mut ServerSn,
+	channel_manager: &ChannelManager,
+	client_id: ClientId,
+	message: ClientMsg,
+	now: std::time::Duration,
+) -> Result<(), Violation> {
+	let Some(config) = channel_manager.client_channel(message.channel_id) else {
+		server.report_malformed(client_id, NetworkError::UnknownChannel(message.channel_id));
+		return Ok(());
+	};
+
+	if message.event.len() > config.max_bytes {
+		let error = NetworkError::MessageTooLarge {
+			channel_id: message.channel_id,
+			size: message.event.len(),
+			max_bytes: config.max_bytes,
+		};
+		server.report_malformed(client_id, error);
+		return Ok(());
+	}
+
+	if let Some(per_second) = config.rate_limit {
+		let limiter = server
+			.rate_limiters
+			.entry((client_id, message.channel_id))
+			.or_insert_with(|| RateLimiter::new(now, per_second));
+
+		if !limiter.try_take(now, per_second) {
+			return Err(Violation::RateLimited { channel_id: message.channel_id });
+		}
+	}
+
+	let bucket = server.message_channel_buckets.entry(message.channel_id).or_default();
+	if config.delivery == DeliveryMode::LatestOnly {
+		// Only this client's newest message is worth reading
+		bucket.retain(|(queued_client_id, _)| *queued_client_id != client_id);
+	}
+	server.stats.record_in(Some(client_id), message.channel_id, message.event.len());
+	bucket.push((client_id, message));
+
+	Ok(())
+}
+
+/// Files a message from the server under its channel for the matching `receiving_system`.
+#[cfg(feature = "client")]
+fn bucket_server_message(
+	client: &mut ClientSn,
+	channel_manager: &ChannelManager,
+	message: ServerMsg,
+) -> Result<(), NetworkError> {
+	let Some(config) = channel_manager.server_channel(message.channel_id) else {
+		return Err(NetworkError::UnknownChannel(message.channel_id));
+	};
+
+	if message.event.len() > config.max_bytes {
+		return Err(NetworkError::MessageTooLarge {
+			channel_id: message.channel_id,
+			size: message.event.len(),
+			max_bytes: config.max_bytes,
+		});
+	}
+
+	client.stats.record_in(None, message.channel_id, message.event.len());
+
+	let bucket = client.message_channel_buckets.entry(message.channel_id).or_default();
+	if config.delivery == DeliveryMode::LatestOnly {
+		bucket.clear();
+	}
+	bucket.push(message);
+
+	Ok(())
+}
+
+/// Tells a client why it can't join, or why it has to go, and closes its connection.
+#[cfg(feature = "server")]
+pub fn reject_client(
+	server: &mut ServerSn,
+	rejected_channel: &EventChannel<ConnectionRejected>,
+	client_id: ClientId,
+	reason: RejectReason,
+) {
+	warn!("rejecting client {client_id}: {reason}");
+
+	// Not `send_server_event`, a rejected client was never added to `client_connections`
+	match rejected_channel.serialize(&ConnectionRejected(reason)) {
+		Ok(event) => {
+			server.stats.record_out(Some(client_id), rejected_channel.channel_id, event.len());
+			server.outbox.entry(client_id).or_default().push(ServerMsg {
+				channel_id: rejected_channel.channel_id,
+				event,
+			});
+		}
+		Err(error) => error!("{error}"),
+	}
+
+	// The reason has to go out before the connection closes
+	if let Err(error) = server.flush_client(client_id) {
+		error!("{error}");
+	}
+
+	server.transport.disconnect_client(client_id);
+}
+
 // #[derive(Serialize, Deserialize, Clone, Debug)]
 // struct PlayerState {
 //     id: u32,
@@ -3,16 +3,14 @@ use std::cmp::Ordering;
 use bevy::prelude::*;
 use serde::{Deserialize, Serialize};
 
-/// Marks entity for replication.
-#[derive(Component, Clone, Copy, Default, Reflect)]
-#[reflect(Component)]
-pub struct Replication;
+/// Default simulation rate, see [`Cli::tick_rate`](crate::Cli::tick_rate).
+pub const DEFAULT_TICK_RATE: f64 = 60.0;
+/// Default snapshot rate, see [`Cli::snapshot_rate`](crate::Cli::snapshot_rate).
+pub const DEFAULT_SNAPSHOT_RATE: f64 = 20.0;
 
-/// A tick that increments each time we need the server to compute and send an update.
+/// A tick that increments once per `FixedUpdate`, the server's simulation step.
 ///
-/// Used as resource only on server.
-/// Mapped to the Bevy's `Tick` in [`AckedTicks`](crate::server::AckedTicks).
-/// See also [`TickPolicy`](crate::server::TickPolicy).
+/// Stamped on snapshots, clients stamp their inputs with [`ClientTick`] instead.
 #[derive(Clone, Copy, Debug, Default, Deserialize, Eq, Hash, PartialEq, Resource, Serialize)]
 pub struct RepliconTick(pub(crate) u32);
 
@@ -35,6 +33,12 @@ impl RepliconTick {
 	pub fn increment(&mut self) {
 		self.increment_by(1)
 	}
+
+	/// Steps the tick back by 1, taking wrapping into account.
+	#[inline]
+	pub fn decrement(&mut self) {
+		self.0 = self.0.wrapping_sub(1);
+	}
 }
 
 impl PartialOrd for RepliconTick {
@@ -50,19 +54,41 @@ impl PartialOrd for RepliconTick {
 	}
 }
 
-/// Last received tick from server.
+/// Tick of the newest snapshot received from the server.
 ///
-/// Used only on clients, sent to the server in last replicon ack message.
+/// Used only on clients.
 #[derive(Debug, Default, Deref, Resource)]
-pub struct LastRepliconTick(pub(super) RepliconTick);
-/// Contains the lowest replicon tick that should be acknowledged by clients.
+pub struct LastRepliconTick(pub(crate) RepliconTick);
+
+/// The tick a client stamps its inputs with.
 ///
-/// If a client has not acked this tick, then replication messages >= this tick
-/// will be sent even if they do not contain data.
+/// Runs a few ticks ahead of the server's [`RepliconTick`] so inputs are already buffered when the
+/// server simulates their tick, steered by the buffer health the server reports back.
+#[derive(Debug, Default, Deref, DerefMut, Resource)]
+pub struct ClientTick(pub RepliconTick);
+
+/// Advances [`ClientTick`], runs first in `FixedUpdate` on clients.
+pub fn increment_client_tick(mut tick: ResMut<ClientTick>) {
+	tick.increment();
+}
+
+/// Forgets the ticks of the previous session whenever a connection is being made, the server may
+/// have restarted since and be far behind them. Otherwise every snapshot would look stale and the
+/// inputs stamped with the old view tick would be rejected as coming from the future.
 ///
-/// Used to synchronize server-sent events with clients. A client cannot consume
-/// a server-sent event until it has acknowledged the tick where that event was
-/// created. This means we need to replicate ticks after a server-sent event is
-/// emitted to guarantee the client can eventually consume the event.
-#[derive(Clone, Copy, Debug, Default, Deref, DerefMut, Resource)]
-pub(super) struct MinRepliconTick(RepliconTick);
+/// The first snapshot sets a new [`LastRepliconTick`], the first
+/// [`InputBufferHealth`](crate::player::InputBufferHealth) a new lead.
+pub fn reset_client_ticks(
+	mut last_tick: ResMut<LastRepliconTick>,
+	mut client_tick: ResMut<ClientTick>,
+) {
+	*last_tick = LastRepliconTick::default();
+	*client_tick = ClientTick::default();
+}
+
+/// Advances [`RepliconTick`], runs first in `FixedUpdate`.
+///
+/// Every system that reads the tick in `FixedUpdate` should run `.after(increment_tick)`.
+pub fn increment_tick(mut tick: ResMut<RepliconTick>) {
+	tick.increment();
+}
@@ -0,0 +1,235 @@
+#[cfg(feature = "server")]
+use std::collections::VecDeque;
+#[cfg(feature = "server")]
+use std::sync::{Arc, Mutex, MutexGuard};
+
+#[cfg(feature = "server")]
+use bevy::utils::{HashMap, HashSet};
+use bevy_simplenet::{Client, ClientEventFrom};
+#[cfg(feature = "server")]
+use bevy_simplenet::{
+	ClientEvent, ClientReport, EnvType, RequestToken, Server, ServerEvent, ServerEventFrom, ServerReport,
+};
+
+#[cfg(feature = "server")]
+use super::helper::{ClientId, ConnectMsg, ServerMsg};
+use super::helper::{ClientMsg, MessageBatch, NetworkChannel};
+
+/// The connection is closed, nothing was sent.
+#[derive(Debug)]
+pub struct TransportClosed;
+
+/// Another client is already connected to the [`MemoryServer`] with this id.
+#[cfg(feature = "server")]
+#[derive(Debug)]
+pub struct ClientIdTaken(pub ClientId);
+
+/// What [`ClientSn`](super::helper::ClientSn) talks to the server through.
+pub enum ClientTransport {
+	Websocket(Client<NetworkChannel>),
+	/// Connected to a [`MemoryServer`] in this process.
+	#[cfg(feature = "server")]
+	Memory(MemoryClient),
+}
+
+impl ClientTransport {
+	pub fn next_event(&mut self) -> Option<ClientEventFrom<NetworkChannel>> {
+		match self {
+			Self::Websocket(client) => client.next(),
+			#[cfg(feature = "server")]
+			Self::Memory(client) => client.next(),
+		}
+	}
+
+	pub fn send(&self, batch: MessageBatch<ClientMsg>) -> Result<(), TransportClosed> {
+		match self {
+			Self::Websocket(client) => client.send(batch).map(|_| ()).map_err(|_| TransportClosed),
+			#[cfg(feature = "server")]
+			Self::Memory(client) => client.send(batch),
+		}
+	}
+
+	pub fn is_connected(&self) -> bool {
+		match self {
+			Self::Websocket(client) => client.is_connected(),
+			#[cfg(feature = "server")]
+			Self::Memory(client) => client.is_connected(),
+		}
+	}
+
+	/// `true` once the connection can't come back by itself.
+	pub fn is_dead(&self) -> bool {
+		match self {
+			Self::Websocket(client) => client.is_dead(),
+			#[cfg(feature = "server")]
+			Self::Memory(client) => !client.is_connected(),
+		}
+	}
+}
+
+/// What [`ServerSn`](super::helper::ServerSn) talks to clients through.
+#[cfg(feature = "server")]
+pub enum ServerTransport {
+	Websocket(Server<NetworkChannel>),
+	Memory(MemoryServer),
+}
+
+#[cfg(feature = "server")]
+impl ServerTransport {
+	pub fn next_event(&mut self) -> Option<(ClientId, ServerEventFrom<NetworkChannel>)> {
+		match self {
+			Self::Websocket(server) => server.next(),
+			Self::Memory(server) => server.next(),
+		}
+	}
+
+	pub fn send(&self, client_id: ClientId, batch: MessageBatch<ServerMsg>) -> Result<(), TransportClosed> {
+		match self {
+			Self::Websocket(server) => server.send(client_id, batch).map_err(|_| TransportClosed),
+			Self::Memory(server) => server.send(client_id, batch),
+		}
+	}
+
+	pub fn disconnect_client(&self, client_id: ClientId) {
+		match self {
+			Self::Websocket(server) => server.disconnect_client(client_id),
+			Self::Memory(server) => server.disconnect_client(client_id),
+		}
+	}
+
+	pub fn reject(&self, token: RequestToken) {
+		match self {
+			Self::Websocket(server) => server.reject(token),
+			// Memory clients have no way to send requests
+			Self::Memory(_) => {}
+		}
+	}
+}
+
+/// Queues shared by a [`MemoryServer`] and its clients.
+#[cfg(feature = "server")]
+#[derive(Default)]
+struct MemoryHub {
+	to_server: VecDeque<(ClientId, ServerEventFrom<NetworkChannel>)>,
+	to_clients: HashMap<ClientId, VecDeque<ClientEventFrom<NetworkChannel>>>,
+	connected: HashSet<ClientId>,
+}
+
+/// Server end of the in-memory transport, for offline play and for running a server and clients
+/// inside one process without sockets.
+///
+/// Nothing is delivered in the background: messages wait in a queue until the other side asks
+/// for them, so apps sharing a server can be stepped one after another deterministically.
+/// Clones are handles to the same server.
+#[cfg(feature = "server")]
+#[derive(Clone, Default)]
+pub struct MemoryServer(Arc<Mutex<MemoryHub>>);
+
+#[cfg(feature = "server")]
+impl MemoryServer {
+	fn hub(&self) -> MutexGuard<'_, MemoryHub> {
+		self.0.lock().expect("memory transport should not be poisoned")
+	}
+
+	/// Opens a connection as `client_id`, the server sees it with its next [`ServerTransport::next`].
+	///
+	/// Fails while another connection uses `client_id`, like a websocket server refusing the
+	/// second socket for an id.
+	pub fn connect(&self, client_id: ClientId, connect_msg: ConnectMsg) -> Result<MemoryClient, ClientIdTaken> {
+		let mut hub = self.hub();
+		if !hub.connected.insert(client_id) {
+			return Err(ClientIdTaken(client_id));
+		}
+		hub.to_clients
+			.insert(client_id, VecDeque::from([ClientEvent::Report(ClientReport::Connected)]));
+		hub.to_server.push_back((
+			client_id,
+			ServerEvent::Report(ServerReport::Connected(EnvType::Native, connect_msg)),
+		));
+
+		Ok(MemoryClient {
+			client_id,
+			hub: self.0.clone(),
+		})
+	}
+
+	fn next(&self) -> Option<(ClientId, ServerEventFrom<NetworkChannel>)> {
+		self.hub().to_server.pop_front()
+	}
+
+	fn send(&self, client_id: ClientId, batch: MessageBatch<ServerMsg>) -> Result<(), TransportClosed> {
+		let mut hub = self.hub();
+		if !hub.connected.contains(&client_id) {
+			return Err(TransportClosed);
+		}
+
+		hub.to_clients
+			.entry(client_id)
+			.or_default()
+			.push_back(ClientEvent::Msg(batch));
+
+		Ok(())
+	}
+
+	fn disconnect_client(&self, client_id: ClientId) {
+		let mut hub = self.hub();
+		if !hub.connected.remove(&client_id) {
+			return;
+		}
+
+		// Whatever was sent before stays readable, same as a websocket closing after its last frame
+		hub.to_clients
+			.entry(client_id)
+			.or_default()
+			.push_back(ClientEvent::Report(ClientReport::ClosedByServer(None)));
+		hub.to_server
+			.push_back((client_id, ServerEvent::Report(ServerReport::Disconnected)));
+	}
+}
+
+/// Client end of the in-memory transport, see [`MemoryServer::connect`].
+///
+/// Dropping it closes the connection.
+#[cfg(feature = "server")]
+pub struct MemoryClient {
+	client_id: ClientId,
+	hub: Arc<Mutex<MemoryHub>>,
+}
+
+#[cfg(feature = "server")]
+impl MemoryClient {
+	fn hub(&self) -> MutexGuard<'_, MemoryHub> {
+		self.hub.lock().expect("memory transport should not be poisoned")
+	}
+
+	fn next(&self) -> Option<ClientEventFrom<NetworkChannel>> {
+		self.hub().to_clients.get_mut(&self.client_id)?.pop_front()
+	}
+
+	fn send(&self, batch: MessageBatch<ClientMsg>) -> Result<(), TransportClosed> {
+		let mut hub = self.hub();
+		if !hub.connected.contains(&self.client_id) {
+			return Err(TransportClosed);
+		}
+
+		hub.to_server.push_back((self.client_id, ServerEvent::Msg(batch)));
+
+		Ok(())
+	}
+
+	fn is_connected(&self) -> bool {
+		self.hub().connected.contains(&self.client_id)
+	}
+}
+
+#[cfg(feature = "server")]
+impl Drop for MemoryClient {
+	fn drop(&mut self) {
+		let mut hub = self.hub();
+		hub.to_clients.remove(&self.client_id);
+		if hub.connected.remove(&self.client_id) {
+			hub.to_server
+				.push_back((self.client_id, ServerEvent::Report(ServerReport::Disconnected)));
+		}
+	}
+}
@@ -0,0 +1,330 @@
+use std::fs::File;
+use std::io::BufWriter;
+use std::path::PathBuf;
+use std::time::Duration;
+
+use bevy::prelude::*;
+use bevy::utils::{HashMap, HashSet};
+use serde::Serialize;
+
+use super::connection::{ConnectionRejected, RejectReason, Sessions};
+use super::helper::{ChannelId, ClientId, EventChannel, ServerSn};
+use super::reject_client;
+use crate::Cli;
+
+/// Violation score a client is kicked at, unless overridden with `--kick-score`.
+pub const DEFAULT_KICK_SCORE: f32 = 20.0;
+/// Kicks after which a client is banned, unless overridden with `--ban-after-kicks`.
+pub const DEFAULT_BAN_AFTER_KICKS: u32 = 3;
+/// Score forgiven every second, so an honest client tripping a check now and then never adds up
+/// to a kick.
+const SCORE_DECAY_PER_SECOND: f32 = 0.5;
+/// How often the [`ViolationLog`] is flushed to disk. A flood of violations shouldn't mean a
+/// write for every one of them.
+const VIOLATION_LOG_FLUSH_SECONDS: f32 = 1.0;
+/// Seconds worth of messages a [`RateLimiter`] lets through at once, e.g. after a lag spike.
+const RATE_LIMIT_BURST_SECONDS: f32 = 2.0;
+
+/// Something a client sent that an honest client never would.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize)]
+pub enum Violation {
+	/// Sent faster than the channel's [`rate_limit`](super::channels_config::ChannelConfig::rate_limit)
+	/// allows, the message was dropped.
+	RateLimited { channel_id: ChannelId },
+	/// Claimed to be looking at a snapshot the server hasn't simulated yet.
+	ViewTickFromFuture,
+	/// Stamped input for a tick too far ahead to ever be applied.
+	InputTooEarly,
+	/// Pulled the trigger before the weapon cooled down, which the client checks on its own.
+	FiredDuringCooldown,
+	/// Sent an admin command with the wrong password.
+	AdminAuthFailed,
+}
+
+impl Violation {
+	/// How much the violation adds to the client's score. Dropped messages weigh little because
+	/// a single flood drops plenty of them.
+	pub fn severity(&self) -> f32 {
+		match self {
+			Violation::RateLimited { .. } => 0.1,
+			Violation::InputTooEarly => 0.5,
+			Violation::FiredDuringCooldown => 1.0,
+			Violation::ViewTickFromFuture => 2.0,
+			Violation::AdminAuthFailed => 5.0,
+		}
+	}
+}
+
+impl std::fmt::Display for Violation {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		match self {
+			Violation::RateLimited { channel_id } => write!(f, "rate limit exceeded on channel {channel_id:08x}"),
+			Violation::ViewTickFromFuture => write!(f, "view tick from the future"),
+			Violation::InputTooEarly => write!(f, "input stamped too far ahead"),
+			Violation::FiredDuringCooldown => write!(f, "fired during cooldown"),
+			Violation::AdminAuthFailed => write!(f, "wrong admin password"),
+		}
+	}
+}
+
+/// Sent by whatever check caught a client misbehaving, handled by [`enforce_violations`].
+#[derive(Event, Clone, Copy, Debug)]
+pub struct ClientViolation {
+	pub client_id: ClientId,
+	pub violation: Violation,
+}
+
+/// Token bucket limiting how many messages one client sends on one channel.
+#[derive(Debug)]
+pub struct RateLimiter {
+	tokens: f32,
+	updated_at: Duration,
+}
+
+impl RateLimiter {
+	pub fn new(now: Duration, per_second: f32) -> Self {
+		Self {
+			tokens: per_second * RATE_LIMIT_BURST_SECONDS,
+			updated_at: now,
+		}
+	}
+
+	/// Spends a token on one message, `false` if the client is over its budget.
+	pub fn try_take(&mut self, now: Duration, per_second: f32) -> bool {
+		let elapsed = now.saturating_sub(self.updated_at).as_secs_f32();
+		self.updated_at = now;
+		self.tokens = (self.tokens + elapsed * per_second).min(per_second * RATE_LIMIT_BURST_SECONDS);
+
+		if self.tokens < 1.0 {
+			return false;
+		}
+
+		self.tokens -= 1.0;
+		true
+	}
+}
+
+/// Server side record of misbehaving clients.
+///
+/// Every [`Violation`] adds to a client's score, which slowly decays. A client reaching
+/// [`Self::kick_score`] is kicked, and banned once it was kicked [`Self::ban_after_kicks`] times.
+/// Bans are by client id and last until the server restarts.
+///
+/// The client id is picked by the client itself and the transport doesn't tell the server where a
+/// connection comes from, so a banned player gets back in just by restarting their client. Bans
+/// only keep out clients that reconnect with the same id, e.g. to resume their session. Use
+/// `--auth-secret` to keep a server private.
+#[derive(Resource, Debug)]
+pub struct Violations {
+	scores: HashMap<ClientId, f32>,
+	kicks: HashMap<ClientId, u32>,
+	banned: HashSet<ClientId>,
+	pub kick_score: f32,
+	/// `0` never bans.
+	pub ban_after_kicks: u32,
+}
+
+impl FromWorld for Violations {
+	fn from_world(world: &mut World) -> Self {
+		let cli = world.resource::<Cli>();
+
+		Self {
+			scores: HashMap::new(),
+			kicks: HashMap::new(),
+			banned: HashSet::new(),
+			kick_score: cli.kick_score,
+			ban_after_kicks: cli.ban_after_kicks,
+		}
+	}
+}
+
+impl Violations {
+	pub fn is_banned(&self, client_id: ClientId) -> bool {
+		self.banned.contains(&client_id)
+	}
+
+	/// Bans `client_id` regardless of its score, it still has to be kicked separately.
+	pub fn ban(&mut self, client_id: ClientId) {
+		self.banned.insert(client_id);
+	}
+
+	/// Adds `violation` to the score of `client_id`, returning the new score and why the client
+	/// has to go if it went too far.
+	fn record(&mut self, client_id: ClientId, violation: Violation) -> (f32, Option<RejectReason>) {
+		let score = self.scores.entry(client_id).or_default();
+		*score += violation.severity();
+		let score = *score;
+
+		warn!("client {client_id} violation: {violation} (score {score:.1}/{:.1})", self.kick_score);
+
+		if score < self.kick_score {
+			return (score, None);
+		}
+
+		self.scores.remove(&client_id);
+		let kicks = self.kicks.entry(client_id).or_default();
+		*kicks += 1;
+
+		if self.ban_after_kicks > 0 && *kicks >= self.ban_after_kicks {
+			self.banned.insert(client_id);
+			return (score, Some(RejectReason::Banned));
+		}
+
+		(score, Some(RejectReason::Kicked))
+	}
+
+	fn decay(&mut self, seconds: f32) {
+		self.scores.retain(|_, score| {
+			*score -= SCORE_DECAY_PER_SECOND * seconds;
+			*score > 0.0
+		});
+	}
+}
+
+/// JSON lines file every recorded [`Violation`] ends up in, along with what was done about it.
+///
+/// Opened on the first violation and kept open, entries are buffered and flushed every
+/// [`VIOLATION_LOG_FLUSH_SECONDS`].
+#[derive(Resource)]
+pub struct ViolationLog {
+	path: PathBuf,
+	writer: Option<BufWriter<File>>,
+	flush_timer: Timer,
+}
+
+impl FromWorld for ViolationLog {
+	fn from_world(world: &mut World) -> Self {
+		Self {
+			path: world.resource::<Cli>().violation_log.clone(),
+			writer: None,
+			flush_timer: Timer::from_seconds(VIOLATION_LOG_FLUSH_SECONDS, TimerMode::Repeating),
+		}
+	}
+}
+
+#[derive(Serialize)]
+struct ViolationEntry {
+	/// Seconds since the unix epoch.
+	at: u64,
+	client_id: ClientId,
+	violation: Violation,
+	/// Score after the violation was added.
+	score: f32,
+	/// `Kicked` or `Banned` if the client had to go because of it.
+	outcome: Option<RejectReason>,
+}
+
+impl ViolationLog {
+	fn record(&mut self, entry: &ViolationEntry) {
+		if let Err(error) = self.append(entry) {
+			error!("failed to write violation log: {error:#}");
+		}
+	}
+
+	fn append(&mut self, entry: &ViolationEntry) -> anyhow::Result<()> {
+		use anyhow::Context;
+		use std::io::Write;
+
+		let writer = match &mut self.writer {
+			Some(writer) => writer,
+			None => {
+				let file = std::fs::OpenOptions::new()
+					.create(true)
+					.append(true)
+					.open(&self.path)
+					.with_context(|| format!("opening {}", self.path.display()))?;
+				self.writer.insert(BufWriter::new(file))
+			}
+		};
+		serde_json::to_writer(&mut *writer, entry)?;
+		writeln!(writer)?;
+
+		Ok(())
+	}
+
+	/// Flushes the buffered entries once the flush timer is up.
+	fn tick(&mut self, delta: Duration) {
+		use std::io::Write;
+
+		if !self.flush_timer.tick(delta).just_finished() {
+			return;
+		}
+		if let Some(Err(error)) = self.writer.as_mut().map(|writer| writer.flush()) {
+			error!("failed to flush violation log {}: {error}", self.path.display());
+			// Reopen on the next entry rather than keep writing into a broken handle
+			self.writer = None;
+		}
+	}
+}
+
+/// Scores every [`ClientViolation`] and kicks or bans clients that went over the limit. A kicked
+/// client's session ends with it, so it can't come straight back with its token.
+pub fn enforce_violations(
+	time: Res<Time>,
+	mut violations: ResMut<Violations>,
+	mut violation_events: EventReader<ClientViolation>,
+	mut server: ResMut<ServerSn>,
+	mut sessions: ResMut<Sessions>,
+	mut log: ResMut<ViolationLog>,
+	rejected_channel: Res<EventChannel<ConnectionRejected>>,
+) {
+	violations.decay(time.delta_seconds());
+
+	for ClientViolation { client_id, violation } in violation_events.read() {
+		// The listen server's own player, or a client that is already on its way out
+		if !server.client_connections.contains(client_id) {
+			continue;
+		}
+
+		let (score, outcome) = violations.record(*client_id, *violation);
+		log.record(&ViolationEntry {
+			at: std::time::SystemTime::now()
+				.duration_since(std::time::UNIX_EPOCH)
+				.map(|duration| duration.as_secs())
+				.unwrap_or_default(),
+			client_id: *client_id,
+			violation: *violation,
+			score,
+			outcome: outcome.clone(),
+		});
+
+		if let Some(reason) = outcome {
+			sessions.revoke(*client_id);
+			reject_client(&mut server, &rejected_channel, *client_id, reason);
+		}
+	}
+
+	log.tick(time.delta());
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn rate_limiter_allows_a_burst_then_refills() {
+		let start = Duration::from_secs(10);
+		let mut limiter = RateLimiter::new(start, 2.0);
+
+		// Two seconds worth at once
+		for _ in 0..4 {
+			assert!(limiter.try_take(start, 2.0));
+		}
+		assert!(!limiter.try_take(start, 2.0));
+
+		assert!(!limiter.try_take(start + Duration::from_millis(400), 2.0));
+		assert!(limiter.try_take(start + Duration::from_millis(750), 2.0));
+		assert!(!limiter.try_take(start + Duration::from_millis(750), 2.0));
+	}
+
+	#[test]
+	fn rate_limiter_caps_saved_up_tokens() {
+		let start = Duration::ZERO;
+		let mut limiter = RateLimiter::new(start, 1.0);
+		let later = start + Duration::from_secs(60);
+
+		assert!(limiter.try_take(later, 1.0));
+		assert!(limiter.try_take(later, 1.0));
+		assert!(!limiter.try_take(later, 1.0));
+	}
+}
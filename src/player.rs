@@ -1,17 +1,44 @@
-use crate::network::{InternalConnectionEvent, EventClientConnected};
-use crate::network::events::server::ToClient;
 #[cfg(feature = "server")]
-use crate::network::helper::ServerSn;
-use bevy::{math::DVec2, prelude::*, render::mesh::VertexAttributeValues};
+use crate::network::EventClientConnected;
+use crate::network::channels_config::ChannelConfig;
+use crate::network::jitter_buffer::{BufferHealth, JitterBuffer, MAX_FUTURE_TICKS};
+use crate::network::tick::RepliconTick;
+#[cfg(feature = "server")]
+use crate::network::tick::increment_tick;
+#[cfg(feature = "client")]
+use crate::network::tick::{increment_client_tick, ClientTick, LastRepliconTick};
+#[cfg(feature = "server")]
+use crate::network::connection::EventSessionExpired;
+use crate::network::events::server::ServerEventAppExt;
+#[cfg(feature = "server")]
+use crate::network::events::server::{SendMode, ToClients};
+#[cfg(feature = "server")]
+use crate::network::helper::{ServerSn, SERVER_ID};
+#[cfg(feature = "server")]
+use crate::interest::InterestScopes;
+#[cfg(feature = "server")]
+use crate::network::violations::{ClientViolation, Violation};
+#[cfg(feature = "server")]
+use crate::stats::RestartMatch;
+#[cfg(feature = "server")]
+use crate::weapon::FireCooldown;
+use bevy::{math::DVec2, prelude::*, render::mesh::VertexAttributeValues, utils::HashMap};
 use bevy_particle_systems::*;
 // use bevy_replicon::renet::ClientId;
-use crate::network::events::client::{ClientEventAppExt, FromClient};
+use crate::network::events::client::ClientEventAppExt;
+#[cfg(feature = "server")]
+use crate::network::events::client::FromClient;
 use bevy_xpbd_2d::prelude::*;
 use serde::{Deserialize, Serialize};
 
 // use bevy_replicon::prelude::*;
 
-use crate::{map::AffectedByGravity, network::helper::ClientSn};
+use crate::{map::AffectedByGravity, network::helper::server_running, Cli};
+#[cfg(feature = "client")]
+use crate::{
+	weapon::{cooled_down, fire_cooldown_ticks},
+	ClientIdResource,
+};
 // use crate::{network::{ClientMsgEvent, NetworkChannel}, ClientMsg};
 use crate::network::helper::ClientId;
 
@@ -25,18 +52,56 @@ pub struct PlayerPlugin;
 
 impl Plugin for PlayerPlugin {
 	fn build(&self, app: &mut App) {
+		let input_rate_limit = app.world.resource::<Cli>().tick_rate as f32 * INPUT_RATE_SLACK;
+
 		app
 			// .replicate::<Player>()
 			.init_resource::<CurrentConnections>()
-			.add_client_event::<Inputs>()
-			.add_systems(Update, input_system.run_if(resource_exists::<ClientSn>()))
+			// Reliable, several ticks of input can go out in one frame and each of them counts
+			.add_client_event_with::<Inputs>(ChannelConfig::for_event::<Inputs>().with_rate_limit(input_rate_limit))
+			.add_event::<DamageEvent>()
+			.add_server_event::<PlayerKilled>()
+			.add_server_event_with::<PlayerNames>(ChannelConfig::for_event::<PlayerNames>().latest_only())
+			.add_server_event_with::<InputBufferHealth>(ChannelConfig::for_event::<InputBufferHealth>().latest_only())
+			.init_resource::<PlayerNames>()
+			.add_systems(
+				Update,
+				(apply_player_names.run_if(not(server_running())), attach_player_names).chain(),
+			)
 			// .add_systems(Startup, spawn_player)
 			.add_systems(PreUpdate, player_init_system);
 
+		#[cfg(feature = "client")]
+		app.init_resource::<TextInputFocus>()
+			.init_resource::<LastShot>()
+			.add_systems(
+				FixedUpdate,
+				// Also runs for a listen server's own player, who has no `ClientSn`
+				input_system.after(increment_client_tick).run_if(resource_exists::<ClientIdResource>()),
+			)
+			.add_systems(Update, adjust_input_lead);
+
 		#[cfg(feature = "server")]
-		app.add_systems(
+		app.init_resource::<InputBuffer>()
+			.init_resource::<InputHealthTimer>()
+			.add_systems(
+				FixedUpdate,
+				(
+					apply_player_movement.after(increment_tick).before(PhysicsSet::Prepare),
+					clamp_player_movement.after(PhysicsSet::StepSimulation).before(PhysicsSet::Sync),
+				)
+					.run_if(resource_exists::<ServerSn>()),
+			)
+			.add_systems(
 			Update,
-			(apply_player_movement, handle_player_connections_system).run_if(resource_exists::<ServerSn>()),
+			(
+				buffer_inputs,
+				report_input_health,
+				handle_player_connections_system,
+				restart_players,
+				(apply_damage_system, player_death_system).chain(),
+			)
+				.run_if(resource_exists::<ServerSn>()),
 		);
 	}
 }
@@ -90,25 +155,317 @@ struct PlayerBundle {
 	locked_axes: LockedAxes,
 	gravity: GravityScale,
 	physics: PhysicsBundle,
+	health: Health,
+	last_attacker: LastAttacker,
+	ammo: Ammo,
 }
 
 #[derive(Serialize, Deserialize, Debug)]
 #[derive(Component, Clone, Copy)]
-pub struct Player(ClientId);
+pub struct Player(pub ClientId);
+
+/// Radius of the circle the ship's triangle is inscribed in.
+pub const SHIP_RADIUS: f32 = 50.0;
+pub const MAX_HEALTH: f32 = 100.0;
+
+#[derive(Component, Serialize, Deserialize, Debug, Clone, Copy)]
+pub struct Health {
+	pub current: f32,
+	pub max: f32,
+}
 
+impl Default for Health {
+	fn default() -> Self {
+		Self {
+			current: MAX_HEALTH,
+			max: MAX_HEALTH,
+		}
+	}
+}
+
+#[derive(Component, Serialize, Deserialize, Debug, Clone, Copy)]
+pub struct Ammo {
+	pub bullets: u32,
+	pub grenades: u32,
+}
+
+impl Default for Ammo {
+	fn default() -> Self {
+		Self {
+			bullets: 30,
+			grenades: 1,
+		}
+	}
+}
+
+/// Sent by the server to every client whenever a ship is destroyed.
+#[derive(Event, TypePath, Serialize, Deserialize, Debug, Clone, Copy)]
+pub struct PlayerKilled {
+	pub victim: ClientId,
+	/// `None` when the victim died to the zone or the environment.
+	pub killer: Option<ClientId>,
+	/// Where the ship was destroyed, since it is despawned by the time clients hear about it.
+	///
+	/// Only sent to clients that had the victim in scope.
+	pub position: Option<DVec2>,
+}
+
+#[cfg(feature = "server")]
 pub fn handle_player_connections_system(
 	// time_step: Res<Time>,
 	mut connection_event: EventReader<EventClientConnected>,
+	mut expired_event: EventReader<EventSessionExpired>,
+	mut player_names: ResMut<PlayerNames>,
+	mut names_update: EventWriter<ToClients<PlayerNames>>,
+	mut input_buffer: ResMut<InputBuffer>,
+	player_query: Query<(Entity, &Player)>,
 	mut commands: Commands
 ) {
-	for connection_event in connection_event.read() {
-		// dbg!(x);
+	let mut names_changed = false;
+
+	for EventClientConnected(client_id, requested_name) in connection_event.read() {
+		// A reconnecting player shouldn't have to compete with its own old name
+		player_names.0.remove(client_id);
+		let name = player_names.unique_name(&sanitize_player_name(requested_name));
+		player_names.0.insert(*client_id, name.clone());
+		names_changed = true;
+
+		// Players resuming a session take back the ship they left behind
+		if let Some((entity, _)) = player_query.iter().find(|(_, player)| player.0 == *client_id) {
+			commands.entity(entity).insert(PlayerName(name));
+			continue;
+		}
+
 		commands.spawn((
-			Player(connection_event.0),
+			Player(*client_id),
+			PlayerName(name),
+			FireCooldown::default(),
 			// Replication,
 			Transform::from_xyz(0., 0., 0.)
 		));
 	}
+
+	for EventSessionExpired(client_id) in expired_event.read() {
+		names_changed |= player_names.0.remove(client_id).is_some();
+		input_buffer.remove(*client_id);
+
+		for (entity, player) in &player_query {
+			if player.0 == *client_id {
+				commands.entity(entity).despawn_recursive();
+			}
+		}
+	}
+
+	if names_changed {
+		names_update.send(ToClients {
+			mode: SendMode::Broadcast,
+			event: player_names.clone(),
+		});
+	}
+}
+
+/// Puts every ship back at the start in mint condition, and gives players who died a new one.
+#[cfg(feature = "server")]
+#[allow(clippy::type_complexity)]
+fn restart_players(
+	mut commands: Commands,
+	mut restart_events: EventReader<RestartMatch>,
+	player_names: Res<PlayerNames>,
+	mut player_query: Query<(
+		Entity,
+		&Player,
+		&mut Position,
+		&mut LinearVelocity,
+		&mut AngularVelocity,
+		&mut Health,
+		&mut Ammo,
+		&mut FireCooldown,
+		&mut LastAttacker,
+	)>,
+) {
+	if restart_events.read().last().is_none() {
+		return;
+	}
+
+	for (entity, _, mut position, mut lvel, mut avel, mut health, mut ammo, mut cooldown, mut last_attacker) in
+		&mut player_query
+	{
+		// Moving back to the start isn't something the movement clamp should undo
+		commands.entity(entity).remove::<CheckedPosition>();
+		*position = Position::default();
+		*lvel = LinearVelocity::default();
+		*avel = AngularVelocity::default();
+		*health = Health::default();
+		*ammo = Ammo::default();
+		*cooldown = FireCooldown::default();
+		*last_attacker = LastAttacker::default();
+	}
+
+	for (client_id, name) in &player_names.0 {
+		if player_query.iter().any(|(_, player, ..)| player.0 == *client_id) {
+			continue;
+		}
+
+		commands.spawn((
+			Player(*client_id),
+			PlayerName(name.clone()),
+			FireCooldown::default(),
+			Transform::from_xyz(0., 0., 0.),
+		));
+	}
+}
+
+const MAX_NAME_LENGTH: usize = 16;
+const DEFAULT_NAME: &str = "Pilot";
+
+/// Strips anything but letters, digits, spaces, `-` and `_` from a requested name
+/// and limits it to [`MAX_NAME_LENGTH`] characters.
+pub fn sanitize_player_name(requested_name: &str) -> String {
+	let name: String = requested_name
+		.chars()
+		.filter(|c| c.is_alphanumeric() || matches!(c, ' ' | '-' | '_'))
+		.take(MAX_NAME_LENGTH)
+		.collect();
+
+	// Collapse repeated whitespace so names can't be padded to look identical
+	let name = name.split_whitespace().collect::<Vec<_>>().join(" ");
+
+	if name.is_empty() {
+		String::from(DEFAULT_NAME)
+	} else {
+		name
+	}
+}
+
+#[derive(Component, Serialize, Deserialize, Debug, Clone)]
+pub struct PlayerName(pub String);
+
+/// Display names of every connected player, owned by the server and broadcast whenever it changes.
+#[derive(Resource, Event, TypePath, Serialize, Deserialize, Default, Debug, Clone)]
+pub struct PlayerNames(pub HashMap<ClientId, String>);
+
+impl PlayerNames {
+	/// Name to show for `client_id`, falling back to the raw id for players we haven't heard about.
+	pub fn display_name(&self, client_id: ClientId) -> String {
+		self.0
+			.get(&client_id)
+			.cloned()
+			.unwrap_or_else(|| format!("{:x}", client_id))
+	}
+
+	/// Appends a number to `name` until no other player is using it.
+	#[cfg(feature = "server")]
+	fn unique_name(&self, name: &str) -> String {
+		let taken = |candidate: &str| self.0.values().any(|existing| existing.eq_ignore_ascii_case(candidate));
+
+		if !taken(name) {
+			return name.to_string();
+		}
+
+		(2..)
+			.map(|suffix| format!("{} {}", name, suffix))
+			.find(|candidate| !taken(candidate))
+			.unwrap()
+	}
+}
+
+fn apply_player_names(
+	mut player_names: ResMut<PlayerNames>,
+	mut names_update: EventReader<PlayerNames>,
+) {
+	if let Some(names) = names_update.read().last() {
+		*player_names = names.clone();
+	}
+}
+
+/// Keeps each ship's [`PlayerName`] in sync with the replicated [`PlayerNames`].
+fn attach_player_names(
+	mut commands: Commands,
+	player_names: Res<PlayerNames>,
+	player_query: Query<(Entity, &Player, Option<&PlayerName>)>,
+) {
+	for (entity, player, player_name) in &player_query {
+		let Some(name) = player_names.0.get(&player.0) else {
+			continue;
+		};
+
+		if player_name.map(|player_name| &player_name.0) != Some(name) {
+			commands.entity(entity).insert(PlayerName(name.clone()));
+		}
+	}
+}
+
+/// Damage dealt to a ship on the server, applied by [`apply_damage_system`].
+#[derive(Event, Clone, Copy, Debug)]
+pub struct DamageEvent {
+	pub victim: Entity,
+	/// `None` for environmental damage like the zone.
+	pub attacker: Option<ClientId>,
+	pub amount: f32,
+}
+
+/// The last player to damage this ship, credited with the kill if it dies.
+#[derive(Component, Default, Debug, Clone, Copy)]
+pub struct LastAttacker(pub Option<ClientId>);
+
+#[cfg(feature = "server")]
+fn apply_damage_system(
+	mut damage_events: EventReader<DamageEvent>,
+	mut player_query: Query<(&mut Health, &mut LastAttacker)>,
+) {
+	for damage in damage_events.read() {
+		let Ok((mut health, mut last_attacker)) = player_query.get_mut(damage.victim) else {
+			continue;
+		};
+
+		health.current -= damage.amount;
+		if damage.attacker.is_some() {
+			last_attacker.0 = damage.attacker;
+		}
+	}
+}
+
+#[cfg(feature = "server")]
+pub fn player_death_system(
+	mut commands: Commands,
+	server: Res<ServerSn>,
+	scopes: Res<InterestScopes>,
+	player_query: Query<(Entity, &Player, &Health, &LastAttacker, &Position)>,
+	mut kill_events: EventWriter<ToClients<PlayerKilled>>,
+) {
+	for (entity, player, health, last_attacker, position) in &player_query {
+		if health.current > 0.0 {
+			continue;
+		}
+
+		let kill = PlayerKilled {
+			victim: player.0,
+			killer: last_attacker.0,
+			position: Some(position.0),
+		};
+
+		// Everyone gets the kill feed entry, but only observers learn where it happened
+		let observers = scopes.observers_of(player.0);
+		let others: Vec<ClientId> = server
+			.client_connections
+			.iter()
+			.copied()
+			.filter(|client_id| !observers.contains(client_id))
+			.collect();
+
+		kill_events.send(ToClients {
+			mode: SendMode::ToMany(observers),
+			event: kill,
+		});
+		if !others.is_empty() {
+			kill_events.send(ToClients {
+				mode: SendMode::ToMany(others),
+				event: PlayerKilled { position: None, ..kill },
+			});
+		}
+
+		commands.entity(entity).despawn_recursive();
+	}
 }
 
 // impl Serialize for Player {
@@ -156,7 +513,7 @@ fn player_init_system(
 	spawned_players: Query<(&Player, Entity), Added<Player>>,
 ) {
 	for (player, entity) in &spawned_players {
-		let player_mesh: Mesh = shape::RegularPolygon::new(50., 3).into();
+		let player_mesh: Mesh = shape::RegularPolygon::new(SHIP_RADIUS, 3).into();
 
 		let mut player_mesh_positions = Vec::new();
 		if let VertexAttributeValues::Float32x3(data) =
@@ -171,8 +528,6 @@ fn player_init_system(
 
 		assert!(player_mesh_positions.len() == 3);
 
-		commands.get_entity(entity).unwrap().log_components();
-
 		commands.entity(entity).insert(PlayerBundle {
 			player: *player,
 			// replication: Replication,
@@ -194,6 +549,9 @@ fn player_init_system(
 				ext_f: ExternalForce::new(DVec2::ZERO).with_persistence(false),
 				..Default::default()
 			},
+			health: Health::default(),
+			last_attacker: LastAttacker::default(),
+			ammo: Ammo::default(),
 		});
 
 		commands.entity(entity).with_children(|parent| {
@@ -249,35 +607,268 @@ fn player_init_system(
 	}
 }
 
-/// A movement event for the controlled box.
-#[derive(Debug, Default, Deserialize, Event, Serialize, Clone)]
+/// Inputs per tick a client may send before it counts as speeding up its clock.
+const INPUT_RATE_SLACK: f32 = 1.25;
+
+/// One tick of input for the controlled ship, sampled by the client every `FixedUpdate`.
+#[derive(Debug, Default, Deserialize, Event, TypePath, Serialize, Clone)]
 pub struct Inputs {
-	click: Option<(f32, f32)>,
+	/// [`ClientTick`](crate::network::tick::ClientTick) the input was sampled on, the server
+	/// applies it when its own tick gets there.
+	pub tick: RepliconTick,
+	/// Newest snapshot the client was showing, shots are judged against the world as it was then.
+	pub view_tick: RepliconTick,
 	space: bool,
 	w: bool,
 	a: bool,
 	d: bool,
 }
 
+impl Inputs {
+	/// Whether the thruster is firing.
+	pub fn engine_on(&self) -> bool {
+		self.w
+	}
+
+	/// Whether the ship fires this tick. Clients only set it once the weapon cooled down, see
+	/// [`cooled_down`](crate::weapon::cooled_down).
+	pub fn firing(&self) -> bool {
+		self.space
+	}
+
+	/// Fixes up whatever an honest client could never have sent, `now` being the last simulated
+	/// tick, and returns what was wrong with it.
+	#[cfg(feature = "server")]
+	pub fn sanitize(&mut self, now: RepliconTick) -> Vec<Violation> {
+		let mut violations = Vec::new();
+
+		// Clients only ever see snapshots the server already sent
+		if self.view_tick > now {
+			self.view_tick = now;
+			violations.push(Violation::ViewTickFromFuture);
+		}
+
+		let mut horizon = now;
+		horizon.increment_by(MAX_FUTURE_TICKS);
+		if self.tick > horizon {
+			violations.push(Violation::InputTooEarly);
+		}
+
+		violations
+	}
+}
+
+/// Inputs received from each client, consumed one per tick by [`apply_player_movement`].
+#[derive(Resource, Default, Debug)]
+pub struct InputBuffer(HashMap<ClientId, JitterBuffer<Inputs>>);
+
+impl InputBuffer {
+	/// Queues `inputs` for the tick they are stamped with, `now` being the last simulated tick.
+	pub fn insert(&mut self, now: RepliconTick, client_id: ClientId, inputs: Inputs) {
+		self.0.entry(client_id).or_default().insert(now, inputs.tick, inputs);
+	}
+
+	/// Input to apply for `client_id` on `tick`.
+	pub fn take(&mut self, client_id: ClientId, tick: RepliconTick) -> Inputs {
+		self.0.entry(client_id).or_default().take(tick).unwrap_or_default()
+	}
+
+	/// Input most recently applied for `client_id`.
+	pub fn last(&self, client_id: ClientId) -> Option<&Inputs> {
+		self.0.get(&client_id).and_then(JitterBuffer::last)
+	}
+
+	pub fn remove(&mut self, client_id: ClientId) {
+		self.0.remove(&client_id);
+	}
+}
+
+/// Sent directly to each client so it can tune how far ahead of the server it stamps its inputs.
+#[derive(Event, TypePath, Serialize, Deserialize, Clone, Copy, Debug)]
+pub struct InputBufferHealth {
+	/// Server tick the report was made on.
+	pub server_tick: RepliconTick,
+	pub health: BufferHealth,
+}
+
+/// Inputs the server should ideally have buffered for each client.
+#[cfg(feature = "client")]
+const TARGET_BUFFERED_INPUTS: u32 = 2;
+/// Extra buffered inputs tolerated before the client is asked to fall back.
+#[cfg(feature = "client")]
+const BUFFERED_INPUTS_SLACK: u32 = 2;
+
+#[cfg(feature = "server")]
+#[derive(Resource)]
+struct InputHealthTimer(Timer);
+
+#[cfg(feature = "server")]
+impl Default for InputHealthTimer {
+	fn default() -> Self {
+		Self(Timer::from_seconds(0.25, TimerMode::Repeating))
+	}
+}
+
+#[cfg(feature = "server")]
+fn buffer_inputs(
+	tick: Res<RepliconTick>,
+	mut input_events: EventReader<FromClient<Inputs>>,
+	mut input_buffer: ResMut<InputBuffer>,
+	mut violation_events: EventWriter<ClientViolation>,
+) {
+	for FromClient { client_id, event, .. } in input_events.read() {
+		let mut inputs = event.clone();
+		for violation in inputs.sanitize(*tick) {
+			violation_events.send(ClientViolation { client_id: *client_id, violation });
+		}
+
+		input_buffer.insert(*tick, *client_id, inputs);
+	}
+}
+
+#[cfg(feature = "server")]
+fn report_input_health(
+	time: Res<Time>,
+	tick: Res<RepliconTick>,
+	server: Res<ServerSn>,
+	mut timer: ResMut<InputHealthTimer>,
+	mut input_buffer: ResMut<InputBuffer>,
+	mut health_events: EventWriter<ToClients<InputBufferHealth>>,
+) {
+	if !timer.0.tick(time.delta()).just_finished() {
+		return;
+	}
+
+	for (client_id, buffer) in &mut input_buffer.0 {
+		let health = buffer.take_health();
+		if health.late + health.early + health.missing > 0 {
+			debug!("input buffer for client {client_id}: {health:?}");
+		}
+
+		// The host's inputs are buffered like anyone's and need the same feedback
+		if server.client_connections.contains(client_id) || *client_id == SERVER_ID {
+			health_events.send(ToClients {
+				mode: SendMode::Direct(*client_id),
+				event: InputBufferHealth { server_tick: *tick, health },
+			});
+		}
+	}
+}
+
+/// Moves [`ClientTick`] a step further ahead when inputs reach the server too late, and a step
+/// back when they pile up, keeping the server's buffer near [`TARGET_BUFFERED_INPUTS`].
+#[cfg(feature = "client")]
+fn adjust_input_lead(
+	mut health_events: EventReader<InputBufferHealth>,
+	mut client_tick: ResMut<ClientTick>,
+) {
+	if let Some(report) = health_events.read().last() {
+		client_tick.0 = steer_input_lead(client_tick.0, *report);
+	}
+}
+
+/// Where [`ClientTick`] should be after `report`, see [`adjust_input_lead`].
+#[cfg(feature = "client")]
+fn steer_input_lead(client_tick: RepliconTick, report: InputBufferHealth) -> RepliconTick {
+	let InputBufferHealth { server_tick, health } = report;
+
+	let mut horizon = server_tick;
+	horizon.increment_by(MAX_FUTURE_TICKS);
+
+	let mut steered = client_tick;
+	if client_tick <= server_tick || client_tick > horizon || health.early > 0 {
+		// First report, we fell hopelessly behind, or the server throws our inputs away for being
+		// too far ahead, e.g. after it restarted
+		steered = server_tick;
+		steered.increment_by(TARGET_BUFFERED_INPUTS + 1);
+	} else if (health.late > 0 || health.missing > 0) && health.buffered < TARGET_BUFFERED_INPUTS {
+		steered.increment();
+	} else if health.buffered > TARGET_BUFFERED_INPUTS + BUFFERED_INPUTS_SLACK {
+		steered.decrement();
+	}
+
+	steered
+}
+
+/// Set while a text box has the keyboard, the ship then gets no keys at all.
+#[cfg(feature = "client")]
+#[derive(Resource, Default, Debug)]
+pub struct TextInputFocus(pub bool);
+
+/// Input tick the controlled ship last fired on, the server holds it to the same cooldown.
+#[cfg(feature = "client")]
+#[derive(Resource, Default, Debug)]
+struct LastShot(Option<RepliconTick>);
+
+#[cfg(feature = "client")]
 fn input_system(
+	tick: Res<ClientTick>,
+	view_tick: Res<LastRepliconTick>,
+	time_step: Res<Time<Fixed>>,
+	mut last_shot: ResMut<LastShot>,
 	mut move_events: EventWriter<Inputs>,
-	// mut client: ResMut<ClientSn>,
 	keys: Res<Input<KeyCode>>,
+	focus: Res<TextInputFocus>,
 ) {
-	// dbg!(&keys);
+	// Still sent while typing, the server applies one input per tick either way
+	let pressed = |key| !focus.0 && keys.pressed(key);
+
+	let space = pressed(KeyCode::Space) && cooled_down(last_shot.0, tick.0, fire_cooldown_ticks(&time_step));
+	if space {
+		last_shot.0 = Some(tick.0);
+	}
 
 	move_events.send(Inputs {
-		click: None,
-		space: keys.pressed(KeyCode::Space),
-		w: keys.pressed(KeyCode::W),
-		a: keys.pressed(KeyCode::A),
-		d: keys.pressed(KeyCode::D),
+		tick: tick.0,
+		view_tick: **view_tick,
+		space,
+		w: pressed(KeyCode::W),
+		a: pressed(KeyCode::A),
+		d: pressed(KeyCode::D),
 	});
 }
 
-fn apply_player_movement(
-	time_step: Res<Time>,
-	mut move_events: EventReader<FromClient<Inputs>>,
+/// How much further than its velocity explains a ship may move in one frame. Collisions push
+/// ships apart without going through their velocity.
+#[cfg(feature = "server")]
+const MOVEMENT_SLACK: f64 = SHIP_RADIUS as f64;
+
+/// Where the ship was when [`clamp_player_movement`] last looked at it.
+#[cfg(feature = "server")]
+#[derive(Component, Debug)]
+struct CheckedPosition(DVec2);
+
+/// Cuts short any move a ship's velocity can't explain, a sanity guard against the simulation
+/// flinging a ship across the map. The server moved the ship itself, so nobody is blamed for it.
+#[cfg(feature = "server")]
+fn clamp_player_movement(
+	mut commands: Commands,
+	time_step: Res<Time<Fixed>>,
+	mut player_query: Query<(Entity, &mut Position, &LinearVelocity, Option<&mut CheckedPosition>), With<Player>>,
+) {
+	let delta = time_step.timestep().as_secs_f64();
+
+	for (entity, mut position, lvel, checked) in &mut player_query {
+		let Some(mut checked) = checked else {
+			commands.entity(entity).insert(CheckedPosition(position.0));
+			continue;
+		};
+
+		let moved = position.0 - checked.0;
+		let allowed = lvel.0.length() * delta + MOVEMENT_SLACK;
+		if moved.length() > allowed {
+			position.0 = checked.0 + moved.clamp_length_max(allowed);
+		}
+
+		checked.0 = position.0;
+	}
+}
+
+#[cfg(feature = "server")]
+pub fn apply_player_movement(
+	time_step: Res<Time<Fixed>>,
+	tick: Res<RepliconTick>,
+	mut input_buffer: ResMut<InputBuffer>,
 	mut player_query: Query<(
 		&Player,
 		&mut ExternalForce,
@@ -286,73 +877,123 @@ fn apply_player_movement(
 		&Rotation,
 		&Children,
 	)>,
-	mut particle_effect_query: Query<(&mut Transform, &mut ParticleSystem)>,
+	mut particle_effect_query: Query<&mut ParticleSystem>,
+) {
+	let delta = time_step.delta_seconds_f64();
+
+	for (player, mut ext_forces, mut avel, lvel, rot, children) in &mut player_query {
+		let inputs = input_buffer.take(player.0, *tick);
+
+		if inputs.w {
+			ext_forces.apply_force(rot.rotate(DVec2::Y * 1.5e8 * delta));
+		}
+
+		let child_id = *children.first().unwrap(); // Thruster ID BC only 1 child that is the truster
+		if let Ok(mut particle_system) = particle_effect_query.get_mut(child_id) {
+			update_thruster(&mut particle_system, inputs.w, lvel, rot);
+		}
+
+		let mut avel_change = 0.;
+
+		if inputs.a {
+			avel_change += 6.;
+		}
+
+		if inputs.d {
+			avel_change -= 6.;
+		}
+
+		if avel_change != 0. {
+			avel.0 += avel_change * delta;
+		}
+
+		avel.0 *= 1. - ((1. - 0.2) * delta);
+	}
+}
+
+/// Points the thruster particles behind the ship and turns them on or off.
+pub fn update_thruster(
+	particle_system: &mut ParticleSystem,
+	engine_on: bool,
+	lvel: &LinearVelocity,
+	rot: &Rotation,
 ) {
 	const THRUST_PARTICLE_SPAWN_RATE: f32 = 500.0;
 	const THRUST_PARTICLE_VELOCITY: f64 = 200.0;
 
-	for input in move_events.read() {
-		// dbg!(&input);
-		let FromClient {
-			client_id: _client_id,
-			event: inputs,
-		} = input.clone();
-
-		// info!("received event {event:?} from client {client_id}");
-		for (_player, mut ext_forces, mut avel, lvel, rot, children) in &mut player_query {
-			let child_id = *children.get(0).unwrap(); // Thruster ID BC only 1 child that is the truster
-
-			if inputs.w {
-				// dbg!(&ext_forces);
-				ext_forces
-					.apply_force(rot.rotate(DVec2::Y * 1.5e8 * time_step.delta().as_secs_f64()));
-
-				particle_effect_query
-					.get_mut(child_id)
-					.unwrap()
-					.1
-					.spawn_rate_per_second = THRUST_PARTICLE_SPAWN_RATE.into();
-			} else {
-				particle_effect_query
-					.get_mut(child_id)
-					.unwrap()
-					.1
-					.spawn_rate_per_second = 0.0.into();
-			}
+	particle_system.spawn_rate_per_second = if engine_on {
+		THRUST_PARTICLE_SPAWN_RATE.into()
+	} else {
+		0.0.into()
+	};
 
-			let rot = Rotation::from_degrees(rot.as_degrees() - 90.);
-			let particle_velocity: DVec2 =
-				lvel.0 + (DVec2::new(rot.cos(), rot.sin()) * THRUST_PARTICLE_VELOCITY);
-
-			particle_effect_query
-				.get_mut(child_id)
-				.unwrap()
-				.1
-				.initial_speed = JitteredValue {
-				value: ((lvel.0.length() + THRUST_PARTICLE_VELOCITY) as f32),
-				jitter_range: Some(-300.0..300.0),
-			}; // (particle_velocity.length().abs() as f32).into();
-			particle_effect_query
-				.get_mut(child_id)
-				.unwrap()
-				.1
-				.initial_rotation = (particle_velocity.angle_between(DVec2::new(1., 0.)) as f32).into();
-
-			let mut avel_change = 0.;
-
-			if inputs.a {
-				avel_change += 6.;
-			}
+	let rot = Rotation::from_degrees(rot.as_degrees() - 90.);
+	let particle_velocity: DVec2 = lvel.0 + (DVec2::new(rot.cos(), rot.sin()) * THRUST_PARTICLE_VELOCITY);
 
-			if inputs.d {
-				avel_change -= 6.;
-			}
+	particle_system.initial_speed = JitteredValue {
+		value: ((lvel.0.length() + THRUST_PARTICLE_VELOCITY) as f32),
+		jitter_range: Some(-300.0..300.0),
+	}; // (particle_velocity.length().abs() as f32).into();
+	particle_system.initial_rotation = (particle_velocity.angle_between(DVec2::new(1., 0.)) as f32).into();
+}
 
-			if avel_change != 0. {
-				avel.0 += avel_change * time_step.delta().as_secs_f64();
-			}
+#[cfg(test)]
+mod tests {
+	use super::*;
 
-			avel.0 *= 1. - ((1. - 0.2) * time_step.delta().as_secs_f64());
+	#[cfg(feature = "client")]
+	fn report(server_tick: u32, health: BufferHealth) -> InputBufferHealth {
+		InputBufferHealth {
+			server_tick: RepliconTick(server_tick),
+			health,
 		}
 	}
+
+	#[cfg(feature = "client")]
+	#[test]
+	fn input_lead_snaps_back_from_far_ahead() {
+		let resynced = RepliconTick(50 + TARGET_BUFFERED_INPUTS + 1);
+		let missing = BufferHealth {
+			missing: 30,
+			..default()
+		};
+		assert_eq!(steer_input_lead(RepliconTick(100_000), report(50, missing)), resynced);
+
+		let early = BufferHealth {
+			early: 1,
+			..default()
+		};
+		assert_eq!(steer_input_lead(RepliconTick(60), report(50, early)), resynced);
+	}
+
+	#[cfg(feature = "client")]
+	#[test]
+	fn input_lead_steps_towards_the_target() {
+		let starving = BufferHealth {
+			missing: 1,
+			..default()
+		};
+		assert_eq!(steer_input_lead(RepliconTick(53), report(50, starving)), RepliconTick(54));
+
+		let piling_up = BufferHealth {
+			buffered: TARGET_BUFFERED_INPUTS + BUFFERED_INPUTS_SLACK + 1,
+			..default()
+		};
+		assert_eq!(steer_input_lead(RepliconTick(60), report(50, piling_up)), RepliconTick(59));
+		assert_eq!(steer_input_lead(RepliconTick(53), report(50, default())), RepliconTick(53));
+	}
+
+	#[test]
+	fn player_names_are_sanitized() {
+		assert_eq!(sanitize_player_name("Ace_Pilot-1"), "Ace_Pilot-1");
+		assert_eq!(sanitize_player_name("  two   spaces  "), "two spaces");
+		assert_eq!(sanitize_player_name("<b>bold</b>\n"), "bboldb");
+		assert_eq!(sanitize_player_name("a name much longer than sixteen"), "a name much long");
+	}
+
+	#[test]
+	fn empty_player_names_get_the_default() {
+		assert_eq!(sanitize_player_name(""), DEFAULT_NAME);
+		assert_eq!(sanitize_player_name("!!! ???"), DEFAULT_NAME);
+	}
 }
@@ -0,0 +1,595 @@
+use std::net::{Ipv4Addr, SocketAddr, UdpSocket};
+#[cfg(feature = "server")]
+use std::net::IpAddr;
+
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::network::helper::PROTOCOL_VERSION;
+#[cfg(feature = "client")]
+use crate::{
+	network::{
+		channels_config::ChannelManager,
+		connection::ClientConnectionState,
+		helper::{connect_client, parse_server_address, random_client_id, ServerUrl, DEFAULT_PORT},
+	},
+	player::TextInputFocus,
+	ClientIdResource,
+};
+#[cfg(feature = "server")]
+use crate::{
+	map::MapSeed,
+	network::{helper::ServerSn, transport::ServerTransport},
+	player::PlayerNames,
+	stats::MatchState,
+};
+use crate::Cli;
+
+/// Finding servers: every server answers info queries over UDP and announces itself on the LAN,
+/// and a client started without `--connect` lists what it finds in a main menu.
+pub struct ServerBrowserPlugin;
+
+impl Plugin for ServerBrowserPlugin {
+	fn build(&self, app: &mut App) {
+		#[cfg(feature = "client")]
+		app.add_event::<JoinServer>()
+			// Offline, hosting or `--connect` already picked a server during `PreStartup`
+			.add_systems(Startup, open_server_browser.run_if(not(resource_exists::<ClientIdResource>())))
+			.add_systems(
+				Update,
+				(
+					refresh_server_list,
+					type_server_address,
+					click_server_rows,
+					update_server_list,
+					update_browser_text,
+					join_server,
+				)
+					.chain()
+					.run_if(resource_exists::<ServerBrowser>()),
+			);
+
+		#[cfg(feature = "server")]
+		app.add_systems(Startup, open_info_socket.run_if(resource_exists::<ServerSn>()))
+			.add_systems(Update, answer_info_queries.run_if(resource_exists::<InfoSocket>()));
+	}
+}
+
+/// Datagram a client sends to a server's info port, answered with its [`ServerInfo`] as JSON.
+pub const INFO_QUERY: &[u8] = b"SPACE_ROYALE_INFO?";
+/// Port clients listen on for servers announcing themselves with a broadcast [`ServerInfo`].
+pub const LAN_DISCOVERY_PORT: u16 = 38729;
+/// Longest server name sent out, in characters.
+#[cfg(feature = "server")]
+const MAX_SERVER_NAME_LENGTH: usize = 48;
+/// Largest [`ServerInfo`] a client reads, anything bigger is not from one of our servers.
+#[cfg(feature = "client")]
+const MAX_INFO_SIZE: usize = 1024;
+
+/// Seconds between LAN announcements.
+#[cfg(feature = "server")]
+const ANNOUNCE_SECONDS: f32 = 2.0;
+
+/// Seconds between info queries to servers the browser asks directly.
+#[cfg(feature = "client")]
+const REFRESH_SECONDS: f32 = 3.0;
+/// Seconds after which a server that went quiet is dropped from the list.
+#[cfg(feature = "client")]
+const SERVER_TIMEOUT_SECONDS: f32 = 10.0;
+/// Longest address the browser lets you type, the limit on a hostname plus a port.
+#[cfg(feature = "client")]
+const MAX_ADDRESS_LENGTH: usize = 260;
+#[cfg(feature = "client")]
+const BROWSER_FONT_SIZE: f32 = 18.0;
+#[cfg(feature = "client")]
+const ROW_COLOR: Color = Color::rgba(1., 1., 1., 0.05);
+#[cfg(feature = "client")]
+const ROW_HOVERED_COLOR: Color = Color::rgba(1., 1., 1., 0.15);
+
+/// Port a server answers info queries on, the one after the port it accepts players on.
+pub fn info_port(game_port: u16) -> u16 {
+	game_port.wrapping_add(1)
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MatchPhase {
+	InProgress,
+	/// A winner was declared and the server waits for an admin to restart the match.
+	Finished,
+}
+
+impl std::fmt::Display for MatchPhase {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		match self {
+			MatchPhase::InProgress => write!(f, "in progress"),
+			MatchPhase::Finished => write!(f, "finished"),
+		}
+	}
+}
+
+/// What a server tells anyone who asks, without them having to connect.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct ServerInfo {
+	pub name: String,
+	pub map_seed: u64,
+	pub players: usize,
+	pub match_phase: MatchPhase,
+	/// Clients with a different [`PROTOCOL_VERSION`] would be turned away.
+	pub protocol_version: u32,
+	/// Port the server accepts players on. Announcements come from the info port, so this is the
+	/// only way a client learns it.
+	pub port: u16,
+}
+
+#[cfg(feature = "server")]
+#[derive(Resource)]
+struct InfoSocket {
+	socket: UdpSocket,
+	/// Broadcast address announcements go to, `None` for servers the LAN can't reach anyway.
+	announce_to: Option<SocketAddr>,
+	announce_timer: Timer,
+}
+
+#[cfg(feature = "server")]
+fn open_info_socket(mut commands: Commands, cli: Res<Cli>, server: Res<ServerSn>) {
+	// Offline play has no address to share
+	if !matches!(server.transport, ServerTransport::Websocket(_)) {
+		return;
+	}
+
+	let address = SocketAddr::new(cli.listen.ip(), info_port(cli.listen.port()));
+	let Some(socket) = bind_udp(address) else {
+		return;
+	};
+
+	// A server only this machine can reach has nothing to tell the LAN
+	let announce_to = match cli.listen.ip() {
+		IpAddr::V4(ip) if !ip.is_loopback() => match socket.set_broadcast(true) {
+			Ok(()) => Some(SocketAddr::from((Ipv4Addr::BROADCAST, LAN_DISCOVERY_PORT))),
+			Err(error) => {
+				warn!("not announcing on the LAN, broadcast is unavailable: {error}");
+				None
+			}
+		},
+		_ => None,
+	};
+
+	info!(
+		"answering server info queries on {address}{}",
+		if announce_to.is_some() { ", announcing on the LAN" } else { "" }
+	);
+
+	commands.insert_resource(InfoSocket {
+		socket,
+		announce_to,
+		announce_timer: Timer::from_seconds(ANNOUNCE_SECONDS, TimerMode::Repeating),
+	});
+}
+
+#[cfg(feature = "server")]
+fn answer_info_queries(
+	time: Res<Time>,
+	cli: Res<Cli>,
+	map_seed: Res<MapSeed>,
+	player_names: Res<PlayerNames>,
+	match_state: Res<MatchState>,
+	mut info_socket: ResMut<InfoSocket>,
+) {
+	let info = || {
+		let info = ServerInfo {
+			name: cli.server_name.chars().take(MAX_SERVER_NAME_LENGTH).collect(),
+			map_seed: map_seed.0,
+			players: player_names.0.len(),
+			match_phase: if match_state.is_finished() { MatchPhase::Finished } else { MatchPhase::InProgress },
+			protocol_version: PROTOCOL_VERSION,
+			port: cli.listen.port(),
+		};
+		serde_json::to_vec(&info).expect("server info should serialize")
+	};
+
+	let InfoSocket { socket, announce_to, announce_timer } = &mut *info_socket;
+
+	let mut query = [0; 64];
+	while let Ok((len, from)) = socket.recv_from(&mut query) {
+		if &query[..len] != INFO_QUERY {
+			continue;
+		}
+
+		if let Err(error) = socket.send_to(&info(), from) {
+			debug!("could not answer info query from {from}: {error}");
+		}
+	}
+
+	if !announce_timer.tick(time.delta()).just_finished() {
+		return;
+	}
+
+	if let Some(announce_to) = announce_to {
+		if let Err(error) = socket.send_to(&info(), *announce_to) {
+			debug!("could not announce on the LAN: {error}");
+		}
+	}
+}
+
+/// Binds a non-blocking UDP socket, logging why it couldn't.
+fn bind_udp(address: SocketAddr) -> Option<UdpSocket> {
+	let socket = UdpSocket::bind(address).and_then(|socket| {
+		socket.set_nonblocking(true)?;
+		Ok(socket)
+	});
+
+	match socket {
+		Ok(socket) => Some(socket),
+		Err(error) => {
+			warn!("could not open UDP socket on {address}: {error}");
+			None
+		}
+	}
+}
+
+/// Sent by the browser once the player picked a server.
+#[cfg(feature = "client")]
+#[derive(Event, Debug, Clone)]
+struct JoinServer(url::Url);
+
+/// Main menu listing the servers we heard from. Exists until the player joins one.
+#[cfg(feature = "client")]
+#[derive(Resource)]
+pub struct ServerBrowser {
+	/// Receives LAN announcements, `None` if the port is taken, e.g. by another client on this machine.
+	lan_socket: Option<UdpSocket>,
+	/// Sends info queries and receives the answers.
+	query_socket: Option<UdpSocket>,
+	/// Info ports of servers we ask directly instead of waiting for an announcement.
+	probes: Vec<SocketAddr>,
+	servers: Vec<ListedServer>,
+	refresh_timer: Timer,
+	/// Address being typed.
+	input: String,
+	/// Why the last attempt to join went nowhere.
+	status: String,
+	/// Set whenever `servers` changes, so the list is only rebuilt when it has to be.
+	list_dirty: bool,
+}
+
+#[cfg(feature = "client")]
+struct ListedServer {
+	/// Address the server accepts players on.
+	address: SocketAddr,
+	info: ServerInfo,
+	last_seen: f32,
+}
+
+#[cfg(feature = "client")]
+impl ListedServer {
+	fn url(&self) -> url::Url {
+		url::Url::parse(&format!("ws://{}/ws", self.address)).expect("socket address should make a valid url")
+	}
+}
+
+#[cfg(feature = "client")]
+impl ServerBrowser {
+	fn query_probes(&self) {
+		let Some(socket) = &self.query_socket else {
+			return;
+		};
+
+		for probe in &self.probes {
+			if let Err(error) = socket.send_to(INFO_QUERY, probe) {
+				debug!("could not query {probe}: {error}");
+			}
+		}
+	}
+
+	/// Lists every server that answered a query or announced itself since the last call.
+	fn receive(&mut self, now: f32) {
+		let mut buffer = [0; MAX_INFO_SIZE];
+		let mut arrived = Vec::new();
+
+		for socket in [&self.lan_socket, &self.query_socket].into_iter().flatten() {
+			while let Ok((len, from)) = socket.recv_from(&mut buffer) {
+				match serde_json::from_slice::<ServerInfo>(&buffer[..len]) {
+					Ok(info) => arrived.push((SocketAddr::new(from.ip(), info.port), info)),
+					Err(error) => debug!("ignoring malformed server info from {from}: {error}"),
+				}
+			}
+		}
+
+		for (address, info) in arrived {
+			match self.servers.iter_mut().find(|server| server.address == address) {
+				Some(server) => {
+					if server.info != info {
+						server.info = info;
+						self.list_dirty = true;
+					}
+					server.last_seen = now;
+				}
+				None => {
+					self.servers.push(ListedServer { address, info, last_seen: now });
+					self.list_dirty = true;
+				}
+			}
+		}
+	}
+
+	fn forget_stale(&mut self, now: f32) {
+		let listed = self.servers.len();
+		self.servers.retain(|server| now - server.last_seen < SERVER_TIMEOUT_SECONDS);
+		self.list_dirty |= self.servers.len() != listed;
+	}
+}
+
+#[cfg(feature = "client")]
+#[derive(Component)]
+struct ServerBrowserRoot;
+
+#[cfg(feature = "client")]
+#[derive(Component)]
+struct ServerListNode;
+
+/// Button joining the server at this address.
+#[cfg(feature = "client")]
+#[derive(Component)]
+struct ServerRow(SocketAddr);
+
+#[cfg(feature = "client")]
+#[derive(Component)]
+struct AddressInputText;
+
+#[cfg(feature = "client")]
+#[derive(Component)]
+struct BrowserStatusText;
+
+#[cfg(feature = "client")]
+fn open_server_browser(mut commands: Commands, mut focus: ResMut<TextInputFocus>) {
+	let browser = ServerBrowser {
+		lan_socket: bind_udp(SocketAddr::from((Ipv4Addr::UNSPECIFIED, LAN_DISCOVERY_PORT))),
+		query_socket: bind_udp(SocketAddr::from((Ipv4Addr::UNSPECIFIED, 0))),
+		// Picks up a server started on this machine with the default `--listen`
+		probes: vec![SocketAddr::from((Ipv4Addr::LOCALHOST, info_port(DEFAULT_PORT)))],
+		servers: Vec::new(),
+		refresh_timer: Timer::from_seconds(REFRESH_SECONDS, TimerMode::Repeating),
+		input: String::new(),
+		status: String::new(),
+		list_dirty: true,
+	};
+	browser.query_probes();
+	commands.insert_resource(browser);
+
+	// The address box has the keyboard until we join
+	focus.0 = true;
+
+	let text = |value: &str, font_size: f32, color: Color| {
+		TextBundle::from_section(value, TextStyle { font_size, color, ..default() })
+	};
+
+	commands
+		.spawn((
+			NodeBundle {
+				style: Style {
+					width: Val::Percent(100.),
+					height: Val::Percent(100.),
+					flex_direction: FlexDirection::Column,
+					align_items: AlignItems::Center,
+					row_gap: Val::Px(12.),
+					padding: UiRect::top(Val::Percent(10.)),
+					..default()
+				},
+				background_color: Color::rgb(0.03, 0.03, 0.08).into(),
+				z_index: ZIndex::Global(10),
+				..default()
+			},
+			ServerBrowserRoot,
+		))
+		.with_children(|parent| {
+			parent.spawn(text("Space Royale", 48., Color::WHITE));
+			parent.spawn(text(
+				"Click a server to join, or type an address and press Enter",
+				BROWSER_FONT_SIZE,
+				Color::GRAY,
+			));
+			parent.spawn((
+				NodeBundle {
+					style: Style {
+						width: Val::Px(640.),
+						min_height: Val::Px(200.),
+						flex_direction: FlexDirection::Column,
+						row_gap: Val::Px(4.),
+						padding: UiRect::all(Val::Px(6.)),
+						..default()
+					},
+					background_color: Color::rgba(0., 0., 0., 0.6).into(),
+					..default()
+				},
+				ServerListNode,
+			));
+			parent.spawn((text("", BROWSER_FONT_SIZE, Color::WHITE), AddressInputText));
+			parent.spawn((text("", BROWSER_FONT_SIZE, Color::ORANGE_RED), BrowserStatusText));
+		});
+}
+
+#[cfg(feature = "client")]
+fn refresh_server_list(time: Res<Time>, mut browser: ResMut<ServerBrowser>) {
+	let now = time.elapsed_seconds();
+
+	if browser.refresh_timer.tick(time.delta()).just_finished() {
+		browser.query_probes();
+		browser.forget_stale(now);
+	}
+
+	browser.receive(now);
+}
+
+#[cfg(feature = "client")]
+fn type_server_address(
+	keys: Res<Input<KeyCode>>,
+	mut characters: EventReader<ReceivedCharacter>,
+	mut browser: ResMut<ServerBrowser>,
+	mut join_events: EventWriter<JoinServer>,
+) {
+	for ReceivedCharacter { char, .. } in characters.read() {
+		if !char.is_control() && !char.is_whitespace() && browser.input.len() < MAX_ADDRESS_LENGTH {
+			browser.input.push(*char);
+		}
+	}
+
+	if keys.just_pressed(KeyCode::Back) {
+		browser.input.pop();
+	}
+
+	if keys.just_pressed(KeyCode::Escape) {
+		browser.input.clear();
+	}
+
+	if !keys.just_pressed(KeyCode::Return) || browser.input.is_empty() {
+		return;
+	}
+
+	match parse_server_address(&browser.input) {
+		Ok(url) => join_events.send(JoinServer(url)),
+		Err(error) => browser.status = error,
+	}
+}
+
+#[cfg(feature = "client")]
+fn click_server_rows(
+	mut browser: ResMut<ServerBrowser>,
+	mut row_query: Query<(&Interaction, &ServerRow, &mut BackgroundColor), Changed<Interaction>>,
+	mut join_events: EventWriter<JoinServer>,
+) {
+	for (interaction, ServerRow(address), mut background) in &mut row_query {
+		*background = match interaction {
+			Interaction::Hovered | Interaction::Pressed => ROW_HOVERED_COLOR,
+			Interaction::None => ROW_COLOR,
+		}
+		.into();
+
+		if *interaction != Interaction::Pressed {
+			continue;
+		}
+
+		let Some(server) = browser.servers.iter().find(|server| server.address == *address) else {
+			continue;
+		};
+
+		if server.info.protocol_version == PROTOCOL_VERSION {
+			join_events.send(JoinServer(server.url()));
+			continue;
+		}
+
+		let status = format!(
+			"{} runs protocol version {}, this client speaks {}",
+			server.info.name, server.info.protocol_version, PROTOCOL_VERSION
+		);
+		browser.status = status;
+	}
+}
+
+#[cfg(feature = "client")]
+fn update_server_list(
+	mut commands: Commands,
+	mut browser: ResMut<ServerBrowser>,
+	list_query: Query<Entity, With<ServerListNode>>,
+) {
+	if !browser.list_dirty {
+		return;
+	}
+	browser.list_dirty = false;
+
+	let Ok(list) = list_query.get_single() else {
+		return;
+	};
+
+	let style = |color| TextStyle {
+		font_size: BROWSER_FONT_SIZE,
+		color,
+		..default()
+	};
+
+	commands.entity(list).despawn_descendants().with_children(|parent| {
+		if browser.servers.is_empty() {
+			parent.spawn(TextBundle::from_section("Looking for servers...", style(Color::GRAY)));
+			return;
+		}
+
+		for server in &browser.servers {
+			let ServerInfo { name, players, match_phase, protocol_version, .. } = &server.info;
+			let compatible = *protocol_version == PROTOCOL_VERSION;
+
+			let mut line = format!("{name}    {players} players, match {match_phase}    {}", server.address);
+			if !compatible {
+				line.push_str(&format!("    (protocol {protocol_version})"));
+			}
+
+			parent
+				.spawn((
+					ButtonBundle {
+						style: Style {
+							padding: UiRect::all(Val::Px(6.)),
+							..default()
+						},
+						background_color: ROW_COLOR.into(),
+						..default()
+					},
+					ServerRow(server.address),
+				))
+				.with_children(|row| {
+					row.spawn(TextBundle::from_section(
+						line,
+						style(if compatible { Color::WHITE } else { Color::GRAY }),
+					));
+				});
+		}
+	});
+}
+
+#[cfg(feature = "client")]
+fn update_browser_text(
+	browser: Res<ServerBrowser>,
+	mut input_query: Query<&mut Text, (With<AddressInputText>, Without<BrowserStatusText>)>,
+	mut status_query: Query<&mut Text, (With<BrowserStatusText>, Without<AddressInputText>)>,
+) {
+	let input = format!("Address: {}_", browser.input);
+
+	for mut text in &mut input_query {
+		if text.sections[0].value != input {
+			text.sections[0].value = input.clone();
+		}
+	}
+
+	for mut text in &mut status_query {
+		if text.sections[0].value != browser.status {
+			text.sections[0].value = browser.status.clone();
+		}
+	}
+}
+
+/// Connects to the chosen server and closes the browser for good.
+#[cfg(feature = "client")]
+fn join_server(
+	mut commands: Commands,
+	cli: Res<Cli>,
+	channel_manager: Res<ChannelManager>,
+	mut join_events: EventReader<JoinServer>,
+	mut focus: ResMut<TextInputFocus>,
+	mut next_connection_state: ResMut<NextState<ClientConnectionState>>,
+	root_query: Query<Entity, With<ServerBrowserRoot>>,
+) {
+	let Some(JoinServer(url)) = join_events.read().last() else {
+		return;
+	};
+
+	info!("joining {url}");
+
+	let client_id = random_client_id();
+	commands.insert_resource(connect_client(client_id, url, &cli, &channel_manager, None));
+	commands.insert_resource(ServerUrl(url.clone()));
+	commands.insert_resource(ClientIdResource(client_id));
+	commands.remove_resource::<ServerBrowser>();
+	next_connection_state.set(ClientConnectionState::Connecting);
+	focus.0 = false;
+
+	for root in &root_query {
+		commands.entity(root).despawn_recursive();
+	}
+}
@@ -0,0 +1,221 @@
+use bevy::{math::DVec2, prelude::*};
+use bevy_particle_systems::ParticleSystem;
+use bevy_xpbd_2d::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::network::channels_config::ChannelConfig;
+use crate::network::events::server::ServerEventAppExt;
+use crate::network::helper::{server_running, ClientSn};
+use crate::network::tick::{LastRepliconTick, RepliconTick};
+use crate::player::{update_thruster, Ammo, Health, Player};
+use crate::ClientIdResource;
+#[cfg(feature = "server")]
+use crate::{
+	network::{
+		events::server::{SendMode, ToClients},
+		helper::{ServerSet, ServerSn},
+	},
+	interest::InterestScopes,
+	player::InputBuffer,
+	Cli,
+};
+
+pub struct SnapshotPlugin;
+
+impl Plugin for SnapshotPlugin {
+	fn build(&self, app: &mut App) {
+		app.init_resource::<PlayersAlive>()
+			.add_server_event_with::<GameState>(ChannelConfig::for_event::<GameState>().latest_only())
+			.add_systems(Update, apply_snapshot.run_if(not(server_running())));
+
+		// When hosting, what we see is the live simulation rather than a snapshot
+		#[cfg(all(feature = "client", feature = "server"))]
+		app.add_systems(
+			PostUpdate,
+			view_local_simulation.after(PhysicsSet::Sync).run_if(server_running()),
+		);
+
+		#[cfg(feature = "server")]
+		app.init_resource::<SnapshotTimer>().add_systems(
+			PostUpdate,
+			send_snapshot
+				.after(PhysicsSet::Sync)
+				.before(ServerSet::Send)
+				.run_if(resource_exists::<ServerSn>()),
+		);
+	}
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct PlayerState {
+	pub player: Player,
+	pub position: DVec2,
+	/// Radians.
+	pub rotation: f64,
+	pub linear_velocity: DVec2,
+	pub angular_velocity: f64,
+	pub health: Health,
+	pub engine_on: bool,
+}
+
+/// Everything a client needs to draw the match, sent at [`Cli::snapshot_rate`](crate::Cli::snapshot_rate).
+#[derive(Serialize, Deserialize, Event, TypePath, Clone, Debug)]
+pub struct GameState {
+	/// Server tick the snapshot was taken on.
+	pub tick: RepliconTick,
+	pub players: Vec<PlayerState>,
+	/// The receiving client's own ammo, nobody else needs to know it.
+	pub ammo: Option<Ammo>,
+	/// Ships left in the match, `players` only has the ones in scope.
+	pub players_alive: u32,
+	// bullets: Vec<PlayerState>,
+}
+
+/// Ships left in the match, including the ones out of our scope.
+#[derive(Resource, Default, Debug)]
+pub struct PlayersAlive(pub u32);
+
+/// Paces snapshots independently of the simulation tick.
+#[cfg(feature = "server")]
+#[derive(Resource)]
+pub struct SnapshotTimer(Timer);
+
+#[cfg(feature = "server")]
+impl FromWorld for SnapshotTimer {
+	fn from_world(world: &mut World) -> Self {
+		let snapshot_rate = world.resource::<Cli>().snapshot_rate;
+		Self(Timer::from_seconds((1.0 / snapshot_rate) as f32, TimerMode::Repeating))
+	}
+}
+
+/// Sends every client the state of the ships in its scope, see [`InterestScopes`].
+#[cfg(feature = "server")]
+#[allow(clippy::too_many_arguments)]
+pub fn send_snapshot(
+	time: Res<Time>,
+	tick: Res<RepliconTick>,
+	mut timer: ResMut<SnapshotTimer>,
+	server: Res<ServerSn>,
+	scopes: Res<InterestScopes>,
+	input_buffer: Res<InputBuffer>,
+	player_query: Query<(&Player, &Position, &Rotation, &LinearVelocity, &AngularVelocity, &Health)>,
+	ammo_query: Query<(&Player, &Ammo)>,
+	mut snapshots: EventWriter<ToClients<GameState>>,
+) {
+	if !timer.0.tick(time.delta()).just_finished() {
+		return;
+	}
+
+	let players: Vec<PlayerState> = player_query
+		.iter()
+		.map(|(player, position, rotation, lvel, avel, health)| PlayerState {
+			player: *player,
+			position: position.0,
+			rotation: rotation.as_radians(),
+			linear_velocity: lvel.0,
+			angular_velocity: avel.0,
+			health: *health,
+			engine_on: input_buffer.last(player.0).is_some_and(|inputs| inputs.engine_on()),
+		})
+		.collect();
+
+	for client_id in &server.client_connections {
+		let players = players
+			.iter()
+			.filter(|state| scopes.sees(*client_id, state.player.0))
+			.cloned()
+			.collect();
+		let ammo = ammo_query
+			.iter()
+			.find_map(|(player, ammo)| (player.0 == *client_id).then_some(*ammo));
+
+		snapshots.send(ToClients {
+			mode: SendMode::Direct(*client_id),
+			event: GameState {
+				tick: *tick,
+				players,
+				ammo,
+				players_alive: player_query.iter().count() as u32,
+			},
+		});
+	}
+}
+
+#[cfg(all(feature = "client", feature = "server"))]
+fn view_local_simulation(
+	tick: Res<RepliconTick>,
+	mut last_tick: ResMut<LastRepliconTick>,
+	mut players_alive: ResMut<PlayersAlive>,
+	player_query: Query<(), With<Player>>,
+) {
+	last_tick.0 = *tick;
+	players_alive.0 = player_query.iter().count() as u32;
+}
+
+/// Moves every ship to where the newest snapshot says it is. Ships are spawned and despawned
+/// as they enter and leave our scope, see [`ScopeUpdate`](crate::interest::ScopeUpdate).
+#[allow(clippy::too_many_arguments, clippy::type_complexity)]
+pub fn apply_snapshot(
+	time: Res<Time<Real>>,
+	mut snapshots: EventReader<GameState>,
+	mut last_tick: ResMut<LastRepliconTick>,
+	mut players_alive: ResMut<PlayersAlive>,
+	client: Option<ResMut<ClientSn>>,
+	client_id: Option<Res<ClientIdResource>>,
+	mut player_query: Query<(
+		&Player,
+		Option<(
+			&mut Position,
+			&mut Rotation,
+			&mut LinearVelocity,
+			&mut AngularVelocity,
+			&mut Health,
+			&Children,
+		)>,
+	)>,
+	mut ammo_query: Query<(&Player, &mut Ammo)>,
+	mut particle_query: Query<&mut ParticleSystem>,
+) {
+	let Some(snapshot) = snapshots.read().last() else {
+		return;
+	};
+
+	if snapshot.tick <= last_tick.0 {
+		return;
+	}
+	last_tick.0 = snapshot.tick;
+	players_alive.0 = snapshot.players_alive;
+	if let Some(mut client) = client {
+		client.stats.last_snapshot_at = Some(time.elapsed());
+	}
+
+	for (player, components) in &mut player_query {
+		let Some(state) = snapshot.players.iter().find(|state| state.player.0 == player.0) else {
+			continue;
+		};
+
+		// Ships spawned last frame haven't been given their physics components yet
+		let Some((mut position, mut rotation, mut lvel, mut avel, mut health, children)) = components else {
+			continue;
+		};
+
+		position.0 = state.position;
+		*rotation = Rotation::from_radians(state.rotation);
+		lvel.0 = state.linear_velocity;
+		avel.0 = state.angular_velocity;
+		*health = state.health;
+
+		if let Some(mut particle_system) = children.first().and_then(|child| particle_query.get_mut(*child).ok()) {
+			update_thruster(&mut particle_system, state.engine_on, &lvel, &rotation);
+		}
+	}
+
+	if let (Some(client_id), Some(new_ammo)) = (client_id, snapshot.ammo) {
+		for (player, mut ammo) in &mut ammo_query {
+			if player.0 == client_id.0 {
+				*ammo = new_ammo;
+			}
+		}
+	}
+
+}
@@ -0,0 +1,423 @@
+use bevy::{prelude::*, utils::HashMap};
+#[cfg(feature = "server")]
+use bevy::utils::HashSet;
+use serde::{Deserialize, Serialize};
+
+use crate::network::channels_config::ChannelConfig;
+use crate::network::events::server::ServerEventAppExt;
+use crate::network::helper::{server_running, ClientId};
+use crate::player::PlayerNames;
+
+#[cfg(feature = "server")]
+use crate::{
+	network::{events::server::{SendMode, ToClients}, helper::ServerSn, EventClientConnected},
+	player::{player_death_system, DamageEvent, Player, PlayerKilled},
+	weapon::ShotFired,
+	Cli,
+};
+#[cfg(feature = "server")]
+use bevy_xpbd_2d::prelude::*;
+
+pub struct StatsPlugin;
+
+impl Plugin for StatsPlugin {
+	fn build(&self, app: &mut App) {
+		app.init_resource::<MatchStats>()
+			.add_server_event_with::<MatchStats>(ChannelConfig::for_event::<MatchStats>().latest_only())
+			.add_server_event::<MatchSummary>()
+			.add_systems(Startup, spawn_scoreboard)
+			.add_systems(
+				Update,
+				(
+					apply_match_stats.run_if(not(server_running())),
+					toggle_scoreboard,
+					update_scoreboard.run_if(resource_changed::<MatchStats>()),
+				)
+					.chain(),
+			);
+
+		#[cfg(feature = "server")]
+		app.init_resource::<MatchState>()
+			.add_event::<RestartMatch>()
+			.add_systems(
+				Update,
+				(
+					restart_match,
+					track_connections,
+					track_damage,
+					track_shots,
+					track_kills,
+					finish_match,
+					broadcast_match_stats,
+				)
+					.chain()
+					// Victims are still around until the end of the frame, see `track_kills`
+					.after(player_death_system)
+					.run_if(resource_exists::<ServerSn>()),
+			)
+			.add_systems(
+				FixedUpdate,
+				track_movement.run_if(resource_exists::<ServerSn>()),
+			);
+	}
+}
+
+/// How often the server pushes the scoreboard to clients.
+#[cfg(feature = "server")]
+const SCOREBOARD_BROADCAST_SECONDS: f32 = 1.0;
+
+#[derive(Serialize, Deserialize, Default, Debug, Clone)]
+pub struct PlayerStats {
+	pub kills: u32,
+	pub deaths: u32,
+	pub damage_dealt: f32,
+	pub damage_taken: f32,
+	pub shots_fired: u32,
+	pub shots_hit: u32,
+	pub distance_travelled: f64,
+	pub time_alive: f32,
+	/// Finishing position, `None` while the player is still alive.
+	pub placement: Option<u32>,
+}
+
+/// Per-player statistics for the current match, keyed by client id.
+#[derive(Resource, Event, TypePath, Serialize, Deserialize, Default, Debug, Clone)]
+pub struct MatchStats(pub HashMap<ClientId, PlayerStats>);
+
+/// Sent to every client once a match ends. Also written to disk by the server.
+#[derive(Event, TypePath, Serialize, Deserialize, Debug, Clone)]
+pub struct MatchSummary {
+	/// Random id picked when the match started, tells apart matches that ended in the same second.
+	pub match_id: u64,
+	/// Seconds since the unix epoch when the match ended.
+	pub ended_at: u64,
+	pub winner: Option<ClientId>,
+	pub players: Vec<PlayerSummary>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct PlayerSummary {
+	pub client_id: ClientId,
+	pub name: String,
+	pub stats: PlayerStats,
+}
+
+/// Sent on the server to start a new match with everyone who is still around.
+#[cfg(feature = "server")]
+#[derive(Event, Debug, Clone, Copy)]
+pub struct RestartMatch;
+
+#[cfg(feature = "server")]
+#[derive(Resource, Debug)]
+pub struct MatchState {
+	match_id: u64,
+	finished: bool,
+	broadcast_timer: Timer,
+}
+
+#[cfg(feature = "server")]
+impl MatchState {
+	/// `true` once a winner was declared, until the match is restarted.
+	pub fn is_finished(&self) -> bool {
+		self.finished
+	}
+}
+
+#[cfg(feature = "server")]
+impl Default for MatchState {
+	fn default() -> Self {
+		Self {
+			match_id: rand::random(),
+			finished: false,
+			broadcast_timer: Timer::from_seconds(SCOREBOARD_BROADCAST_SECONDS, TimerMode::Repeating),
+		}
+	}
+}
+
+#[cfg(feature = "server")]
+fn restart_match(
+	mut restart_events: EventReader<RestartMatch>,
+	mut match_state: ResMut<MatchState>,
+	mut match_stats: ResMut<MatchStats>,
+) {
+	if restart_events.read().last().is_none() {
+		return;
+	}
+
+	*match_state = MatchState::default();
+	for stats in match_stats.0.values_mut() {
+		*stats = PlayerStats::default();
+	}
+}
+
+#[cfg(feature = "server")]
+fn track_connections(
+	mut connection_events: EventReader<EventClientConnected>,
+	mut match_stats: ResMut<MatchStats>,
+) {
+	for EventClientConnected(client_id, _) in connection_events.read() {
+		match_stats.0.entry(*client_id).or_default();
+	}
+}
+
+#[cfg(feature = "server")]
+fn track_damage(
+	mut damage_events: EventReader<DamageEvent>,
+	mut match_stats: ResMut<MatchStats>,
+	player_query: Query<&Player>,
+) {
+	for damage in damage_events.read() {
+		let Ok(victim) = player_query.get(damage.victim) else {
+			continue;
+		};
+
+		match_stats.0.entry(victim.0).or_default().damage_taken += damage.amount;
+
+		if let Some(attacker) = damage.attacker {
+			match_stats.0.entry(attacker).or_default().damage_dealt += damage.amount;
+		}
+	}
+}
+
+#[cfg(feature = "server")]
+fn track_kills(
+	mut kill_events: EventReader<ToClients<PlayerKilled>>,
+	mut match_stats: ResMut<MatchStats>,
+	player_query: Query<&Player>,
+) {
+	// Victims are despawned at the end of the frame, so everyone who died this frame is still
+	// here. Take them out in the order they died so they don't share a placement.
+	let mut alive: HashSet<ClientId> = player_query.iter().map(|player| player.0).collect();
+
+	// Non-observers get a copy without the position, only count the original
+	for ToClients { event: kill, .. } in kill_events.read().filter(|kill| kill.event.position.is_some()) {
+		alive.remove(&kill.victim);
+
+		let victim_stats = match_stats.0.entry(kill.victim).or_default();
+		victim_stats.deaths += 1;
+		victim_stats.placement = Some(alive.len() as u32 + 1);
+
+		if let Some(killer) = kill.killer {
+			match_stats.0.entry(killer).or_default().kills += 1;
+		}
+	}
+}
+
+#[cfg(feature = "server")]
+fn track_shots(mut shot_events: EventReader<ToClients<ShotFired>>, mut match_stats: ResMut<MatchStats>) {
+	for ToClients { event: shot, .. } in shot_events.read() {
+		let shooter_stats = match_stats.0.entry(shot.shooter).or_default();
+		shooter_stats.shots_fired += 1;
+
+		if shot.hit.is_some() {
+			shooter_stats.shots_hit += 1;
+		}
+	}
+}
+
+#[cfg(feature = "server")]
+fn track_movement(
+	time_step: Res<Time<Fixed>>,
+	mut match_stats: ResMut<MatchStats>,
+	player_query: Query<(&Player, &LinearVelocity)>,
+) {
+	let delta = time_step.timestep();
+
+	for (player, linear_velocity) in &player_query {
+		let stats = match_stats.0.entry(player.0).or_default();
+		stats.distance_travelled += linear_velocity.length() * delta.as_secs_f64();
+		stats.time_alive += delta.as_secs_f32();
+	}
+}
+
+/// Ends the match once a single ship is left out of at least two players.
+#[cfg(feature = "server")]
+fn finish_match(
+	cli: Res<Cli>,
+	player_names: Res<PlayerNames>,
+	player_query: Query<&Player>,
+	mut match_state: ResMut<MatchState>,
+	mut match_stats: ResMut<MatchStats>,
+	mut summary_events: EventWriter<ToClients<MatchSummary>>,
+) {
+	if match_state.finished || match_stats.0.len() < 2 || player_query.iter().count() > 1 {
+		return;
+	}
+
+	match_state.finished = true;
+
+	let winner = player_query.get_single().ok().map(|player| player.0);
+	if let Some(winner) = winner {
+		match_stats.0.entry(winner).or_default().placement = Some(1);
+	}
+
+	let mut players: Vec<PlayerSummary> = match_stats
+		.0
+		.iter()
+		.map(|(client_id, stats)| PlayerSummary {
+			client_id: *client_id,
+			name: player_names.display_name(*client_id),
+			stats: stats.clone(),
+		})
+		.collect();
+	players.sort_by_key(|player| player.stats.placement.unwrap_or(u32::MAX));
+
+	let summary = MatchSummary {
+		match_id: match_state.match_id,
+		ended_at: std::time::SystemTime::now()
+			.duration_since(std::time::UNIX_EPOCH)
+			.map(|duration| duration.as_secs())
+			.unwrap_or_default(),
+		winner,
+		players,
+	};
+
+	if let Err(error) = write_match_summary(&cli.stats_dir, &summary) {
+		error!("failed to write match summary: {error:#}");
+	}
+
+	summary_events.send(ToClients {
+		mode: SendMode::Broadcast,
+		event: summary,
+	});
+}
+
+#[cfg(feature = "server")]
+fn write_match_summary(stats_dir: &std::path::Path, summary: &MatchSummary) -> anyhow::Result<()> {
+	use anyhow::Context;
+
+	std::fs::create_dir_all(stats_dir)
+		.with_context(|| format!("creating {}", stats_dir.display()))?;
+
+	let path = stats_dir.join(format!("match_{}_{:016x}.json", summary.ended_at, summary.match_id));
+	let file = std::fs::File::create(&path).with_context(|| format!("creating {}", path.display()))?;
+	serde_json::to_writer_pretty(file, summary)?;
+
+	info!("wrote match summary to {}", path.display());
+	Ok(())
+}
+
+#[cfg(feature = "server")]
+fn broadcast_match_stats(
+	time: Res<Time>,
+	match_stats: Res<MatchStats>,
+	mut match_state: ResMut<MatchState>,
+	mut stats_events: EventWriter<ToClients<MatchStats>>,
+) {
+	if match_state.broadcast_timer.tick(time.delta()).just_finished() {
+		stats_events.send(ToClients {
+			mode: SendMode::Broadcast,
+			event: match_stats.clone(),
+		});
+	}
+}
+
+fn apply_match_stats(
+	mut match_stats: ResMut<MatchStats>,
+	mut stats_events: EventReader<MatchStats>,
+) {
+	if let Some(stats) = stats_events.read().last() {
+		*match_stats = stats.clone();
+	}
+}
+
+#[derive(Component)]
+struct Scoreboard;
+
+#[derive(Component)]
+struct ScoreboardText;
+
+fn spawn_scoreboard(mut commands: Commands) {
+	commands
+		.spawn((
+			NodeBundle {
+				style: Style {
+					position_type: PositionType::Absolute,
+					width: Val::Percent(100.),
+					height: Val::Percent(100.),
+					justify_content: JustifyContent::Center,
+					align_items: AlignItems::Center,
+					..default()
+				},
+				visibility: Visibility::Hidden,
+				..default()
+			},
+			Scoreboard,
+		))
+		.with_children(|parent| {
+			parent
+				.spawn(NodeBundle {
+					style: Style {
+						padding: UiRect::all(Val::Px(16.)),
+						..default()
+					},
+					background_color: Color::rgba(0., 0., 0., 0.8).into(),
+					..default()
+				})
+				.with_children(|parent| {
+					parent.spawn((
+						TextBundle::from_section(
+							"",
+							TextStyle {
+								font_size: 18.0,
+								color: Color::WHITE,
+								..default()
+							},
+						),
+						ScoreboardText,
+					));
+				});
+		});
+}
+
+/// Shows the scoreboard while Tab is held.
+fn toggle_scoreboard(
+	keys: Res<Input<KeyCode>>,
+	mut scoreboard_query: Query<&mut Visibility, With<Scoreboard>>,
+) {
+	for mut visibility in &mut scoreboard_query {
+		*visibility = if keys.pressed(KeyCode::Tab) {
+			Visibility::Visible
+		} else {
+			Visibility::Hidden
+		};
+	}
+}
+
+fn update_scoreboard(
+	match_stats: Res<MatchStats>,
+	player_names: Res<PlayerNames>,
+	mut text_query: Query<&mut Text, With<ScoreboardText>>,
+) {
+	let mut rows: Vec<(&ClientId, &PlayerStats)> = match_stats.0.iter().collect();
+	rows.sort_by(|(_, a), (_, b)| {
+		b.kills
+			.cmp(&a.kills)
+			.then(a.deaths.cmp(&b.deaths))
+			.then(b.damage_dealt.total_cmp(&a.damage_dealt))
+	});
+
+	let mut table = format!(
+		"{:<16} {:>5} {:>6} {:>7} {:>7} {:>9} {:>8} {:>6}\n",
+		"Name", "Kills", "Deaths", "Dealt", "Taken", "Hit/Shot", "Alive", "Place"
+	);
+
+	for (client_id, stats) in rows {
+		table += &format!(
+			"{:<16} {:>5} {:>6} {:>7.0} {:>7.0} {:>4}/{:<4} {:>7.0}s {:>6}\n",
+			player_names.display_name(*client_id),
+			stats.kills,
+			stats.deaths,
+			stats.damage_dealt,
+			stats.damage_taken,
+			stats.shots_hit,
+			stats.shots_fired,
+			stats.time_alive,
+			stats.placement.map(|place| format!("#{place}")).unwrap_or_default(),
+		);
+	}
+
+	for mut text in &mut text_query {
+		text.sections[0].value = table.clone();
+	}
+}
@@ -0,0 +1,198 @@
+use bevy::{math::DVec2, prelude::*};
+#[cfg(feature = "server")]
+use bevy_xpbd_2d::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::network::events::server::ServerEventAppExt;
+use crate::network::helper::ClientId;
+use crate::network::tick::RepliconTick;
+#[cfg(feature = "server")]
+use crate::player::SHIP_RADIUS;
+
+#[cfg(feature = "server")]
+use crate::{
+	interest::InterestScopes,
+	lag_compensation::PoseHistory,
+	network::{
+		events::server::{SendMode, ToClients},
+		helper::ServerSn,
+		violations::{ClientViolation, Violation},
+	},
+	player::{apply_player_movement, Ammo, DamageEvent, InputBuffer, Player},
+};
+
+/// How far a shot reaches.
+pub const SHOT_RANGE: f64 = 4000.0;
+#[cfg(feature = "server")]
+const SHOT_DAMAGE: f32 = 10.0;
+const FIRE_COOLDOWN_SECONDS: f32 = 0.25;
+/// How long a tracer stays on screen.
+const TRACER_SECONDS: f32 = 0.1;
+
+pub struct WeaponPlugin;
+
+impl Plugin for WeaponPlugin {
+	fn build(&self, app: &mut App) {
+		app.add_server_event::<ShotFired>()
+			.init_resource::<Tracers>()
+			.add_systems(Update, (spawn_tracers, draw_tracers).chain());
+
+		#[cfg(feature = "server")]
+		app.add_systems(
+			FixedUpdate,
+			fire_weapons
+				.after(apply_player_movement)
+				.run_if(resource_exists::<ServerSn>()),
+		);
+	}
+}
+
+/// Broadcast by the server for every shot, so clients can draw it and stats can count it.
+#[derive(Event, TypePath, Serialize, Deserialize, Debug, Clone, Copy)]
+pub struct ShotFired {
+	pub shooter: ClientId,
+	pub origin: DVec2,
+	pub end: DVec2,
+	pub hit: Option<ClientId>,
+}
+
+/// Ticks between two shots, `time_step` being the fixed timestep both sides simulate with.
+pub fn fire_cooldown_ticks(time_step: &Time<Fixed>) -> u32 {
+	(FIRE_COOLDOWN_SECONDS as f64 / time_step.timestep().as_secs_f64()).ceil() as u32
+}
+
+/// Whether a ship that last fired on `last_shot` may fire again on `tick`. Both ticks are input
+/// ticks, so the client and the server come to the same answer.
+pub fn cooled_down(last_shot: Option<RepliconTick>, tick: RepliconTick, cooldown_ticks: u32) -> bool {
+	match last_shot {
+		Some(last_shot) => tick.get().wrapping_sub(last_shot.get()) >= cooldown_ticks,
+		None => true,
+	}
+}
+
+/// Input tick of the ship's last shot, part of every ship spawned on the server.
+#[cfg(feature = "server")]
+#[derive(Component, Default, Debug)]
+pub struct FireCooldown(Option<RepliconTick>);
+
+/// Fires a hitscan shot for every ship holding the trigger, testing it against every other ship
+/// rewound to the tick the shooter was looking at.
+#[cfg(feature = "server")]
+#[allow(clippy::too_many_arguments, clippy::type_complexity)]
+fn fire_weapons(
+	time_step: Res<Time<Fixed>>,
+	tick: Res<RepliconTick>,
+	input_buffer: Res<InputBuffer>,
+	mut shooter_query: Query<(Entity, &Player, &Position, &Rotation, &mut Ammo, &mut FireCooldown)>,
+	target_query: Query<(Entity, &Player, &PoseHistory)>,
+	scopes: Res<InterestScopes>,
+	mut damage_events: EventWriter<DamageEvent>,
+	mut shot_events: EventWriter<ToClients<ShotFired>>,
+	mut violation_events: EventWriter<ClientViolation>,
+) {
+	let cooldown_ticks = fire_cooldown_ticks(&time_step);
+
+	for (entity, shooter, position, rotation, mut ammo, mut cooldown) in &mut shooter_query {
+		// An input repeated because the next one went missing never fires again
+		let Some(inputs) = input_buffer.last(shooter.0).filter(|inputs| inputs.tick == *tick) else {
+			continue;
+		};
+
+		if !inputs.firing() {
+			continue;
+		}
+
+		// Honest clients only pull the trigger once their own copy of the cooldown ran out
+		if !cooled_down(cooldown.0, inputs.tick, cooldown_ticks) {
+			violation_events.send(ClientViolation {
+				client_id: shooter.0,
+				violation: Violation::FiredDuringCooldown,
+			});
+			continue;
+		}
+
+		if ammo.bullets == 0 {
+			continue;
+		}
+
+		cooldown.0 = Some(inputs.tick);
+		ammo.bullets -= 1;
+
+		// Never trust a view tick from the future, or older than the rewind window allows
+		let view_tick = if inputs.view_tick > *tick { *tick } else { inputs.view_tick };
+
+		let direction = rotation.rotate(DVec2::Y);
+		let origin = position.0 + direction * SHIP_RADIUS as f64;
+
+		let hit = target_query
+			.iter()
+			.filter(|(target, _, _)| *target != entity)
+			.filter_map(|(target, player, history)| {
+				let pose = history.at(view_tick)?;
+				let (time_of_impact, _) =
+					pose.collider
+						.cast_ray(pose.position.0, pose.rotation, origin, direction, SHOT_RANGE, true)?;
+
+				Some((target, player.0, time_of_impact))
+			})
+			.min_by(|a, b| a.2.total_cmp(&b.2));
+
+		let distance = hit.map_or(SHOT_RANGE, |(_, _, time_of_impact)| time_of_impact);
+		if let Some((target, _, _)) = hit {
+			damage_events.send(DamageEvent {
+				victim: target,
+				attacker: Some(shooter.0),
+				amount: SHOT_DAMAGE,
+			});
+		}
+
+		// Only players who can see the shooter learn where it is
+		shot_events.send(ToClients {
+			mode: SendMode::ToMany(scopes.observers_of(shooter.0)),
+			event: ShotFired {
+				shooter: shooter.0,
+				origin,
+				end: origin + direction * distance,
+				hit: hit.map(|(_, client_id, _)| client_id),
+			},
+		});
+	}
+}
+
+/// Shots still being drawn, with the time they have left.
+#[derive(Resource, Default)]
+struct Tracers(Vec<(ShotFired, f32)>);
+
+fn spawn_tracers(mut shot_events: EventReader<ShotFired>, mut tracers: ResMut<Tracers>) {
+	for shot in shot_events.read() {
+		tracers.0.push((*shot, TRACER_SECONDS));
+	}
+}
+
+fn draw_tracers(time: Res<Time>, mut tracers: ResMut<Tracers>, mut gizmos: Gizmos) {
+	tracers.0.retain_mut(|(shot, remaining)| {
+		*remaining -= time.delta_seconds();
+		gizmos.line_2d(shot.origin.as_vec2(), shot.end.as_vec2(), Color::YELLOW);
+
+		*remaining > 0.0
+	});
+}
+
+#[cfg(all(test, feature = "server"))]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn cooldown_counts_input_ticks() {
+		let last_shot = RepliconTick(100);
+		assert!(cooled_down(None, RepliconTick(0), 15));
+		assert!(!cooled_down(Some(last_shot), RepliconTick(114), 15));
+		assert!(cooled_down(Some(last_shot), RepliconTick(115), 15));
+	}
+
+	#[test]
+	fn cooldown_survives_tick_wrapping() {
+		assert!(!cooled_down(Some(RepliconTick(u32::MAX - 2)), RepliconTick(5), 15));
+		assert!(cooled_down(Some(RepliconTick(u32::MAX - 2)), RepliconTick(12), 15));
+	}
+}
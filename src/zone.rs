@@ -0,0 +1,193 @@
+use bevy::{math::DVec2, prelude::*};
+#[cfg(feature = "server")]
+use bevy_xpbd_2d::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::map::WORLD_SIZE;
+use crate::network::channels_config::ChannelConfig;
+use crate::network::events::server::ServerEventAppExt;
+use crate::network::helper::server_running;
+#[cfg(feature = "server")]
+use crate::{
+	network::{events::server::{SendMode, ToClients}, helper::ServerSn, EventClientConnected},
+	player::{DamageEvent, Player},
+	stats::RestartMatch,
+};
+
+pub struct ZonePlugin;
+
+impl Plugin for ZonePlugin {
+	fn build(&self, app: &mut App) {
+		app.init_resource::<SafeZone>()
+			.add_server_event_with::<ZoneUpdate>(ChannelConfig::for_event::<ZoneUpdate>().latest_only())
+			.add_systems(Update, apply_zone_update.run_if(not(server_running())));
+
+		#[cfg(feature = "server")]
+		app.add_systems(
+			FixedUpdate,
+			(shrink_zone, apply_zone_damage).run_if(resource_exists::<ServerSn>()),
+		)
+		.add_systems(Update, (restart_zone, send_zone).run_if(resource_exists::<ServerSn>()));
+	}
+}
+
+/// Seconds between the end of one shrink and the start of the next.
+const ZONE_WAIT_SECONDS: f32 = 60.0;
+/// Seconds it takes the zone to shrink to its next radius.
+#[cfg(feature = "server")]
+const ZONE_SHRINK_SECONDS: f32 = 30.0;
+/// Each phase shrinks the zone to this fraction of its previous radius.
+const ZONE_SHRINK_FACTOR: f64 = 0.6;
+/// Health lost per second while outside the safe zone.
+#[cfg(feature = "server")]
+const ZONE_DAMAGE_PER_SECOND: f32 = 5.0;
+
+/// The circle players have to stay inside of to avoid taking damage.
+///
+/// Owned by the server, mirrored on clients through [`ZoneUpdate`].
+#[derive(Resource, Serialize, Deserialize, Clone, Copy, Debug)]
+pub struct SafeZone {
+	pub center: DVec2,
+	pub radius: f64,
+	/// Radius the zone is shrinking towards during the current phase.
+	pub target_radius: f64,
+	pub phase: u32,
+	pub shrinking: bool,
+	/// Seconds until the zone starts (or finishes) shrinking.
+	pub countdown: f32,
+}
+
+impl Default for SafeZone {
+	fn default() -> Self {
+		// Circumscribes the whole generated map
+		let radius = WORLD_SIZE * std::f64::consts::FRAC_1_SQRT_2;
+
+		Self {
+			center: DVec2::ZERO,
+			radius,
+			target_radius: radius * ZONE_SHRINK_FACTOR,
+			phase: 0,
+			shrinking: false,
+			countdown: ZONE_WAIT_SECONDS,
+		}
+	}
+}
+
+impl SafeZone {
+	pub fn contains(&self, position: DVec2) -> bool {
+		self.center.distance_squared(position) <= self.radius.powi(2)
+	}
+
+	/// The zone as it is once `phase` shrinks are over, waiting for the next one.
+	pub fn at_phase(phase: u32) -> Self {
+		let mut zone = Self::default();
+		zone.radius *= ZONE_SHRINK_FACTOR.powi(phase as i32);
+		zone.target_radius = zone.radius * ZONE_SHRINK_FACTOR;
+		zone.phase = phase;
+		zone
+	}
+}
+
+#[derive(Event, TypePath, Serialize, Deserialize, Clone, Copy, Debug)]
+pub struct ZoneUpdate(pub SafeZone);
+
+#[cfg(feature = "server")]
+fn shrink_zone(
+	time_step: Res<Time<Fixed>>,
+	mut zone: ResMut<SafeZone>,
+	mut zone_updates: EventWriter<ToClients<ZoneUpdate>>,
+) {
+	let delta = time_step.timestep().as_secs_f32();
+	let previous_phase = (zone.phase, zone.shrinking);
+
+	zone.countdown -= delta;
+
+	if zone.shrinking {
+		let shrink_per_second = (zone.radius - zone.target_radius) / zone.countdown.max(delta) as f64;
+		zone.radius = (zone.radius - shrink_per_second * delta as f64).max(zone.target_radius);
+	}
+
+	if zone.countdown <= 0.0 {
+		if zone.shrinking {
+			zone.radius = zone.target_radius;
+			zone.target_radius *= ZONE_SHRINK_FACTOR;
+			zone.phase += 1;
+			zone.shrinking = false;
+			zone.countdown = ZONE_WAIT_SECONDS;
+		} else {
+			zone.shrinking = true;
+			zone.countdown = ZONE_SHRINK_SECONDS;
+		}
+	}
+
+	// Clients interpolate the countdown themselves, only send on phase changes
+	if previous_phase != (zone.phase, zone.shrinking) {
+		zone_updates.send(ToClients {
+			mode: SendMode::Broadcast,
+			event: ZoneUpdate(*zone),
+		});
+	}
+}
+
+#[cfg(feature = "server")]
+fn restart_zone(
+	mut restart_events: EventReader<RestartMatch>,
+	mut zone: ResMut<SafeZone>,
+	mut zone_updates: EventWriter<ToClients<ZoneUpdate>>,
+) {
+	if restart_events.read().last().is_none() {
+		return;
+	}
+
+	*zone = SafeZone::default();
+	zone_updates.send(ToClients {
+		mode: SendMode::Broadcast,
+		event: ZoneUpdate(*zone),
+	});
+}
+
+/// Tells new clients where the zone is, they only hear about phase changes otherwise.
+#[cfg(feature = "server")]
+fn send_zone(
+	zone: Res<SafeZone>,
+	mut connection_events: EventReader<EventClientConnected>,
+	mut zone_updates: EventWriter<ToClients<ZoneUpdate>>,
+) {
+	for EventClientConnected(client_id, _) in connection_events.read() {
+		zone_updates.send(ToClients {
+			mode: SendMode::Direct(*client_id),
+			event: ZoneUpdate(*zone),
+		});
+	}
+}
+
+#[cfg(feature = "server")]
+fn apply_zone_damage(
+	time_step: Res<Time<Fixed>>,
+	zone: Res<SafeZone>,
+	player_query: Query<(Entity, &Position), With<Player>>,
+	mut damage_events: EventWriter<DamageEvent>,
+) {
+	for (entity, position) in &player_query {
+		if !zone.contains(position.0) {
+			damage_events.send(DamageEvent {
+				victim: entity,
+				attacker: None,
+				amount: ZONE_DAMAGE_PER_SECOND * time_step.timestep().as_secs_f32(),
+			});
+		}
+	}
+}
+
+fn apply_zone_update(
+	time: Res<Time>,
+	mut zone: ResMut<SafeZone>,
+	mut zone_updates: EventReader<ZoneUpdate>,
+) {
+	if let Some(ZoneUpdate(update)) = zone_updates.read().last() {
+		*zone = *update;
+		return;
+	}
+
+	zone.countdown = (zone.countdown - time.delta_seconds()).max(0.0);
+}
@@ -0,0 +1,67 @@
+//! Pins the channel table. A change here means old clients can't join new servers, bump
+//! `PROTOCOL_VERSION` or register the renamed event with its old key.
+
+use bevy::prelude::*;
+use clap::Parser;
+
+use space_royale::admin::AdminPlugin;
+use space_royale::chat::ChatPlugin;
+use space_royale::interest::InterestPlugin;
+use space_royale::map::MapPlugin;
+use space_royale::network::channels_config::ChannelManager;
+use space_royale::network::diagnostics::NetworkDiagnosticsPlugin;
+use space_royale::network::NetworkPlugin;
+use space_royale::player::PlayerPlugin;
+use space_royale::snapshot::SnapshotPlugin;
+use space_royale::stats::StatsPlugin;
+use space_royale::weapon::WeaponPlugin;
+use space_royale::zone::ZonePlugin;
+use space_royale::Cli;
+
+/// What `--dump-channels` prints, the same for every feature set.
+const CHANNEL_TABLE: &str = "\
+server 0fbdbefd ReliableOrdered 65536 space_royale::player::PlayerKilled\n\
+server 1db6f5cc LatestOnly 65536 space_royale::player::PlayerNames\n\
+server 225f371c ReliableOrdered 65536 space_royale::weapon::ShotFired\n\
+server 22d96a17 ReliableOrdered 65536 space_royale::admin::ServerAnnouncement\n\
+server 4224f0e8 ReliableOrdered 65536 space_royale::stats::MatchSummary\n\
+server 42b95932 ReliableOrdered 65536 space_royale::chat::ChatRejected\n\
+server 492504c8 LatestOnly 65536 space_royale::network::diagnostics::Pong\n\
+server 4a133803 LatestOnly 65536 space_royale::stats::MatchStats\n\
+server 59a27be5 ReliableOrdered 65536 space_royale::admin::AdminResponse\n\
+server 6cb20fd9 ReliableOrdered 65536 space_royale::network::InternalConnectionEvent\n\
+server 8ddabe32 ReliableOrdered 65536 space_royale::network::connection::SessionAssigned\n\
+server 8f277ae9 LatestOnly 65536 space_royale::network::diagnostics::ServerPing\n\
+server a873f457 ReliableOrdered 65536 space_royale::chat::ChatMessage\n\
+server b424610f ReliableOrdered 65536 space_royale::interest::ScopeUpdate\n\
+server b8745a20 ReliableOrdered 65536 space_royale::network::connection::ConnectionRejected\n\
+server cc9f4983 LatestOnly 65536 space_royale::snapshot::GameState\n\
+server daec89c5 LatestOnly 65536 space_royale::map::MapSeed\n\
+server de980e57 LatestOnly 65536 space_royale::player::InputBufferHealth\n\
+server e2ec2c91 LatestOnly 65536 space_royale::zone::ZoneUpdate\n\
+client 386c70c1 ReliableOrdered 65536 space_royale::chat::ChatRequest\n\
+client 6448f7bc ReliableOrdered 65536 space_royale::player::Inputs\n\
+client 948aecdf ReliableOrdered 65536 space_royale::admin::AdminRequest\n\
+client b4a7930a LatestOnly 65536 space_royale::network::diagnostics::Ping\n\
+client f12bcb17 LatestOnly 65536 space_royale::network::diagnostics::ServerPong\n\
+";
+
+#[test]
+fn channel_table_is_pinned() {
+	let mut app = App::new();
+	app.insert_resource(Cli::parse_from(["space_royale"])).add_plugins((
+		NetworkPlugin,
+		NetworkDiagnosticsPlugin,
+		MapPlugin,
+		PlayerPlugin,
+		ZonePlugin,
+		StatsPlugin,
+		SnapshotPlugin,
+		InterestPlugin,
+		WeaponPlugin,
+		AdminPlugin,
+		ChatPlugin,
+	));
+
+	assert_eq!(app.world.resource::<ChannelManager>().channel_table(), CHANNEL_TABLE);
+}
@@ -0,0 +1,220 @@
+//! Runs a server and several clients in one process over the in-memory transport, stepping their
+//! apps one after another.
+#![cfg(all(feature = "client", feature = "server"))]
+
+use bevy::input::InputPlugin;
+use bevy::prelude::*;
+use clap::Parser;
+use serde::{Deserialize, Serialize};
+
+use space_royale::network::channels_config::ChannelManager;
+use space_royale::network::connection::{ClientConnectionState, ClientSession};
+use space_royale::network::events::client::{ClientEventAppExt, FromClient};
+use space_royale::network::events::server::{SendMode, ServerEventAppExt, ToClients};
+use space_royale::network::helper::{connect_local_client, ClientId, ClientSn, ServerSn};
+use space_royale::network::transport::{MemoryServer, ServerTransport};
+use space_royale::network::{EventClientConnected, EventClientDisconnected, NetworkPlugin};
+use space_royale::Cli;
+
+#[derive(Event, TypePath, Clone, Debug, PartialEq, Serialize, Deserialize)]
+struct Greeting(String);
+
+#[derive(Event, TypePath, Clone, Debug, PartialEq, Serialize, Deserialize)]
+struct Shout(u32);
+
+/// Every `T` an app has seen, events only live for two updates.
+#[derive(Resource)]
+struct Received<T>(Vec<T>);
+
+fn collect<T: Event + Clone>(mut events: EventReader<T>, mut received: ResMut<Received<T>>) {
+	received.0.extend(events.read().cloned());
+}
+
+fn app(cli: Cli) -> App {
+	let mut app = App::new();
+	app.insert_resource(cli)
+		.add_plugins((MinimalPlugins, InputPlugin, NetworkPlugin))
+		.add_server_event::<Greeting>()
+		.add_client_event::<Shout>();
+	app
+}
+
+fn server(hub: &MemoryServer) -> App {
+	let mut app = app(Cli::parse_from(["space_royale"]));
+	app.insert_resource(ServerSn::new(ServerTransport::Memory(hub.clone())))
+		.insert_resource(Received::<EventClientConnected>(Vec::new()))
+		.insert_resource(Received::<EventClientDisconnected>(Vec::new()))
+		.insert_resource(Received::<FromClient<Shout>>(Vec::new()))
+		.add_systems(
+			Last,
+			(
+				collect::<EventClientConnected>,
+				collect::<EventClientDisconnected>,
+				collect::<FromClient<Shout>>,
+			),
+		);
+	app
+}
+
+fn client(hub: &MemoryServer, client_id: ClientId) -> App {
+	let cli = Cli::parse_from(["space_royale", "--name", &format!("pilot {client_id}")]);
+	let mut app = app(cli.clone());
+	let client = connect_local_client(hub, client_id, &cli, app.world.resource::<ChannelManager>(), None)
+		.expect("client ids in a test should be unique");
+	app.insert_resource(client)
+		.insert_resource(Received::<Greeting>(Vec::new()))
+		.add_systems(Last, collect::<Greeting>);
+	app
+}
+
+fn step(server: &mut App, clients: &mut [App]) {
+	for _ in 0..3 {
+		server.update();
+		for client in clients.iter_mut() {
+			client.update();
+		}
+	}
+}
+
+#[test]
+fn server_and_clients_exchange_events_in_memory() {
+	let hub = MemoryServer::default();
+	let mut server = server(&hub);
+	let client_ids: [ClientId; 3] = [11, 12, 13];
+	let mut clients: Vec<App> = client_ids.iter().map(|client_id| client(&hub, *client_id)).collect();
+
+	step(&mut server, &mut clients);
+
+	// Connect
+	let mut connected: Vec<(ClientId, String)> = server
+		.world
+		.resource::<Received<EventClientConnected>>()
+		.0
+		.iter()
+		.map(|EventClientConnected(client_id, name)| (*client_id, name.clone()))
+		.collect();
+	connected.sort();
+	assert_eq!(
+		connected,
+		client_ids.map(|client_id| (client_id, format!("pilot {client_id}"))).to_vec()
+	);
+	for client in &clients {
+		assert_eq!(
+			client.world.resource::<State<ClientConnectionState>>().get(),
+			&ClientConnectionState::Connected
+		);
+	}
+
+	// A second connection can't take an id that is in use
+	let cli = Cli::parse_from(["space_royale"]);
+	assert!(connect_local_client(&hub, 12, &cli, server.world.resource::<ChannelManager>(), None).is_err());
+
+	// Replication both ways
+	server.world.send_event(ToClients {
+		mode: SendMode::Broadcast,
+		event: Greeting(String::from("welcome")),
+	});
+	server.world.send_event(ToClients {
+		mode: SendMode::Direct(13),
+		event: Greeting(String::from("just you")),
+	});
+	for (shout, client) in clients.iter_mut().enumerate() {
+		client.world.send_event(Shout(shout as u32));
+	}
+
+	step(&mut server, &mut clients);
+
+	for (client_id, client) in client_ids.iter().zip(&clients) {
+		let mut expected = vec![Greeting(String::from("welcome"))];
+		if *client_id == 13 {
+			expected.push(Greeting(String::from("just you")));
+		}
+		assert_eq!(client.world.resource::<Received<Greeting>>().0, expected);
+	}
+
+	let mut shouts: Vec<(ClientId, u32, bool)> = server
+		.world
+		.resource::<Received<FromClient<Shout>>>()
+		.0
+		.iter()
+		.map(|from_client| (from_client.client_id, from_client.event.0, from_client.local))
+		.collect();
+	shouts.sort();
+	assert_eq!(shouts, vec![(11, 0, false), (12, 1, false), (13, 2, false)]);
+
+	// Disconnect
+	clients[1].world.remove_resource::<ClientSn>();
+	step(&mut server, &mut clients);
+
+	let disconnected: Vec<ClientId> = server
+		.world
+		.resource::<Received<EventClientDisconnected>>()
+		.0
+		.iter()
+		.map(|EventClientDisconnected(client_id)| *client_id)
+		.collect();
+	assert_eq!(disconnected, vec![12]);
+
+	let mut still_connected: Vec<ClientId> =
+		server.world.resource::<ServerSn>().client_connections.iter().copied().collect();
+	still_connected.sort();
+	assert_eq!(still_connected, vec![11, 13]);
+
+	// The id is free again once its connection is gone
+	assert!(connect_local_client(&hub, 12, &cli, server.world.resource::<ChannelManager>(), None).is_ok());
+}
+
+#[test]
+fn claiming_a_live_id_leaves_its_owner_connected() {
+	let hub = MemoryServer::default();
+	let mut server = server(&hub);
+	let mut clients = vec![client(&hub, 21)];
+
+	step(&mut server, &mut clients);
+	assert_eq!(
+		clients[0].world.resource::<State<ClientConnectionState>>().get(),
+		&ClientConnectionState::Connected
+	);
+
+	// Someone else claims the id while its owner is playing
+	let cli = Cli::parse_from(["space_royale"]);
+	let channel_manager = server.world.resource::<ChannelManager>();
+	assert!(connect_local_client(&hub, 21, &cli, channel_manager, None).is_err());
+
+	clients[0].world.send_event(Shout(7));
+	step(&mut server, &mut clients);
+
+	assert_eq!(
+		clients[0].world.resource::<State<ClientConnectionState>>().get(),
+		&ClientConnectionState::Connected
+	);
+	assert!(server.world.resource::<ServerSn>().client_connections.contains(&21));
+	assert!(server.world.resource::<Received<EventClientDisconnected>>().0.is_empty());
+	let shouts: Vec<(ClientId, u32)> = server
+		.world
+		.resource::<Received<FromClient<Shout>>>()
+		.0
+		.iter()
+		.map(|from_client| (from_client.client_id, from_client.event.0))
+		.collect();
+	assert_eq!(shouts, vec![(21, 7)]);
+
+	// Once the owner dropped, the id is free on the transport but still held for them
+	let token = clients[0].world.resource::<ClientSession>().0;
+	clients[0].world.remove_resource::<ClientSn>();
+	step(&mut server, &mut clients);
+
+	let channel_manager = server.world.resource::<ChannelManager>();
+	let impostor = connect_local_client(&hub, 21, &cli, channel_manager, None).expect("the socket is gone");
+	step(&mut server, &mut clients);
+	assert!(!server.world.resource::<ServerSn>().client_connections.contains(&21));
+	drop(impostor);
+
+	// The owner comes back with its token
+	let channel_manager = server.world.resource::<ChannelManager>();
+	let owner =
+		connect_local_client(&hub, 21, &cli, channel_manager, Some(token)).expect("the impostor was kicked");
+	clients[0].insert_resource(owner);
+	step(&mut server, &mut clients);
+	assert!(server.world.resource::<ServerSn>().client_connections.contains(&21));
+}